@@ -1,9 +1,16 @@
 //! The hardware controls for the agent
 
-use std::convert::Infallible;
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
 
 use inputs::Input;
+use metadata::Metadata;
 use outputs::Output;
+use safety::{GuardState, SafetyLimits};
 use slotmap::{new_key_type, SlotMap};
 
 pub mod inputs;
@@ -32,13 +39,59 @@ pub enum PeripheralError {
     #[error("PWM Error")]
     /// A raspberry pi pwm error
     PwmError(#[from] rppal::pwm::Error),
+    #[cfg(feature = "linux")]
+    #[error("Serial Error")]
+    /// A linux termios error
+    SerialError(#[from] nix::Error),
+    #[error("IO Error: {0}")]
+    /// An error reading or writing a file or device
+    Io(#[from] std::io::Error),
+    #[error("Blocking task failed: {0}")]
+    /// A blocking read or write panicked or was cancelled
+    BlockingTask(#[from] tokio::task::JoinError),
+    #[error("Invalid payload: {0}")]
+    /// An instruction payload isn't valid for the output it targets
+    InvalidPayload(&'static str),
+    #[error("Safety limit violated: {0}")]
+    /// A write broke its node's safety limits
+    SafetyLimit(String),
+    #[error("No such node")]
+    /// A node was looked up that isn't in the body
+    MissingNode,
+    #[error("Not an input")]
+    /// A read was made from a node that isn't an input
+    NotAnInput,
+    #[error("Not an output")]
+    /// A write was sent to a node that isn't an output
+    NotAnOutput,
+    #[error("End of recording")]
+    /// A replayed input has no samples left
+    EndOfRecording,
     #[error("Infallible")]
     /// An error that will never be returned
     Infallible(#[from] Infallible),
 }
 
+/// The future an async peripheral's read or write returns. Boxed so `AsyncInput` and
+/// `AsyncOutput` can be used as trait objects, the same way `Input` and `Output` are
+pub type PeripheralFuture<'io, T> = Pin<Box<dyn Future<Output = T> + Send + 'io>>;
+
+pub mod actuators;
+pub mod blocking;
+pub mod calibration;
+pub mod description;
+pub mod graph;
+#[cfg(feature = "linux")]
+pub mod linux_peripherals;
+pub mod metadata;
 #[cfg(feature = "rpi")]
 pub mod pi_peripherals;
+pub mod record;
+pub mod safety;
+pub mod sampling;
+#[cfg(feature = "linux")]
+pub mod serial;
+pub mod virtual_peripherals;
 
 /// Abstraction over the hardware of the device. This is still TODO because I don't exactly know
 /// how we want to do this. My main idea is we can have an enum for Input/Output, and from there
@@ -56,7 +109,17 @@ pub struct Body {
 unsafe impl Send for Body {}
 unsafe impl Sync for Body {}
 
+/// A body shared between the agent loop and anything that has to reach the hardware while the
+/// loop is busy, such as a `Watchdog`. Holders lock it per read or write rather than for a whole
+/// plan, so a stalled plan never keeps the others out
+pub type SharedBody = Arc<Mutex<Body>>;
+
 impl Body {
+    /// Creates a new builder for a body
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
     /// Returns all input nodes
     pub fn inputs(&self) -> Vec<&PeripheralNode> {
         self.peripheral_graph
@@ -113,6 +176,12 @@ pub struct PeripheralNode {
     pub peripheral: Peripheral,
     /// All peripherals this peripheral connects to
     pub points_to: Option<Vec<PeripheralKey>>,
+    /// What the peripheral is and how its readings map onto AHTP data
+    pub metadata: Metadata,
+    /// The limits every write to this peripheral is checked against
+    pub limits: Option<SafetyLimits>,
+    /// What the limits remember between writes
+    guard: GuardState,
 }
 
 impl From<Peripheral> for PeripheralNode {
@@ -120,6 +189,9 @@ impl From<Peripheral> for PeripheralNode {
         PeripheralNode {
             peripheral: value,
             points_to: None,
+            metadata: Metadata::default(),
+            limits: None,
+            guard: GuardState::default(),
         }
     }
 }
@@ -130,6 +202,7 @@ new_key_type! {
 }
 
 /// Builder for a Body
+#[derive(Default)]
 pub struct Builder {
     /// The root peripheral and all peripherals with no parents
     root: Vec<PeripheralKey>,
@@ -139,20 +212,37 @@ pub struct Builder {
 
 impl Builder {
     /// Adds a node to the root
-    pub fn with_node(mut self, node: Peripheral) -> Self {
+    pub fn with_node(mut self, node: impl Into<PeripheralNode>) -> Self {
         let id = self.graph.insert(node.into());
         self.root.push(id);
         self
     }
 
-    /// Adds a node that's connected to another
-    pub fn add_node_to(&mut self, node: Peripheral, connected_to: PeripheralKey) {
+    /// Adds a node to the root with safety limits on its writes
+    pub fn with_limited_node(self, node: Peripheral, limits: SafetyLimits) -> Self {
+        self.with_node(PeripheralNode::from(node).with_limits(limits))
+    }
+
+    /// Adds a node to the root, returning its key so other nodes can be connected to it
+    pub fn add_node(&mut self, node: impl Into<PeripheralNode>) -> PeripheralKey {
+        let id = self.graph.insert(node.into());
+        self.root.push(id);
+        id
+    }
+
+    /// Adds a node that's connected to another, returning its key. If `connected_to` isn't in the
+    /// body the node is added to the root instead, so it's never left unreachable
+    pub fn add_node_to(
+        &mut self,
+        node: impl Into<PeripheralNode>,
+        connected_to: PeripheralKey,
+    ) -> PeripheralKey {
         let id = self.graph.insert(node.into());
-        if let Some(nodes) = &mut self.graph[connected_to].points_to {
-            nodes.push(id)
-        } else {
-            self.graph[connected_to].points_to = Some(vec![id]);
+        match self.graph.get_mut(connected_to) {
+            Some(parent) => parent.points_to.get_or_insert_with(Vec::new).push(id),
+            None => self.root.push(id),
         }
+        id
     }
 
     /// Constructs a body from a nodeset
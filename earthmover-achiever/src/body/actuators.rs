@@ -0,0 +1,65 @@
+//! Semantic actuators built on top of raw `Output`s. Every actuator has a documented encoding of
+//! its command to and from an instruction payload, so the hivemind and the agent agree on what an
+//! instruction's bytes mean. All multi-byte values in a payload are big endian
+//!
+//! | Actuator  | Command       | Payload                                                      |
+//! |-----------|---------------|--------------------------------------------------------------|
+//! | `Servo`   | `Angle`       | `f32` angle in degrees                                       |
+//! | `DcMotor` | `Speed`       | `f32` speed in `-1.0..=1.0`, negative runs in reverse        |
+//! | `Stepper` | `StepCommand` | `i32` steps, positive is clockwise, negative counter-clockwise |
+
+pub mod motor;
+pub mod servo;
+pub mod stepper;
+
+pub use motor::{DcMotor, Speed};
+pub use servo::{Angle, Servo};
+pub use stepper::{StepCommand, Stepper};
+
+use super::{outputs::Output, PeripheralError};
+
+/// A command with a fixed encoding as instruction bytes
+pub trait Payload: Sized {
+    /// Encodes the command as an instruction payload, failing if it isn't a valid command
+    fn encode(&self) -> Result<[u8; 4], PeripheralError>;
+    /// Decodes an instruction payload into a command, failing if it isn't a valid command
+    fn decode(payload: &[u8]) -> Result<Self, PeripheralError>;
+}
+
+/// An output driven by a typed command. Writing raw bytes to an actuator decodes them as its
+/// command's payload
+pub trait Actuator: Output<Error = PeripheralError> {
+    /// The command this actuator accepts
+    type Command: Payload;
+    /// Drives the actuator
+    fn command(&mut self, command: Self::Command) -> Result<(), PeripheralError>;
+}
+
+/// Reads the first four bytes of a payload, failing if there aren't enough
+fn payload_word(payload: &[u8]) -> Result<[u8; 4], PeripheralError> {
+    payload
+        .get(0..4)
+        .and_then(|word| word.try_into().ok())
+        .ok_or(PeripheralError::InvalidPayload(
+            "payload is shorter than 4 bytes",
+        ))
+}
+
+/// Encodes a PWM frequency in hertz and duty cycle in `0.0..=1.0` the way a PWM `Output` expects
+/// them: two native endian f64s
+pub fn pwm_bytes(frequency: f64, duty_cycle: f64) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    bytes[0..8].copy_from_slice(&frequency.to_ne_bytes());
+    bytes[8..16].copy_from_slice(&duty_cycle.to_ne_bytes());
+    bytes
+}
+
+/// Encodes only a PWM duty cycle the way a PWM `Output` expects it: one native endian f64
+pub fn duty_cycle_bytes(duty_cycle: f64) -> [u8; 8] {
+    duty_cycle.to_ne_bytes()
+}
+
+/// The byte written to a digital output to drive it high
+pub const HIGH: u8 = u8::MAX;
+/// The byte written to a digital output to drive it low
+pub const LOW: u8 = 0;
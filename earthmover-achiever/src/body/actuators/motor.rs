@@ -0,0 +1,112 @@
+//! A brushed DC motor driven through an H-bridge
+
+use crate::body::{outputs::Output, PeripheralError};
+
+use super::{duty_cycle_bytes, payload_word, Actuator, Payload, HIGH, LOW};
+
+/// A motor speed in `-1.0..=1.0`, negative runs in reverse
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speed(pub f32);
+
+impl Payload for Speed {
+    fn encode(&self) -> Result<[u8; 4], PeripheralError> {
+        let payload = self.0.to_be_bytes();
+        Self::decode(&payload).map(|_| payload)
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self, PeripheralError> {
+        let speed = f32::from_be_bytes(payload_word(payload)?);
+        if (-1.0..=1.0).contains(&speed) {
+            Ok(Self(speed))
+        } else {
+            Err(PeripheralError::InvalidPayload(
+                "motor speed is outside -1.0..=1.0",
+            ))
+        }
+    }
+}
+
+/// A DC motor on an H-bridge such as the L298N: two direction pins select forward or reverse and a
+/// PWM enable pin sets the speed. A speed of 0 lets the motor coast
+pub struct DcMotor<PWM: Output<Error = PeripheralError>, PIN: Output<Error = PeripheralError>> {
+    /// The PWM output on the bridge's enable pin
+    enable: PWM,
+    /// The bridge's first direction input
+    in1: PIN,
+    /// The bridge's second direction input
+    in2: PIN,
+}
+
+impl<PWM: Output<Error = PeripheralError>, PIN: Output<Error = PeripheralError>> DcMotor<PWM, PIN> {
+    /// Creates a new motor from its bridge's enable and direction pins
+    pub fn new(enable: PWM, in1: PIN, in2: PIN) -> Self {
+        Self { enable, in1, in2 }
+    }
+}
+
+impl<PWM: Output<Error = PeripheralError>, PIN: Output<Error = PeripheralError>> Actuator
+    for DcMotor<PWM, PIN>
+{
+    type Command = Speed;
+
+    fn command(&mut self, Speed(speed): Speed) -> Result<(), PeripheralError> {
+        let (in1, in2) = match speed {
+            s if s > 0.0 => (HIGH, LOW),
+            s if s < 0.0 => (LOW, HIGH),
+            _ => (LOW, LOW),
+        };
+
+        self.in1.write(&[in1])?;
+        self.in2.write(&[in2])?;
+        self.enable
+            .write(&duty_cycle_bytes(speed.abs().clamp(0.0, 1.0) as f64))
+    }
+}
+
+impl<PWM: Output<Error = PeripheralError>, PIN: Output<Error = PeripheralError>> Output
+    for DcMotor<PWM, PIN>
+{
+    type Error = PeripheralError;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.command(Speed::decode(bytes)?)
+    }
+
+    fn validate(&self, bytes: &[u8]) -> Result<(), Self::Error> {
+        Speed::decode(bytes).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{
+        actuators::{duty_cycle_bytes, Payload, HIGH, LOW},
+        outputs::{virtual_outputs::RecordingOutput, Output},
+    };
+
+    use super::{DcMotor, Speed};
+
+    #[test]
+    fn negative_speed_reverses_motor() {
+        let (enable, in1, in2) = (
+            RecordingOutput::new(),
+            RecordingOutput::new(),
+            RecordingOutput::new(),
+        );
+        let logs = (enable.log(), in1.log(), in2.log());
+        let mut motor = DcMotor::new(enable, in1, in2);
+
+        motor
+            .write(&Speed(-0.5).encode().unwrap())
+            .expect("Write motor speed");
+
+        assert_eq!(logs.0.last(), Some(duty_cycle_bytes(0.5).to_vec()));
+        assert_eq!(logs.1.last(), Some(vec![LOW]));
+        assert_eq!(logs.2.last(), Some(vec![HIGH]));
+    }
+
+    #[test]
+    fn motor_rejects_out_of_range_speed() {
+        let payload = 1.5f32.to_be_bytes();
+        assert!(Speed::decode(&payload).is_err());
+    }
+}
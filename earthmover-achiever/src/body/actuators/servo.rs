@@ -0,0 +1,137 @@
+//! A hobby servo driven by a PWM output
+
+use std::time::Duration;
+
+use crate::body::{outputs::Output, PeripheralError};
+
+use super::{payload_word, pwm_bytes, Actuator, Payload};
+
+/// A servo angle in degrees
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Angle(pub f32);
+
+impl Payload for Angle {
+    fn encode(&self) -> Result<[u8; 4], PeripheralError> {
+        let payload = self.0.to_be_bytes();
+        Self::decode(&payload).map(|_| payload)
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self, PeripheralError> {
+        let angle = f32::from_be_bytes(payload_word(payload)?);
+        if angle.is_finite() {
+            Ok(Self(angle))
+        } else {
+            Err(PeripheralError::InvalidPayload("servo angle is not finite"))
+        }
+    }
+}
+
+/// A servo positioned by pulse width. An angle of 0 maps to `min_pulse` and `max_angle` maps to
+/// `max_pulse`, with one pulse every `period`. Angles outside `0..=max_angle` are clamped
+pub struct Servo<PWM: Output<Error = PeripheralError>> {
+    /// The PWM output driving the servo
+    pwm: PWM,
+    /// Pulse width at 0 degrees
+    min_pulse: Duration,
+    /// Pulse width at `max_angle` degrees
+    max_pulse: Duration,
+    /// The servo's full range of motion in degrees
+    max_angle: f32,
+    /// Time between pulses
+    period: Duration,
+}
+
+impl<PWM: Output<Error = PeripheralError>> Servo<PWM> {
+    /// Creates a standard 180 degree servo with 1ms-2ms pulses every 20ms
+    pub fn new(pwm: PWM) -> Self {
+        Self {
+            pwm,
+            min_pulse: Duration::from_micros(1_000),
+            max_pulse: Duration::from_micros(2_000),
+            max_angle: 180.0,
+            period: Duration::from_millis(20),
+        }
+    }
+
+    /// Sets the pulse widths at either end of the servo's range
+    pub fn with_pulse_range(mut self, min_pulse: Duration, max_pulse: Duration) -> Self {
+        self.min_pulse = min_pulse;
+        self.max_pulse = max_pulse;
+        self
+    }
+
+    /// Sets the servo's full range of motion in degrees
+    pub fn with_max_angle(mut self, max_angle: f32) -> Self {
+        self.max_angle = max_angle;
+        self
+    }
+
+    /// Sets the time between pulses
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// The pulse width for an angle
+    pub fn pulse_for(&self, angle: f32) -> Duration {
+        let ratio = (angle / self.max_angle).clamp(0.0, 1.0) as f64;
+        let range = self.max_pulse.as_secs_f64() - self.min_pulse.as_secs_f64();
+        Duration::from_secs_f64(self.min_pulse.as_secs_f64() + ratio * range)
+    }
+}
+
+impl<PWM: Output<Error = PeripheralError>> Actuator for Servo<PWM> {
+    type Command = Angle;
+
+    fn command(&mut self, Angle(angle): Angle) -> Result<(), PeripheralError> {
+        let period = self.period.as_secs_f64();
+        let duty_cycle = self.pulse_for(angle).as_secs_f64() / period;
+        self.pwm.write(&pwm_bytes(1.0 / period, duty_cycle))
+    }
+}
+
+impl<PWM: Output<Error = PeripheralError>> Output for Servo<PWM> {
+    type Error = PeripheralError;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.command(Angle::decode(bytes)?)
+    }
+
+    fn validate(&self, bytes: &[u8]) -> Result<(), Self::Error> {
+        Angle::decode(bytes).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{
+        actuators::Payload,
+        outputs::{virtual_outputs::RecordingOutput, Output},
+    };
+
+    use super::{Angle, Servo};
+
+    #[test]
+    fn servo_angle_maps_to_pulse_width() {
+        let pwm = RecordingOutput::new();
+        let log = pwm.log();
+        let mut servo = Servo::new(pwm);
+
+        servo
+            .write(&Angle(90.0).encode().unwrap())
+            .expect("Write servo angle");
+
+        let written = log.last().unwrap();
+        let frequency = f64::from_ne_bytes(written[0..8].try_into().unwrap());
+        let duty_cycle = f64::from_ne_bytes(written[8..16].try_into().unwrap());
+
+        // 1.5ms out of 20ms
+        assert!((frequency - 50.0).abs() < 1e-9);
+        assert!((duty_cycle - 0.075).abs() < 1e-9);
+    }
+
+    #[test]
+    fn servo_rejects_non_finite_angles() {
+        let payload = f32::NAN.to_be_bytes();
+        assert!(Angle::decode(&payload).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! A unipolar stepper motor half-stepped through four coil outputs
+
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    body::{outputs::Output, PeripheralError},
+    brain::executor::StopSignal,
+};
+
+use super::{payload_word, Actuator, Payload, HIGH, LOW};
+
+/// Steps in a full revolution of a half-stepped 28BYJ-48
+pub const STEPS_PER_REV: u32 = 4096;
+
+/// The half-step coil sequence, clockwise when walked forwards
+const HALF_STEPS: [[bool; 4]; 8] = [
+    [true, false, false, false],
+    [true, true, false, false],
+    [false, true, false, false],
+    [false, true, true, false],
+    [false, false, true, false],
+    [false, false, true, true],
+    [false, false, false, true],
+    [true, false, false, true],
+];
+
+/// Which way a stepper turns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Clockwise
+    Clockwise,
+    /// Counter-clockwise
+    CounterClockwise,
+}
+
+/// A number of steps to take in a direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepCommand {
+    /// How many steps to take
+    pub steps: u32,
+    /// Which way to turn
+    pub direction: Direction,
+}
+
+impl StepCommand {
+    /// The steps needed to turn from `prev_angle` to `new_angle` in degrees, as in `DegToStep.m`.
+    /// Decreasing the angle turns clockwise
+    pub fn from_degrees(prev_angle: f32, new_angle: f32) -> Self {
+        let deg_per_step = 360.0 / STEPS_PER_REV as f32;
+        let delta = prev_angle - new_angle;
+
+        Self {
+            steps: (delta.abs() / deg_per_step).round() as u32,
+            direction: if delta >= 0.0 {
+                Direction::Clockwise
+            } else {
+                Direction::CounterClockwise
+            },
+        }
+    }
+}
+
+impl Payload for StepCommand {
+    fn encode(&self) -> Result<[u8; 4], PeripheralError> {
+        let steps = i32::try_from(self.steps).map_err(|_| {
+            PeripheralError::InvalidPayload("step count doesn't fit in an i32 payload")
+        })?;
+        Ok(match self.direction {
+            Direction::Clockwise => steps,
+            Direction::CounterClockwise => -steps,
+        }
+        .to_be_bytes())
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self, PeripheralError> {
+        let steps = i32::from_be_bytes(payload_word(payload)?);
+        Ok(Self {
+            steps: steps.unsigned_abs(),
+            direction: if steps >= 0 {
+                Direction::Clockwise
+            } else {
+                Direction::CounterClockwise
+            },
+        })
+    }
+}
+
+/// The coils and where the motor is in its sequence, shared with the thread stepping a command
+struct Coils<COIL> {
+    /// The four coil outputs in sequence order
+    coils: [COIL; 4],
+    /// Where in the half-step sequence the motor is
+    phase: usize,
+    /// Bumped by every command, so a thread stepping an older one knows to give up
+    command: u64,
+}
+
+impl<COIL: Output<Error = PeripheralError>> Coils<COIL> {
+    /// Takes one step and energizes the coils for the new phase
+    fn step(&mut self, direction: Direction) -> Result<(), PeripheralError> {
+        self.phase = match direction {
+            Direction::Clockwise => (self.phase + 1) % HALF_STEPS.len(),
+            Direction::CounterClockwise => (self.phase + HALF_STEPS.len() - 1) % HALF_STEPS.len(),
+        };
+        for (coil, on) in self.coils.iter_mut().zip(HALF_STEPS[self.phase]) {
+            coil.write(&[if on { HIGH } else { LOW }])?;
+        }
+        Ok(())
+    }
+}
+
+/// A stepper such as the 28BYJ-48 on a ULN2003 driver, half-stepped through its four coils at
+/// `STEPS_PER_REV` steps per revolution. A command takes its first step before the write returns
+/// and the rest on a thread of its own, so the body isn't held while the motor turns. A new
+/// command replaces one still stepping, and the stop signal cuts one short between steps
+pub struct Stepper<COIL: Output<Error = PeripheralError> + Send + 'static> {
+    /// The coils, shared with the thread stepping the current command
+    coils: Arc<Mutex<Coils<COIL>>>,
+    /// How long to hold each step
+    step_delay: Duration,
+    /// Stops a command between steps
+    stop: StopSignal,
+    /// The thread stepping the latest command, if it took more than one step
+    stepping: Option<JoinHandle<Result<(), PeripheralError>>>,
+}
+
+impl<COIL: Output<Error = PeripheralError> + Send + 'static> Stepper<COIL> {
+    /// Creates a new stepper from its four coils, holding each step for 1ms
+    pub fn new(coils: [COIL; 4]) -> Self {
+        Self {
+            coils: Arc::new(Mutex::new(Coils {
+                coils,
+                phase: 0,
+                command: 0,
+            })),
+            step_delay: Duration::from_millis(1),
+            stop: StopSignal::default(),
+            stepping: None,
+        }
+    }
+
+    /// Watches a stop signal, such as an executor's, ending any command early once it's stopped
+    pub fn with_stop_signal(mut self, stop: StopSignal) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Sets how long each step is held
+    pub fn with_step_delay(mut self, step_delay: Duration) -> Self {
+        self.step_delay = step_delay;
+        self
+    }
+
+    /// Waits for the latest command to finish stepping, returning any error from its later steps
+    pub fn wait(&mut self) -> Result<(), PeripheralError> {
+        match self.stepping.take() {
+            Some(stepping) => stepping
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<COIL: Output<Error = PeripheralError> + Send + 'static> Actuator for Stepper<COIL> {
+    type Command = StepCommand;
+
+    fn command(&mut self, command: StepCommand) -> Result<(), PeripheralError> {
+        let current = {
+            let mut coils = self.coils.lock().unwrap();
+            coils.command += 1;
+            if command.steps == 0 || self.stop.is_stopped() {
+                return Ok(());
+            }
+            coils.step(command.direction)?;
+            coils.command
+        };
+
+        let (coils, stop, step_delay) = (self.coils.clone(), self.stop.clone(), self.step_delay);
+        self.stepping = Some(std::thread::spawn(move || {
+            for _ in 1..command.steps {
+                if stop.wait_until(Instant::now() + step_delay) {
+                    break;
+                }
+                let mut coils = coils.lock().unwrap();
+                if coils.command != current {
+                    break;
+                }
+                coils.step(command.direction)?;
+            }
+            Ok(())
+        }));
+        Ok(())
+    }
+}
+
+impl<COIL: Output<Error = PeripheralError> + Send + 'static> Output for Stepper<COIL> {
+    type Error = PeripheralError;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.command(StepCommand::decode(bytes)?)
+    }
+
+    fn validate(&self, bytes: &[u8]) -> Result<(), Self::Error> {
+        StepCommand::decode(bytes).map(|_| ())
+    }
+}
+
+impl<COIL: Output<Error = PeripheralError> + Send + 'static> Drop for Stepper<COIL> {
+    fn drop(&mut self) {
+        // Leaves the motor where it is rather than stepping on after it's gone
+        if let Ok(mut coils) = self.coils.lock() {
+            coils.command += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::body::{
+        actuators::{Payload, HIGH, LOW},
+        outputs::{virtual_outputs::RecordingOutput, Output},
+    };
+
+    use crate::brain::executor::StopSignal;
+
+    use super::{Direction, StepCommand, Stepper};
+
+    #[test]
+    fn quarter_turn_is_a_quarter_of_the_steps() {
+        let command = StepCommand::from_degrees(90.0, 0.0);
+        assert_eq!(command.steps, 1024);
+        assert_eq!(command.direction, Direction::Clockwise);
+
+        let command = StepCommand::from_degrees(0.0, 90.0);
+        assert_eq!(command.direction, Direction::CounterClockwise);
+    }
+
+    #[test]
+    fn step_command_round_trips_through_payload() {
+        let command = StepCommand {
+            steps: 300,
+            direction: Direction::CounterClockwise,
+        };
+        let payload = command.encode().unwrap();
+        assert_eq!(StepCommand::decode(&payload).unwrap(), command);
+    }
+
+    #[test]
+    fn stepper_walks_the_half_step_sequence() {
+        let coils = [(); 4].map(|_| RecordingOutput::new());
+        let logs = coils.each_ref().map(RecordingOutput::log);
+        let mut stepper = Stepper::new(coils).with_step_delay(Duration::ZERO);
+
+        let payload = StepCommand {
+            steps: 1,
+            direction: Direction::Clockwise,
+        }
+        .encode()
+        .unwrap();
+        stepper.write(&payload).expect("Step once");
+
+        let levels = logs.map(|log| log.last().unwrap()[0]);
+        assert_eq!(levels, [HIGH, HIGH, LOW, LOW]);
+    }
+
+    #[test]
+    fn step_counts_past_i32_are_rejected() {
+        let command = StepCommand {
+            steps: u32::MAX,
+            direction: Direction::Clockwise,
+        };
+        assert!(command.encode().is_err());
+    }
+
+    #[test]
+    fn stop_signal_ends_a_command_between_steps() {
+        let coils = [(); 4].map(|_| RecordingOutput::new());
+        let logs = coils.each_ref().map(RecordingOutput::log);
+        let stop = StopSignal::new();
+        let mut stepper = Stepper::new(coils)
+            .with_step_delay(Duration::from_secs(5))
+            .with_stop_signal(stop.clone());
+
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            stop.stop();
+        });
+
+        let payload = StepCommand {
+            steps: 1_000,
+            direction: Direction::Clockwise,
+        }
+        .encode()
+        .unwrap();
+        let started = Instant::now();
+        stepper.write(&payload).expect("Step until stopped");
+        stepper.wait().expect("Step until stopped");
+        stopper.join().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(logs[0].records().len(), 1);
+    }
+
+    #[test]
+    fn writes_return_while_the_motor_steps() {
+        let coils = [(); 4].map(|_| RecordingOutput::new());
+        let logs = coils.each_ref().map(RecordingOutput::log);
+        let mut stepper = Stepper::new(coils).with_step_delay(Duration::from_millis(20));
+
+        let payload = StepCommand {
+            steps: 3,
+            direction: Direction::Clockwise,
+        }
+        .encode()
+        .unwrap();
+        let started = Instant::now();
+        stepper.write(&payload).expect("Start stepping");
+        assert!(started.elapsed() < Duration::from_millis(20));
+        assert_eq!(logs[0].records().len(), 1);
+
+        stepper.wait().expect("Finish stepping");
+        assert_eq!(logs[0].records().len(), 3);
+
+        // A new command replaces one that's still stepping
+        let long = StepCommand {
+            steps: 1_000,
+            direction: Direction::Clockwise,
+        };
+        stepper
+            .write(&long.encode().unwrap())
+            .expect("Start stepping");
+        stepper.write(&[0, 0, 0, 0]).expect("Replace the command");
+        stepper.wait().expect("Nothing left to step");
+        assert_eq!(logs[0].records().len(), 4);
+    }
+}
@@ -0,0 +1,110 @@
+//! Adapters running blocking `Input`s and `Output`s on tokio's blocking threads so they can be used
+//! as `AsyncInput`s and `AsyncOutput`s
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task::JoinError,
+};
+
+use super::{
+    inputs::{AsyncInput, Input},
+    outputs::{AsyncOutput, Output},
+    PeripheralFuture,
+};
+
+/// Wraps a blocking peripheral so every read or write happens on a blocking thread. The peripheral
+/// is shared with the thread, so dropping a pending read or write never loses it
+pub struct Blocking<T> {
+    /// The wrapped peripheral
+    inner: Arc<Mutex<T>>,
+}
+
+impl<T> Blocking<T> {
+    /// Wraps a blocking peripheral
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+}
+
+impl<T> AsyncInput for Blocking<T>
+where
+    T: Input + Send + 'static,
+    T::Error: From<JoinError> + Send + 'static,
+{
+    type Error = T::Error;
+    fn read_input<'io>(
+        &'io mut self,
+        buf: &'io mut [u8],
+    ) -> PeripheralFuture<'io, Result<(), Self::Error>> {
+        let inner = self.inner.clone();
+        let mut read = vec![0u8; buf.len()];
+        Box::pin(async move {
+            let read = tokio::task::spawn_blocking(move || {
+                inner.lock().unwrap().read_input(&mut read).map(|_| read)
+            })
+            .await??;
+
+            buf.copy_from_slice(&read);
+            Ok(())
+        })
+    }
+}
+
+impl<T> AsyncOutput for Blocking<T>
+where
+    T: Output + Send + 'static,
+    T::Error: From<JoinError> + Send + 'static,
+{
+    type Error = T::Error;
+    fn write<'io>(
+        &'io mut self,
+        bytes: &'io [u8],
+    ) -> PeripheralFuture<'io, Result<(), Self::Error>> {
+        let inner = self.inner.clone();
+        let bytes = bytes.to_vec();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || inner.lock().unwrap().write(&bytes)).await??;
+            Ok(())
+        })
+    }
+}
+
+/// Runs blocking peripheral IO in place. On a multi threaded runtime the worker hands its other
+/// tasks off first so they aren't starved, anywhere else this simply calls `f`
+pub fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{
+        inputs::{virtual_inputs::ScriptedInput, AsyncInput},
+        outputs::{virtual_outputs::RecordingOutput, AsyncOutput},
+        PeripheralError,
+    };
+
+    use super::Blocking;
+
+    #[tokio::test]
+    async fn blocking_adapters_read_and_write() {
+        let mut input: Box<dyn AsyncInput<Error = PeripheralError>> =
+            Box::new(Blocking::new(ScriptedInput::constant(3.0)));
+        let mut buf = [0u8; 4];
+        input.read_input(&mut buf).await.expect("Read async input");
+        assert_eq!(f32::from_be_bytes(buf), 3.0);
+
+        let output = RecordingOutput::new();
+        let log = output.log();
+        let mut output: Box<dyn AsyncOutput<Error = PeripheralError>> =
+            Box::new(Blocking::new(output));
+        output.write(&[5, 6]).await.expect("Write async output");
+        assert_eq!(log.last(), Some(vec![5, 6]));
+    }
+}
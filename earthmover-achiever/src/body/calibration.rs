@@ -0,0 +1,448 @@
+//! Converting raw input samples to engineering units, fitting those conversions from reference
+//! readings, and the calibration file they're stored in alongside a body description
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{Body, PeripheralError, PeripheralNode};
+
+/// How many raw samples are averaged into each reference reading by default
+pub const DEFAULT_SAMPLES: usize = 16;
+
+/// An error calibrating a node or loading a calibration file
+#[derive(thiserror::Error, Debug)]
+pub enum CalibrationError {
+    #[error("IO Error: {0}")]
+    /// The calibration file or the calibration prompt couldn't be read or written
+    Io(#[from] std::io::Error),
+    #[error("Malformed calibration file: {0}")]
+    /// The calibration file isn't valid
+    Serde(#[from] serde_json::Error),
+    #[error("No peripheral named {0}")]
+    /// A calibration names a node that isn't in the body
+    UnknownNode(String),
+    #[error("Need at least {needed} distinct reference readings, got {got}")]
+    /// Too few reference readings to fit the requested conversion
+    NotEnoughPoints {
+        /// How many distinct readings the fit needs
+        needed: usize,
+        /// How many were given
+        got: usize,
+    },
+    #[error("Reference readings can't determine a unique fit")]
+    /// The reference readings don't pin down the coefficients
+    Singular,
+    #[error("Peripheral Error: {0}")]
+    /// Reading the node being calibrated failed
+    Peripheral(#[from] PeripheralError),
+}
+
+/// Converts a raw sample to engineering units
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Calibration {
+    /// `raw * scale + offset`, enough for most thermistors over a narrow range, pots and
+    /// load cells
+    Linear {
+        /// Multiplied with each raw sample
+        scale: f64,
+        /// Added after scaling
+        offset: f64,
+    },
+    /// A polynomial in the raw sample
+    Polynomial {
+        /// Coefficients from the constant term upwards
+        coefficients: Vec<f64>,
+    },
+    /// Linear interpolation between raw-value pairs, clamped to the ends of the table
+    Lookup {
+        /// Raw-value pairs sorted by raw sample, sorted on loading if a file has them in any order
+        #[serde(deserialize_with = "sorted_table")]
+        table: Vec<(f64, f64)>,
+    },
+}
+
+/// Deserializes a lookup table, sorting it by raw sample so it can be searched
+fn sorted_table<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(f64, f64)>, D::Error> {
+    let mut table = Vec::<(f64, f64)>::deserialize(deserializer)?;
+    table.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(table)
+}
+
+impl Calibration {
+    /// A linear conversion
+    pub fn linear(scale: f64, offset: f64) -> Self {
+        Self::Linear { scale, offset }
+    }
+
+    /// A polynomial conversion with coefficients from the constant term upwards
+    pub fn polynomial(coefficients: Vec<f64>) -> Self {
+        Self::Polynomial { coefficients }
+    }
+
+    /// A lookup table from raw-value pairs in any order
+    pub fn lookup(mut table: Vec<(f64, f64)>) -> Self {
+        table.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self::Lookup { table }
+    }
+
+    /// Converts a raw sample
+    pub fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Self::Linear { scale, offset } => raw * scale + offset,
+            Self::Polynomial { coefficients } => coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |acc, coefficient| acc * raw + coefficient),
+            Self::Lookup { table } => {
+                let after = table.partition_point(|&(at, _)| at < raw);
+                match (after.checked_sub(1).map(|i| table[i]), table.get(after)) {
+                    (Some((x0, y0)), Some(&(x1, y1))) if x1 > x0 => {
+                        y0 + (y1 - y0) * (raw - x0) / (x1 - x0)
+                    }
+                    (_, Some(&(_, value))) | (Some((_, value)), None) => value,
+                    (None, None) => raw,
+                }
+            }
+        }
+    }
+}
+
+/// Which sort of conversion to fit to reference readings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// A least squares line
+    Linear,
+    /// A least squares polynomial of this degree
+    Polynomial(usize),
+    /// A lookup table through every reading
+    Lookup,
+}
+
+impl Fit {
+    /// Fits a conversion to raw-reference pairs
+    pub fn fit(self, points: &[(f64, f64)]) -> Result<Calibration, CalibrationError> {
+        match self {
+            Self::Linear => match fit_polynomial(points, 1)?.as_slice() {
+                &[offset, scale] => Ok(Calibration::linear(scale, offset)),
+                _ => Err(CalibrationError::Singular),
+            },
+            Self::Polynomial(degree) => {
+                Ok(Calibration::polynomial(fit_polynomial(points, degree)?))
+            }
+            Self::Lookup => {
+                require_distinct(points, 1)?;
+                Ok(Calibration::lookup(points.to_vec()))
+            }
+        }
+    }
+}
+
+/// Checks there are at least `needed` distinct raw samples
+fn require_distinct(points: &[(f64, f64)], needed: usize) -> Result<(), CalibrationError> {
+    let mut raws: Vec<_> = points.iter().map(|&(raw, _)| raw).collect();
+    raws.sort_by(f64::total_cmp);
+    raws.dedup();
+
+    if raws.len() < needed {
+        return Err(CalibrationError::NotEnoughPoints {
+            needed,
+            got: raws.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Least squares polynomial coefficients from the constant term upwards, solved from the normal
+/// equations by Gaussian elimination
+fn fit_polynomial(points: &[(f64, f64)], degree: usize) -> Result<Vec<f64>, CalibrationError> {
+    let terms = degree + 1;
+    require_distinct(points, terms)?;
+
+    // The augmented normal equations: sums of x^(i + j) with sums of y * x^i on the right
+    let mut system = vec![vec![0.0; terms + 1]; terms];
+    for &(x, y) in points {
+        for (i, row) in system.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().take(terms).enumerate() {
+                *cell += x.powi((i + j) as i32);
+            }
+            row[terms] += y * x.powi(i as i32);
+        }
+    }
+
+    for column in 0..terms {
+        let pivot = (column..terms)
+            .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))
+            .ok_or(CalibrationError::Singular)?;
+        if system[pivot][column].abs() < f64::EPSILON {
+            return Err(CalibrationError::Singular);
+        }
+        system.swap(column, pivot);
+
+        let (done, rest) = system.split_at_mut(column + 1);
+        let pivot_row = &done[column];
+        for row in rest {
+            let factor = row[column] / pivot_row[column];
+            for (cell, pivot_cell) in row.iter_mut().zip(pivot_row).skip(column) {
+                *cell -= factor * pivot_cell;
+            }
+        }
+    }
+
+    let mut coefficients = vec![0.0; terms];
+    for row in (0..terms).rev() {
+        let known: f64 = (row + 1..terms)
+            .map(|k| system[row][k] * coefficients[k])
+            .sum();
+        coefficients[row] = (system[row][terms] - known) / system[row][row];
+    }
+    Ok(coefficients)
+}
+
+/// Every node's calibration by name, stored as JSON next to the body description
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationFile {
+    /// Calibrations keyed by node name
+    pub nodes: BTreeMap<String, Calibration>,
+}
+
+impl CalibrationFile {
+    /// Reads a calibration file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CalibrationError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Writes the calibration file, replacing whatever was there
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CalibrationError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Adds or replaces a node's calibration
+    pub fn with(mut self, name: impl Into<String>, calibration: Calibration) -> Self {
+        self.nodes.insert(name.into(), calibration);
+        self
+    }
+
+    /// Sets each named node's calibration. Nothing is changed if any name isn't in the body
+    pub fn apply_to(&self, body: &mut Body) -> Result<(), CalibrationError> {
+        let keys = self
+            .nodes
+            .keys()
+            .map(|name| {
+                body.get_by_name(name)
+                    .ok_or_else(|| CalibrationError::UnknownNode(name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (key, calibration) in keys.into_iter().zip(self.nodes.values()) {
+            body.peripheral_graph[key].metadata.calibration = Some(calibration.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Fits a node's calibration interactively: the sensor is brought to known conditions one after
+/// another, and each reference value entered is paired with the averaged raw reading
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibrator {
+    /// The conversion to fit
+    fit: Fit,
+    /// How many raw samples are averaged per reference value
+    samples: usize,
+}
+
+impl Calibrator {
+    /// Creates a calibrator fitting this sort of conversion
+    pub fn new(fit: Fit) -> Self {
+        Self {
+            fit,
+            samples: DEFAULT_SAMPLES,
+        }
+    }
+
+    /// Sets how many raw samples are averaged per reference value
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Averages raw samples from a node
+    pub fn measure(&self, node: &mut PeripheralNode) -> Result<f64, CalibrationError> {
+        let mut total = 0.0;
+        for _ in 0..self.samples {
+            total += node.read_raw()? as f64;
+        }
+        Ok(total / self.samples as f64)
+    }
+
+    /// Prompts for reference values until a blank line or the end of input, measuring the node
+    /// after each, then fits the readings
+    pub fn run(
+        &self,
+        node: &mut PeripheralNode,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> Result<Calibration, CalibrationError> {
+        let unit = node.metadata.unit.clone().unwrap_or_default();
+        let mut points = vec![];
+
+        loop {
+            write!(
+                output,
+                "Bring {} to a known value and enter it{}{unit}, or nothing to finish: ",
+                node.metadata.name,
+                if unit.is_empty() { "" } else { " in " },
+            )?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            let Ok(reference) = line.trim().parse::<f64>() else {
+                writeln!(output, "{:?} isn't a number", line.trim())?;
+                continue;
+            };
+
+            let raw = self.measure(node)?;
+            writeln!(output, "Raw {raw} is {reference}{unit}")?;
+            points.push((raw, reference));
+        }
+
+        self.fit.fit(&points)
+    }
+}
+
+impl Body {
+    /// Interactively calibrates the named node and sets its calibration
+    pub fn calibrate(
+        &mut self,
+        name: &str,
+        calibrator: &Calibrator,
+        input: impl BufRead,
+        output: impl Write,
+    ) -> Result<Calibration, CalibrationError> {
+        let key = self
+            .get_by_name(name)
+            .ok_or_else(|| CalibrationError::UnknownNode(name.to_string()))?;
+        let node = &mut self.peripheral_graph[key];
+
+        let calibration = calibrator.run(node, input, output)?;
+        node.metadata.calibration = Some(calibration.clone());
+        Ok(calibration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{metadata::Metadata, Body, Peripheral, PeripheralNode};
+
+    use super::{Calibration, CalibrationError, CalibrationFile, Calibrator, Fit};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn conversions_apply() {
+        assert_eq!(Calibration::linear(2.0, 1.0).apply(3.0), 7.0);
+        assert_eq!(
+            Calibration::polynomial(vec![1.0, 0.0, 2.0]).apply(3.0),
+            19.0
+        );
+
+        let table = Calibration::lookup(vec![(10.0, 100.0), (0.0, 0.0)]);
+        assert_eq!(table.apply(2.5), 25.0);
+        assert_eq!(table.apply(-1.0), 0.0);
+        assert_eq!(table.apply(11.0), 100.0);
+
+        let loaded: Calibration =
+            serde_json::from_str(r#"{"type": "lookup", "table": [[10.0, 100.0], [0.0, 0.0]]}"#)
+                .unwrap();
+        assert_eq!(loaded, table);
+        assert_eq!(loaded.apply(2.5), 25.0);
+    }
+
+    #[test]
+    fn fits_reference_readings() {
+        let line = Fit::Linear
+            .fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)])
+            .unwrap();
+        let Calibration::Linear { scale, offset } = line else {
+            panic!("Expected a linear fit, got {line:?}");
+        };
+        assert!(close(scale, 2.0) && close(offset, 1.0));
+
+        let square = Fit::Polynomial(2)
+            .fit(&[(-1.0, 1.0), (0.0, 0.0), (1.0, 1.0), (2.0, 4.0)])
+            .unwrap();
+        assert!(close(square.apply(3.0), 9.0));
+
+        assert!(matches!(
+            Fit::Linear.fit(&[(1.0, 1.0), (1.0, 2.0)]),
+            Err(CalibrationError::NotEnoughPoints { needed: 2, got: 1 })
+        ));
+    }
+
+    #[test]
+    fn calibration_file_sets_node_conversions() {
+        let sensor = PeripheralNode::from(Peripheral::constant_input(2.0))
+            .with_metadata(Metadata::new("thermistor").with_scaling(10.0, 0.0));
+        let mut body = Body::builder().with_node(sensor).build();
+
+        let file = CalibrationFile::default().with("thermistor", Calibration::linear(0.5, -1.0));
+        let json = serde_json::to_string(&file).unwrap();
+        let file: CalibrationFile = serde_json::from_str(&json).unwrap();
+        file.apply_to(&mut body).unwrap();
+
+        let key = body.root[0];
+        assert_eq!(body.peripheral_graph[key].read_sample().unwrap(), 0.0);
+
+        let unknown = CalibrationFile::default().with("missing", Calibration::linear(1.0, 0.0));
+        assert!(matches!(
+            unknown.apply_to(&mut body),
+            Err(CalibrationError::UnknownNode(_))
+        ));
+    }
+
+    #[test]
+    fn interactive_calibration_pairs_references_with_readings() {
+        let mut reads = 0;
+        let sensor = PeripheralNode::from(Peripheral::fn_input(move |_| {
+            reads += 1;
+            if reads <= 2 {
+                10.0
+            } else {
+                20.0
+            }
+        }))
+        .with_metadata(Metadata::new("probe").with_unit("C"));
+        let mut body = Body::builder().with_node(sensor).build();
+
+        let mut prompts = vec![];
+        let calibration = body
+            .calibrate(
+                "probe",
+                &Calibrator::new(Fit::Linear).with_samples(2),
+                "0\nwarm\n100\n\n".as_bytes(),
+                &mut prompts,
+            )
+            .unwrap();
+
+        assert!(close(calibration.apply(15.0), 50.0));
+        assert!(String::from_utf8(prompts)
+            .unwrap()
+            .contains("isn't a number"));
+
+        let key = body.root[0];
+        assert!((body.peripheral_graph[key].read_sample().unwrap() - 100.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,299 @@
+//! The JSON description of a body an agent is started with, naming each peripheral, what drives
+//! it and the AHTP column it feeds
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{metadata::Metadata, Body, Peripheral, PeripheralError, PeripheralKey, PeripheralNode};
+
+/// An error loading a body description or building a body from it
+#[derive(thiserror::Error, Debug)]
+pub enum DescriptionError {
+    #[error("IO Error: {0}")]
+    /// The description couldn't be read
+    Io(#[from] std::io::Error),
+    #[error("Malformed body description: {0}")]
+    /// The description isn't valid
+    Serde(#[from] serde_json::Error),
+    #[error("More than one peripheral is named {0}")]
+    /// Two nodes share a name, so neither can be looked up by it
+    DuplicateName(String),
+    #[error("{node} is connected to {to}, which comes after it or isn't in the body")]
+    /// A node is connected to one that hasn't been described before it
+    UnknownConnection {
+        /// The node being connected
+        node: String,
+        /// The name it's connected to
+        to: String,
+    },
+    #[error("Couldn't open {node}: {err}")]
+    /// A peripheral's hardware couldn't be opened
+    Peripheral {
+        /// The node whose peripheral failed
+        node: String,
+        /// Why it failed
+        err: PeripheralError,
+    },
+}
+
+/// What drives a node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeripheralDescription {
+    /// An input that always reads the same value
+    Constant {
+        /// The value read
+        value: f32,
+    },
+    /// An input reading a sine wave over time
+    Sine {
+        /// The wave's amplitude
+        amplitude: f32,
+        /// The wave's frequency in hertz
+        frequency: f32,
+        /// Added to every reading
+        offset: f32,
+    },
+    /// An input replaying a column of a CSV file
+    Csv {
+        /// The CSV file
+        path: PathBuf,
+        /// Which column is replayed
+        column: usize,
+    },
+    /// An output that only records what's written to it
+    Recording,
+    #[cfg(feature = "rpi")]
+    /// A Raspberry Pi GPIO pin read as an input
+    GpioInput {
+        /// The BCM pin number
+        pin: u8,
+    },
+    #[cfg(feature = "rpi")]
+    /// A Raspberry Pi GPIO pin driven as an output
+    GpioOutput {
+        /// The BCM pin number
+        pin: u8,
+    },
+    #[cfg(feature = "rpi")]
+    /// A Raspberry Pi i2c device read as an input
+    I2cInput {
+        /// The device's address
+        address: u16,
+    },
+    #[cfg(feature = "linux")]
+    /// A serial tty read as an input, at 8N1
+    SerialInput {
+        /// The tty's path
+        path: PathBuf,
+        /// The baud rate in bits per second
+        baud_rate: u32,
+    },
+    #[cfg(feature = "linux")]
+    /// A serial tty written as an output, at 8N1
+    SerialOutput {
+        /// The tty's path
+        path: PathBuf,
+        /// The baud rate in bits per second
+        baud_rate: u32,
+    },
+}
+
+impl PeripheralDescription {
+    /// Opens the peripheral described
+    pub fn open(&self) -> Result<Peripheral, PeripheralError> {
+        Ok(match self {
+            Self::Constant { value } => Peripheral::constant_input(*value),
+            Self::Sine {
+                amplitude,
+                frequency,
+                offset,
+            } => Peripheral::sine_input(*amplitude, *frequency, *offset),
+            Self::Csv { path, column } => Peripheral::csv_input(path, *column)?,
+            Self::Recording => Peripheral::recording_output().0,
+            #[cfg(feature = "rpi")]
+            Self::GpioInput { pin } => Peripheral::gpio_input(*pin)?,
+            #[cfg(feature = "rpi")]
+            Self::GpioOutput { pin } => Peripheral::gpio_output(*pin)?,
+            #[cfg(feature = "rpi")]
+            Self::I2cInput { address } => Peripheral::i2c_input(*address)?,
+            #[cfg(feature = "linux")]
+            Self::SerialInput { path, baud_rate } => Peripheral::serial_input(
+                path,
+                super::serial::SerialConfig {
+                    baud_rate: *baud_rate,
+                    ..Default::default()
+                },
+            )?,
+            #[cfg(feature = "linux")]
+            Self::SerialOutput { path, baud_rate } => Peripheral::serial_output(
+                path,
+                super::serial::SerialConfig {
+                    baud_rate: *baud_rate,
+                    ..Default::default()
+                },
+            )?,
+        })
+    }
+}
+
+/// One node of a body description
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeDescription {
+    /// A human readable name, unique within the body
+    pub name: String,
+    /// What drives the node
+    pub peripheral: PeripheralDescription,
+    /// The unit readings are reported in after scaling
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The AHTP data column the node's readings fill, if it feeds one
+    #[serde(default)]
+    pub column: Option<usize>,
+    /// How many bytes make up one sample, 4 for a big endian `f32` when not given
+    #[serde(default)]
+    pub sample_width: Option<usize>,
+    /// Multiplied with each raw sample, then `offset` added
+    #[serde(default)]
+    pub scale: Option<f32>,
+    /// Added to each raw sample after scaling
+    #[serde(default)]
+    pub offset: Option<f32>,
+    /// The name of an earlier node this one is connected to, at the root when not given
+    #[serde(default)]
+    pub connected_to: Option<String>,
+}
+
+impl NodeDescription {
+    /// The node's metadata
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new(&self.name);
+        if let Some(unit) = &self.unit {
+            metadata = metadata.with_unit(unit);
+        }
+        if let Some(column) = self.column {
+            metadata = metadata.with_column(column);
+        }
+        if let Some(width) = self.sample_width {
+            metadata = metadata.with_sample_width(width);
+        }
+        if self.scale.is_some() || self.offset.is_some() {
+            metadata = metadata.with_scaling(self.scale.unwrap_or(1.0), self.offset.unwrap_or(0.0));
+        }
+        metadata
+    }
+}
+
+/// Every node of a body, in the order they're added
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BodyDescription {
+    /// The body's nodes. A node may only be connected to one described before it
+    pub nodes: Vec<NodeDescription>,
+}
+
+impl BodyDescription {
+    /// Reads a body description
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DescriptionError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Opens every peripheral and builds the body
+    pub fn build(&self) -> Result<Body, DescriptionError> {
+        let mut builder = Body::builder();
+        let mut keys: HashMap<&str, PeripheralKey> = HashMap::new();
+
+        for node in &self.nodes {
+            if keys.contains_key(node.name.as_str()) {
+                return Err(DescriptionError::DuplicateName(node.name.clone()));
+            }
+            let parent = match &node.connected_to {
+                Some(to) => Some(*keys.get(to.as_str()).ok_or_else(|| {
+                    DescriptionError::UnknownConnection {
+                        node: node.name.clone(),
+                        to: to.clone(),
+                    }
+                })?),
+                None => None,
+            };
+
+            let peripheral =
+                node.peripheral
+                    .open()
+                    .map_err(|err| DescriptionError::Peripheral {
+                        node: node.name.clone(),
+                        err,
+                    })?;
+            let peripheral = PeripheralNode::from(peripheral).with_metadata(node.metadata());
+            let key = match parent {
+                Some(parent) => builder.add_node_to(peripheral, parent),
+                None => builder.add_node(peripheral),
+            };
+            keys.insert(&node.name, key);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BodyDescription, DescriptionError};
+
+    #[test]
+    fn descriptions_build_named_bodies() {
+        let description: BodyDescription = serde_json::from_str(
+            r#"{"nodes": [
+                {"name": "light", "peripheral": {"type": "constant", "value": 2.0}, "column": 0},
+                {"name": "arm", "peripheral": {"type": "recording"}},
+                {"name": "temp", "peripheral": {"type": "constant", "value": 4.0},
+                 "column": 1, "scale": 0.5, "connected_to": "arm"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut body = description.build().unwrap();
+        let schema = body.channel_schema().unwrap();
+        assert_eq!(schema.dims(), 2);
+        assert_eq!(body.root.len(), 2);
+
+        let temp = body.get_by_name("temp").unwrap();
+        assert_eq!(
+            body.get_by_id_mut(temp).unwrap().read_sample().unwrap(),
+            2.0
+        );
+        let arm = body.get_by_name("arm").unwrap();
+        assert_eq!(body.get_by_id(arm).unwrap().points_to, Some(vec![temp]));
+    }
+
+    #[test]
+    fn connections_must_name_earlier_nodes() {
+        let description: BodyDescription = serde_json::from_str(
+            r#"{"nodes": [
+                {"name": "temp", "peripheral": {"type": "recording"}, "connected_to": "arm"},
+                {"name": "arm", "peripheral": {"type": "recording"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            description.build(),
+            Err(DescriptionError::UnknownConnection { .. })
+        ));
+
+        let description: BodyDescription = serde_json::from_str(
+            r#"{"nodes": [
+                {"name": "arm", "peripheral": {"type": "recording"}},
+                {"name": "arm", "peripheral": {"type": "recording"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            description.build(),
+            Err(DescriptionError::DuplicateName(name)) if name == "arm"
+        ));
+    }
+}
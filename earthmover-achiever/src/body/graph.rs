@@ -0,0 +1,290 @@
+//! Topology queries over a Body's peripheral graph, and a Graphviz DOT export of its wiring
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use slotmap::Key;
+
+use super::{Body, Peripheral, PeripheralKey};
+
+/// A problem with the shape of a Body's peripheral graph
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    #[error("Peripheral graph has a cycle through {0:?}")]
+    /// Following edges from these nodes leads back to the first
+    Cycle(Vec<PeripheralKey>),
+    #[error("Peripherals {0:?} can't be reached from the root")]
+    /// These nodes have no path from any root node
+    Unreachable(Vec<PeripheralKey>),
+    #[error("Edge from {from:?} points to missing peripheral {to:?}")]
+    /// An edge or root entry points at a node that isn't in the graph
+    DanglingEdge {
+        /// Where the edge starts, `None` for a root entry
+        from: Option<PeripheralKey>,
+        /// The missing node
+        to: PeripheralKey,
+    },
+}
+
+impl Body {
+    /// The nodes `key` points to
+    pub fn children(&self, key: PeripheralKey) -> &[PeripheralKey] {
+        self.peripheral_graph
+            .get(key)
+            .and_then(|node| node.points_to.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The nodes that point to `key`
+    pub fn parents(&self, key: PeripheralKey) -> Vec<PeripheralKey> {
+        self.peripheral_graph
+            .iter()
+            .filter(|(_, node)| node.points_to.iter().flatten().any(|&child| child == key))
+            .map(|(parent, _)| parent)
+            .collect()
+    }
+
+    /// Every node reachable from `key`, not including `key` itself unless it's on a cycle, in
+    /// breadth first order
+    pub fn descendants(&self, key: PeripheralKey) -> Vec<PeripheralKey> {
+        self.reachable_from(self.children(key).iter().copied())
+    }
+
+    /// Every node reachable from the given starting nodes, including them, in breadth first order
+    fn reachable_from(&self, start: impl IntoIterator<Item = PeripheralKey>) -> Vec<PeripheralKey> {
+        let mut seen = HashSet::new();
+        let mut order = vec![];
+        let mut queue: VecDeque<_> = start.into_iter().collect();
+
+        while let Some(key) = queue.pop_front() {
+            if !self.peripheral_graph.contains_key(key) || !seen.insert(key) {
+                continue;
+            }
+            order.push(key);
+            queue.extend(self.children(key));
+        }
+        order
+    }
+
+    /// Finds a cycle in the graph if there is one, as the nodes along it in edge order
+    pub fn find_cycle(&self) -> Option<Vec<PeripheralKey>> {
+        /// Where a node is in the depth first search
+        #[derive(Clone, Copy, PartialEq)]
+        enum Visit {
+            /// On the current path
+            Active,
+            /// Fully explored
+            Done,
+        }
+
+        let mut visits = HashMap::new();
+        for start in self.peripheral_graph.keys() {
+            if visits.contains_key(&start) {
+                continue;
+            }
+
+            // Each frame is a node on the current path and how many of its children are explored
+            let mut path = vec![(start, 0)];
+            visits.insert(start, Visit::Active);
+
+            while let Some((key, next)) = path.last_mut() {
+                let Some(&child) = self.children(*key).get(*next) else {
+                    visits.insert(*key, Visit::Done);
+                    path.pop();
+                    continue;
+                };
+                *next += 1;
+
+                match visits.get(&child) {
+                    Some(Visit::Active) => {
+                        let from = path.iter().position(|&(key, _)| key == child)?;
+                        return Some(path[from..].iter().map(|&(key, _)| key).collect());
+                    }
+                    Some(Visit::Done) => {}
+                    None if self.peripheral_graph.contains_key(child) => {
+                        visits.insert(child, Visit::Active);
+                        path.push((child, 0));
+                    }
+                    None => {}
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns true if following edges can lead back to where it started
+    pub fn has_cycle(&self) -> bool {
+        self.find_cycle().is_some()
+    }
+
+    /// Every node with no path from a root node
+    pub fn unreachable(&self) -> Vec<PeripheralKey> {
+        let reachable: HashSet<_> = self
+            .reachable_from(self.root.iter().copied())
+            .into_iter()
+            .collect();
+
+        self.peripheral_graph
+            .keys()
+            .filter(|key| !reachable.contains(key))
+            .collect()
+    }
+
+    /// Checks that every edge points at a real node, that there are no cycles, and that every
+    /// node can be reached from the root
+    pub fn validate_graph(&self) -> Result<(), GraphError> {
+        for &to in &self.root {
+            if !self.peripheral_graph.contains_key(to) {
+                return Err(GraphError::DanglingEdge { from: None, to });
+            }
+        }
+        for (from, node) in &self.peripheral_graph {
+            for &to in node.points_to.iter().flatten() {
+                if !self.peripheral_graph.contains_key(to) {
+                    return Err(GraphError::DanglingEdge {
+                        from: Some(from),
+                        to,
+                    });
+                }
+            }
+        }
+
+        if let Some(cycle) = self.find_cycle() {
+            return Err(GraphError::Cycle(cycle));
+        }
+
+        let unreachable = self.unreachable();
+        if !unreachable.is_empty() {
+            return Err(GraphError::Unreachable(unreachable));
+        }
+        Ok(())
+    }
+
+    /// Removes a node along with every edge to it. Children left without any parent are moved to
+    /// the root so they stay reachable
+    pub fn remove_node(&mut self, key: PeripheralKey) -> Option<Peripheral> {
+        let removed = self.peripheral_graph.remove(key)?;
+
+        self.root.retain(|&root| root != key);
+        for (_, node) in self.peripheral_graph.iter_mut() {
+            if let Some(children) = &mut node.points_to {
+                children.retain(|&child| child != key);
+            }
+        }
+
+        for child in removed.points_to.into_iter().flatten() {
+            let orphaned = self.peripheral_graph.contains_key(child)
+                && !self.root.contains(&child)
+                && self.parents(child).is_empty();
+            if orphaned {
+                self.root.push(child);
+            }
+        }
+
+        Some(removed.peripheral)
+    }
+
+    /// Renders the graph's wiring in Graphviz DOT, labelled by name, with inputs as ellipses and
+    /// outputs as boxes
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph body {\n    root [shape=point];\n");
+
+        for (key, node) in &self.peripheral_graph {
+            let (kind, shape) = match node.peripheral {
+                Peripheral::Input(_) => ("input", "ellipse"),
+                Peripheral::Output(_) => ("output", "box"),
+            };
+            let label = match node.metadata.name.as_str() {
+                "" => format!("{kind} {key:?}"),
+                name => name.replace('"', "\\\""),
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{label}\", shape={shape}];",
+                dot_id(key)
+            );
+        }
+
+        for &key in &self.root {
+            let _ = writeln!(dot, "    root -> {};", dot_id(key));
+        }
+        for (key, node) in &self.peripheral_graph {
+            for &child in node.points_to.iter().flatten() {
+                let _ = writeln!(dot, "    {} -> {};", dot_id(key), dot_id(child));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A DOT identifier for a node
+fn dot_id(key: PeripheralKey) -> String {
+    format!("n{}", key.data().as_ffi())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, Peripheral};
+
+    use super::GraphError;
+
+    /// A root output with an input child, which has an output grandchild
+    fn chain() -> Body {
+        let mut builder = Body::builder();
+        let arm = builder.add_node(Peripheral::recording_output().0);
+        let sensor = builder.add_node_to(Peripheral::constant_input(1.0), arm);
+        builder.add_node_to(Peripheral::recording_output().0, sensor);
+        builder.build()
+    }
+
+    #[test]
+    fn traverses_parents_children_and_descendants() {
+        let body = chain();
+        let arm = body.root[0];
+        let sensor = body.children(arm)[0];
+        let gripper = body.children(sensor)[0];
+
+        assert_eq!(body.parents(gripper), vec![sensor]);
+        assert_eq!(body.descendants(arm), vec![sensor, gripper]);
+        assert!(body.validate_graph().is_ok());
+    }
+
+    #[test]
+    fn detects_cycles_and_unreachable_nodes() {
+        let mut body = chain();
+        let arm = body.root[0];
+        let sensor = body.children(arm)[0];
+        let gripper = body.children(sensor)[0];
+
+        body.peripheral_graph[gripper].points_to = Some(vec![sensor]);
+        assert_eq!(body.find_cycle(), Some(vec![sensor, gripper]));
+
+        body.peripheral_graph[gripper].points_to = None;
+        body.peripheral_graph[arm].points_to = None;
+        assert_eq!(
+            body.validate_graph(),
+            Err(GraphError::Unreachable(vec![sensor, gripper]))
+        );
+    }
+
+    #[test]
+    fn removing_a_node_keeps_its_children_reachable() {
+        let mut body = chain();
+        let arm = body.root[0];
+        let sensor = body.children(arm)[0];
+        let gripper = body.children(sensor)[0];
+
+        assert!(body.remove_node(sensor).is_some());
+        assert!(body.children(arm).is_empty());
+        assert_eq!(body.root, vec![arm, gripper]);
+        assert!(body.validate_graph().is_ok());
+
+        let dot = body.to_dot();
+        assert!(dot.starts_with("digraph body {"));
+        assert_eq!(dot.matches("->").count(), 2);
+    }
+}
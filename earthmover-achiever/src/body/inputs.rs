@@ -1,4 +1,6 @@
-//! Trait defining a peripheral input
+//! Traits defining a peripheral input
+
+use super::PeripheralFuture;
 
 /// A trait for inputs, defines a single method for polling the peripheral's data
 pub trait Input {
@@ -9,8 +11,24 @@ pub trait Input {
     fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
 }
 
+/// The async counterpart of `Input`, for inputs that can be polled without blocking the runtime
+pub trait AsyncInput {
+    /// The error read_input may return
+    type Error;
+    /// Read bytes from peripheral entirely to an output buffer
+    fn read_input<'io>(
+        &'io mut self,
+        buf: &'io mut [u8],
+    ) -> PeripheralFuture<'io, Result<(), Self::Error>>;
+}
+
 #[cfg(feature = "rpi")]
 pub mod rpi_inputs;
 
 #[cfg(feature = "jetson")]
 pub mod jetson_inputs;
+
+#[cfg(feature = "linux")]
+pub mod linux_inputs;
+
+pub mod virtual_inputs;
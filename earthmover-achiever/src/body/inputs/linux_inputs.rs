@@ -0,0 +1,49 @@
+//! Input Implementations for generic Linux Peripherals
+
+use crate::body::{serial::SerialPort, PeripheralError};
+
+use super::Input;
+
+impl Input for SerialPort {
+    type Error = PeripheralError;
+    fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_exact(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write, time::Duration};
+
+    use nix::pty::openpty;
+
+    use crate::body::serial::{SerialConfig, SerialPort};
+
+    use super::Input;
+
+    #[test]
+    fn serial_input_reads_from_pseudo_terminal() {
+        let pty = openpty(None, None).expect("Open pseudo-terminal");
+        let mut port = SerialPort::from_file(File::from(pty.slave), SerialConfig::default())
+            .expect("Configure serial port");
+        let mut master = File::from(pty.master);
+
+        master.write_all(&[1, 2, 3, 4]).expect("Write to master");
+
+        let mut buf = [0u8; 4];
+        port.read_input(&mut buf).expect("Read serial input");
+        assert_eq!(buf, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn serial_input_times_out_without_data() {
+        let pty = openpty(None, None).expect("Open pseudo-terminal");
+        let config =
+            SerialConfig::with_baud_rate(9_600).with_timeout(Some(Duration::from_millis(100)));
+        let mut port =
+            SerialPort::from_file(File::from(pty.slave), config).expect("Configure serial port");
+
+        let mut buf = [0u8; 1];
+        assert!(port.read_input(&mut buf).is_err());
+    }
+}
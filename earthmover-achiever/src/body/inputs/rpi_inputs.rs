@@ -33,7 +33,7 @@ impl Input for Spi {
 }
 
 impl Input for Uart {
-    type Error = rppal::uart::Error;
+    type Error = PeripheralError;
     fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
         self.read(buf)?;
         Ok(())
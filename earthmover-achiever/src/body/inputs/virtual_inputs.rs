@@ -0,0 +1,124 @@
+//! Scripted Input Implementations for running an agent without hardware
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::body::PeripheralError;
+
+use super::Input;
+
+/// The size of a single virtual sample in bytes. Every sample is a big endian f32
+pub const SAMPLE_SIZE: usize = std::mem::size_of::<f32>();
+
+/// An input whose readings come from a script instead of hardware. Every sample is produced by a
+/// function of the time elapsed since the input was created and encoded as a big endian f32. If
+/// the read buffer fits more than one sample it is filled with consecutive samples, a trailing
+/// partial sample is truncated
+pub struct ScriptedInput {
+    /// Produces the next sample given the time since creation
+    script: Box<dyn FnMut(Duration) -> f32 + Send>,
+    /// When this input was created
+    started: Instant,
+}
+
+impl ScriptedInput {
+    /// Creates an input whose samples come from an arbitrary closure of elapsed time
+    pub fn from_fn(script: impl FnMut(Duration) -> f32 + Send + 'static) -> Self {
+        Self {
+            script: Box::new(script),
+            started: Instant::now(),
+        }
+    }
+
+    /// Creates an input that always reads the same value
+    pub fn constant(value: f32) -> Self {
+        Self::from_fn(move |_| value)
+    }
+
+    /// Creates an input that reads `offset + amplitude * sin(2π * frequency * t)`, with the
+    /// frequency in hertz
+    pub fn sine(amplitude: f32, frequency: f32, offset: f32) -> Self {
+        Self::from_fn(move |elapsed| {
+            offset
+                + amplitude
+                    * f32::sin(2.0 * std::f32::consts::PI * frequency * elapsed.as_secs_f32())
+        })
+    }
+
+    /// Creates an input that replays one column of a CSV file, one row per sample, looping back
+    /// to the first row once the file is exhausted. Rows whose column isn't a number (such as a
+    /// header) are skipped
+    pub fn from_csv(path: impl AsRef<Path>, column: usize) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let values = contents
+            .lines()
+            .filter_map(|line| line.split(',').nth(column)?.trim().parse::<f32>().ok())
+            .collect::<Vec<_>>();
+
+        Self::from_values(values).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("CSV column {column} has no numeric values"),
+            )
+        })
+    }
+
+    /// Creates an input that replays a list of values one per sample, looping back to the start.
+    /// `None` if there are no values to replay
+    pub fn from_values(values: Vec<f32>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut idx = 0;
+        Some(Self::from_fn(move |_| {
+            let val = values[idx % values.len()];
+            idx += 1;
+            val
+        }))
+    }
+}
+
+impl Input for ScriptedInput {
+    type Error = PeripheralError;
+    fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        for chunk in buf.chunks_mut(SAMPLE_SIZE) {
+            let sample = (self.script)(self.started.elapsed()).to_be_bytes();
+            chunk.copy_from_slice(&sample[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, ScriptedInput};
+
+    #[test]
+    fn constant_input_fills_every_sample() {
+        let mut input = ScriptedInput::constant(2.5);
+        let mut buf = [0u8; 8];
+        input.read_input(&mut buf).expect("Read constant input");
+
+        assert_eq!(buf[0..4], 2.5f32.to_be_bytes());
+        assert_eq!(buf[4..8], 2.5f32.to_be_bytes());
+    }
+
+    #[test]
+    fn value_input_loops_back_to_start() {
+        let mut input = ScriptedInput::from_values(vec![1.0, 2.0]).expect("Values to replay");
+        let mut buf = [0u8; 12];
+        input.read_input(&mut buf).expect("Read scripted input");
+
+        assert_eq!(buf[0..4], 1f32.to_be_bytes());
+        assert_eq!(buf[4..8], 2f32.to_be_bytes());
+        assert_eq!(buf[8..12], 1f32.to_be_bytes());
+    }
+
+    #[test]
+    fn value_input_needs_values() {
+        assert!(ScriptedInput::from_values(vec![]).is_none());
+    }
+}
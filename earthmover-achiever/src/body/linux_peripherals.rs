@@ -0,0 +1,30 @@
+//! Extensions of the Peripheral Enum to construct generic Linux Peripherals
+
+use std::path::Path;
+
+use super::{
+    serial::{SerialConfig, SerialPort},
+    Peripheral, PeripheralError,
+};
+
+impl Peripheral {
+    /// Creates a Peripheral Input Node for a serial tty
+    pub fn serial_input(
+        path: impl AsRef<Path>,
+        config: SerialConfig,
+    ) -> Result<Self, PeripheralError> {
+        let port = SerialPort::open(path, config)?;
+
+        Ok(Self::Input(Box::new(port)))
+    }
+
+    /// Creates a Peripheral Output Node for a serial tty
+    pub fn serial_output(
+        path: impl AsRef<Path>,
+        config: SerialConfig,
+    ) -> Result<Self, PeripheralError> {
+        let port = SerialPort::open(path, config)?;
+
+        Ok(Self::Output(Box::new(port)))
+    }
+}
@@ -0,0 +1,356 @@
+//! Descriptions of what each peripheral is, and the AHTP channel schema generated from them
+
+use std::collections::BTreeMap;
+
+use crate::{
+    brain::fusion::{SPATIAL_DIMS, SPATIAL_NAMES},
+    goals::{
+        expr::{Expr, ExprError},
+        ChannelGoal, Goal,
+    },
+};
+
+use super::{
+    calibration::Calibration, inputs::virtual_inputs::SAMPLE_SIZE, safety::SafetyLimits, Body,
+    Peripheral, PeripheralError, PeripheralKey, PeripheralNode,
+};
+
+/// What sort of peripheral a node is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Kind {
+    /// A sensor reporting a continuous value
+    Analog,
+    /// An on/off pin, either read or driven
+    Digital,
+    /// A DC motor
+    Motor,
+    /// A hobby servo
+    Servo,
+    /// A stepper motor
+    Stepper,
+    /// A serial device
+    Serial,
+    /// Anything else
+    #[default]
+    Other,
+}
+
+/// A human readable description of a peripheral and how its readings map onto AHTP data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    /// A human readable name, unique within a body
+    pub name: String,
+    /// What sort of peripheral this is
+    pub kind: Kind,
+    /// The unit readings are reported in after scaling
+    pub unit: Option<String>,
+    /// How many bytes make up one sample: 1 for a `u8`, 2 for a big endian `u16`, or 4 for a big
+    /// endian `f32`
+    pub sample_width: usize,
+    /// Multiplied with each raw sample
+    pub scale: f32,
+    /// Added to each raw sample after scaling
+    pub offset: f32,
+    /// The AHTP data column this peripheral's readings fill, if it feeds one
+    pub column: Option<usize>,
+    /// Converts raw samples to the reported unit, replacing the scale and offset
+    pub calibration: Option<Calibration>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: Kind::default(),
+            unit: None,
+            sample_width: SAMPLE_SIZE,
+            scale: 1.0,
+            offset: 0.0,
+            column: None,
+            calibration: None,
+        }
+    }
+}
+
+impl Metadata {
+    /// Describes a peripheral by name, reading `f32` samples unscaled
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets what sort of peripheral this is
+    pub fn with_kind(mut self, kind: Kind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Sets the unit readings are reported in
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    /// Sets how many bytes make up one sample
+    pub fn with_sample_width(mut self, sample_width: usize) -> Self {
+        self.sample_width = sample_width;
+        self
+    }
+
+    /// Sets how raw samples are converted to the reported unit, as `raw * scale + offset`
+    pub fn with_scaling(mut self, scale: f32, offset: f32) -> Self {
+        self.scale = scale;
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the AHTP data column this peripheral's readings fill
+    pub fn with_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Sets the calibration raw samples are converted with, in place of scaling
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Reads one raw sample's value before any conversion
+    pub fn raw(&self, sample: &[u8]) -> Result<f32, PeripheralError> {
+        match *sample {
+            [byte] => Ok(byte as f32),
+            [a, b] => Ok(u16::from_be_bytes([a, b]) as f32),
+            [a, b, c, d] => Ok(f32::from_be_bytes([a, b, c, d])),
+            _ => Err(PeripheralError::InvalidPayload(
+                "sample width must be 1, 2 or 4 bytes",
+            )),
+        }
+    }
+
+    /// Converts a raw value to the reported unit, with the calibration if there is one and the
+    /// scaling otherwise
+    pub fn convert(&self, raw: f32) -> f32 {
+        match &self.calibration {
+            Some(calibration) => calibration.apply(raw as f64) as f32,
+            None => raw * self.scale + self.offset,
+        }
+    }
+
+    /// Converts one raw sample to the reported unit
+    pub fn decode(&self, sample: &[u8]) -> Result<f32, PeripheralError> {
+        Ok(self.convert(self.raw(sample)?))
+    }
+}
+
+impl PeripheralNode {
+    /// Describes the node
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Limits every write to the node
+    pub fn with_limits(mut self, limits: SafetyLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Reads one sample from an input node and converts it to its unit
+    pub fn read_sample(&mut self) -> Result<f32, PeripheralError> {
+        let raw = self.read_raw()?;
+        Ok(self.metadata.convert(raw))
+    }
+
+    /// Reads one sample from an input node without converting it
+    pub fn read_raw(&mut self) -> Result<f32, PeripheralError> {
+        let Peripheral::Input(input) = &mut self.peripheral else {
+            return Err(PeripheralError::NotAnInput);
+        };
+
+        let mut sample = vec![0; self.metadata.sample_width];
+        input.read_input(&mut sample)?;
+        self.metadata.raw(&sample)
+    }
+}
+
+/// A single data column of the AHTP schema
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    /// The node feeding this column
+    pub node: PeripheralKey,
+    /// The node's name
+    pub name: String,
+    /// The unit the column is in
+    pub unit: Option<String>,
+}
+
+/// A problem generating a channel schema
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    #[error("Column {0} is fed by more than one peripheral")]
+    /// Two nodes claim the same column
+    DuplicateColumn(usize),
+    #[error("No peripheral feeds column {0}")]
+    /// A column below the highest is left empty
+    MissingColumn(usize),
+}
+
+/// Which peripheral feeds each dimension of the data sent to the hivemind
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelSchema {
+    /// The channels in column order
+    pub channels: Vec<Channel>,
+}
+
+impl ChannelSchema {
+    /// How many channels the body feeds
+    pub fn dims(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// How many dimensions each fused point has, its xyz position then every channel, to be sent
+    /// as `SetDims`
+    pub fn point_dims(&self) -> usize {
+        SPATIAL_DIMS + self.dims()
+    }
+
+    /// Where the named dimension is in a fused point, counting the spatial dimensions first like
+    /// goals and reward expressions do. A channel's own column is `SPATIAL_DIMS` less
+    pub fn point_index(&self, name: &str) -> Option<usize> {
+        self.point_names().iter().position(|&dim| dim == name)
+    }
+
+    /// The name of every dimension of a fused point, in order. The spatial names come first, so
+    /// they shadow any channel sharing one
+    fn point_names(&self) -> Vec<&str> {
+        SPATIAL_NAMES
+            .into_iter()
+            .chain(self.channels.iter().map(|channel| channel.name.as_str()))
+            .collect()
+    }
+
+    /// Parses a reward expression over a fused point's dimensions, by spatial or channel name, to
+    /// be sent as a `Reward`
+    pub fn reward(&self, source: &str) -> Result<Expr, ExprError> {
+        Expr::parse(source, &self.point_names())
+    }
+
+    /// A goal for the named dimension of a fused point, to be sent as a `Goal`
+    pub fn goal(&self, name: &str, goal: Goal) -> Option<ChannelGoal> {
+        self.point_index(name)
+            .map(|index| ChannelGoal::new(index, goal))
+    }
+}
+
+impl Body {
+    /// Builds the channel schema from every node's column. Columns must run from 0 without gaps
+    pub fn channel_schema(&self) -> Result<ChannelSchema, SchemaError> {
+        let mut columns = BTreeMap::new();
+        for (key, node) in &self.peripheral_graph {
+            if let Some(column) = node.metadata.column {
+                let channel = Channel {
+                    node: key,
+                    name: node.metadata.name.clone(),
+                    unit: node.metadata.unit.clone(),
+                };
+                if columns.insert(column, channel).is_some() {
+                    return Err(SchemaError::DuplicateColumn(column));
+                }
+            }
+        }
+
+        let mut channels = vec![];
+        for (expected, (column, channel)) in columns.into_iter().enumerate() {
+            if column != expected {
+                return Err(SchemaError::MissingColumn(expected));
+            }
+            channels.push(channel);
+        }
+        Ok(ChannelSchema { channels })
+    }
+
+    /// Finds a node by its metadata name
+    pub fn get_by_name(&self, name: &str) -> Option<PeripheralKey> {
+        self.peripheral_graph
+            .iter()
+            .find(|(_, node)| node.metadata.name == name)
+            .map(|(key, _)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, Peripheral, PeripheralNode};
+
+    use crate::goals::{ChannelGoal, Goal};
+
+    use super::{Metadata, SchemaError};
+
+    fn sensor(name: &str, column: usize) -> PeripheralNode {
+        PeripheralNode::from(Peripheral::constant_input(2.0))
+            .with_metadata(Metadata::new(name).with_unit("m").with_column(column))
+    }
+
+    #[test]
+    fn schema_follows_columns() {
+        let body = Body::builder()
+            .with_node(sensor("temp", 1))
+            .with_node(Peripheral::recording_output().0)
+            .with_node(sensor("light", 0))
+            .build();
+
+        let schema = body.channel_schema().unwrap();
+        assert_eq!(schema.dims(), 2);
+        assert_eq!(schema.channels[0].node, body.get_by_name("light").unwrap());
+    }
+
+    #[test]
+    fn schema_names_follow_the_fused_point() {
+        let body = Body::builder()
+            .with_node(sensor("temp", 1))
+            .with_node(sensor("light", 0))
+            .build();
+
+        let schema = body.channel_schema().unwrap();
+        assert_eq!(schema.point_dims(), 5);
+        assert_eq!(schema.point_index("light"), Some(3));
+        assert_eq!(schema.point_index("y"), Some(1));
+        assert_eq!(
+            schema.goal("temp", Goal::Maximize),
+            Some(ChannelGoal::maximize(4))
+        );
+        assert_eq!(
+            schema.goal("z", Goal::Minimize),
+            Some(ChannelGoal::minimize(2))
+        );
+        assert_eq!(schema.goal("humidity", Goal::Maximize), None);
+
+        let reward = schema.reward("light - abs(x)").unwrap();
+        assert_eq!(reward.max_channel(), Some(3));
+        assert_eq!(reward.eval(&[1.0, 0.0, 0.0, 5.0, 0.0]), 4.0);
+    }
+
+    #[test]
+    fn schema_rejects_gaps_and_duplicates() {
+        let gap = Body::builder().with_node(sensor("x", 1)).build();
+        assert_eq!(gap.channel_schema(), Err(SchemaError::MissingColumn(0)));
+
+        let duplicate = Body::builder()
+            .with_node(sensor("x", 0))
+            .with_node(sensor("y", 0))
+            .build();
+        assert_eq!(
+            duplicate.channel_schema(),
+            Err(SchemaError::DuplicateColumn(0))
+        );
+    }
+
+    #[test]
+    fn samples_are_scaled() {
+        let mut node = sensor("x", 0).with_metadata(Metadata::new("x").with_scaling(0.5, 1.0));
+        assert_eq!(node.read_sample().unwrap(), 2.0);
+    }
+}
@@ -1,4 +1,6 @@
-//! Trait defining a peripheral output
+//! Traits defining a peripheral output
+
+use super::PeripheralFuture;
 
 /// A trait for outputs, defines a single method for writing bytes to an output
 pub trait Output {
@@ -6,6 +8,23 @@ pub trait Output {
     type Error;
     /// Write bytes to an output peripheral
     fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+    /// Checks that bytes would make sense to write without writing them. Outputs that accept any
+    /// bytes don't need to override this
+    fn validate(&self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let _ = bytes;
+        Ok(())
+    }
+}
+
+/// The async counterpart of `Output`, for outputs that can be written without blocking the runtime
+pub trait AsyncOutput {
+    /// The error write may return
+    type Error;
+    /// Write bytes to an output peripheral
+    fn write<'io>(
+        &'io mut self,
+        bytes: &'io [u8],
+    ) -> PeripheralFuture<'io, Result<(), Self::Error>>;
 }
 
 #[cfg(feature = "rpi")]
@@ -13,3 +32,8 @@ pub mod rpi_outputs;
 
 #[cfg(feature = "jetson")]
 pub mod jetson_outputs;
+
+#[cfg(feature = "linux")]
+pub mod linux_outputs;
+
+pub mod virtual_outputs;
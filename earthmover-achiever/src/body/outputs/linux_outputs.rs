@@ -0,0 +1,38 @@
+//! Output Implementations for generic Linux Peripherals
+
+use crate::body::{serial::SerialPort, PeripheralError};
+
+use super::Output;
+
+impl Output for SerialPort {
+    type Error = PeripheralError;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_all(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Read};
+
+    use nix::pty::openpty;
+
+    use crate::body::serial::{Parity, SerialConfig, SerialPort};
+
+    use super::Output;
+
+    #[test]
+    fn serial_output_writes_to_pseudo_terminal() {
+        let pty = openpty(None, None).expect("Open pseudo-terminal");
+        let config = SerialConfig::with_baud_rate(57_600).with_parity(Parity::Even);
+        let mut port =
+            SerialPort::from_file(File::from(pty.slave), config).expect("Configure serial port");
+        let mut master = File::from(pty.master);
+
+        port.write(&[9, 8, 7]).expect("Write serial output");
+
+        let mut buf = [0u8; 3];
+        master.read_exact(&mut buf).expect("Read from master");
+        assert_eq!(buf, [9, 8, 7]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Recording Output Implementations for running an agent without hardware
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::body::PeripheralError;
+
+use super::Output;
+
+/// A single write made to a `RecordingOutput`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteRecord {
+    /// When the write happened, relative to the log's creation
+    pub at: Duration,
+    /// The bytes written
+    pub bytes: Vec<u8>,
+}
+
+/// A shared, timestamped log of every write made to a `RecordingOutput`. Cloning the log gives
+/// another handle to the same records, so tests can keep one after the output is boxed into a
+/// `Body`
+#[derive(Clone, Debug)]
+pub struct WriteLog {
+    /// When the log was created
+    started: Instant,
+    /// All writes so far
+    records: Arc<Mutex<Vec<WriteRecord>>>,
+}
+
+impl Default for WriteLog {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            records: Arc::default(),
+        }
+    }
+}
+
+impl WriteLog {
+    /// Returns a copy of every write recorded so far
+    pub fn records(&self) -> Vec<WriteRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Returns the bytes of the most recent write if there was one
+    pub fn last(&self) -> Option<Vec<u8>> {
+        self.records
+            .lock()
+            .unwrap()
+            .last()
+            .map(|record| record.bytes.clone())
+    }
+
+    /// Returns how many writes have been recorded
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    /// Returns true if nothing has been written yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records a new write
+    fn push(&self, bytes: &[u8]) {
+        self.records.lock().unwrap().push(WriteRecord {
+            at: self.started.elapsed(),
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// An output that drives nothing and records every write to a `WriteLog`
+#[derive(Clone, Debug, Default)]
+pub struct RecordingOutput {
+    /// Where writes are recorded
+    log: WriteLog,
+}
+
+impl RecordingOutput {
+    /// Creates a new recording output with an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to this output's log
+    pub fn log(&self) -> WriteLog {
+        self.log.clone()
+    }
+}
+
+impl Output for RecordingOutput {
+    type Error = PeripheralError;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.log.push(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Output, RecordingOutput};
+
+    #[test]
+    fn recording_output_logs_every_write_in_order() {
+        let mut output = RecordingOutput::new();
+        let log = output.log();
+
+        output.write(&[1, 2]).expect("Write to recording output");
+        output.write(&[3]).expect("Write to recording output");
+
+        let records = log.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].bytes, vec![1, 2]);
+        assert_eq!(records[1].bytes, vec![3]);
+        assert!(records[0].at <= records[1].at);
+    }
+}
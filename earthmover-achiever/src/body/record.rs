@@ -0,0 +1,402 @@
+//! Recording raw Input streams to a log file and replaying them back as Inputs, so a field run can
+//! be reproduced at the desk
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Cursor, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use deku::prelude::*;
+use slotmap::{Key, KeyData};
+
+use super::{
+    inputs::Input, metadata::Metadata, Body, Peripheral, PeripheralError, PeripheralKey,
+    PeripheralNode,
+};
+
+/// A single frame of a recording log. A stream is declared once with its name, after which every
+/// sample only carries the stream's key
+#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
+#[deku(id_type = "u8", endian = "big")]
+enum Frame {
+    /// Declares a new stream for a peripheral
+    #[deku(id = 0)]
+    Stream {
+        /// The recorded node's key
+        key: u64,
+        /// Length of the name in bytes
+        name_len: u16,
+        /// The recorded node's name as UTF-8
+        #[deku(count = "name_len")]
+        name: Vec<u8>,
+    },
+    /// A single `read_input` result
+    #[deku(id = 1)]
+    Sample {
+        /// The recorded node's key
+        key: u64,
+        /// Microseconds since the recording started
+        micros: u64,
+        /// Length of the read in bytes
+        len: u32,
+        /// The bytes read
+        #[deku(count = "len")]
+        bytes: Vec<u8>,
+    },
+}
+
+/// Converts a deku error into an IO error
+fn deku_to_io(err: DekuError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// A shared writer to a recording log. Any number of inputs can be wrapped to record into the same
+/// log, all timestamped against the same clock
+#[derive(Clone)]
+pub struct Recorder {
+    /// When the recording started
+    started: Instant,
+    /// The log file
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recorder {
+    /// Creates a new recording log at `path`, truncating anything already there
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            started: Instant::now(),
+            writer: Arc::new(Mutex::new(BufWriter::new(File::create(path)?))),
+        })
+    }
+
+    /// Wraps an input so every read from it is recorded under a key and name. Names longer than
+    /// `u16::MAX` bytes can't be recorded
+    pub fn wrap<IN: Input<Error = PeripheralError> + ?Sized>(
+        &self,
+        key: PeripheralKey,
+        name: &str,
+        input: Box<IN>,
+    ) -> std::io::Result<RecordingInput<IN>> {
+        self.declare(key, name)?;
+        Ok(self.recording(key, input))
+    }
+
+    /// Declares a stream for a key and name, which is all that can fail in wrapping an input
+    fn declare(&self, key: PeripheralKey, name: &str) -> std::io::Result<()> {
+        let name_len = u16::try_from(name.len()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Peripheral name is {} bytes, a recording holds at most {}",
+                    name.len(),
+                    u16::MAX
+                ),
+            )
+        })?;
+        self.write_frame(Frame::Stream {
+            key: key.data().as_ffi(),
+            name_len,
+            name: name.as_bytes().to_vec(),
+        })
+    }
+
+    /// Records an input under a key whose stream is already declared
+    fn recording<IN: Input<Error = PeripheralError> + ?Sized>(
+        &self,
+        key: PeripheralKey,
+        input: Box<IN>,
+    ) -> RecordingInput<IN> {
+        RecordingInput {
+            key,
+            input,
+            recorder: self.clone(),
+        }
+    }
+
+    /// Flushes everything recorded so far to disk
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /// Appends a frame to the log
+    fn write_frame(&self, frame: Frame) -> std::io::Result<()> {
+        let bytes = frame.to_bytes().map_err(deku_to_io)?;
+        self.writer.lock().unwrap().write_all(&bytes)
+    }
+}
+
+/// An input that records every read from the input it wraps
+pub struct RecordingInput<IN: Input<Error = PeripheralError> + ?Sized> {
+    /// The key this input is recorded under
+    key: PeripheralKey,
+    /// The wrapped input
+    input: Box<IN>,
+    /// Where reads are recorded
+    recorder: Recorder,
+}
+
+impl<IN: Input<Error = PeripheralError> + ?Sized> Input for RecordingInput<IN> {
+    type Error = PeripheralError;
+    fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.input.read_input(buf)?;
+        self.recorder.write_frame(Frame::Sample {
+            key: self.key.data().as_ffi(),
+            micros: self.recorder.started.elapsed().as_micros() as u64,
+            len: buf.len() as u32,
+            bytes: buf.to_vec(),
+        })?;
+        Ok(())
+    }
+}
+
+impl Body {
+    /// Wraps every input node in the body so its reads are recorded to `recorder` under the node's
+    /// name. An input whose stream can't be declared is left as it was
+    pub fn record_inputs(&mut self, recorder: &Recorder) -> std::io::Result<()> {
+        for (key, node) in self.peripheral_graph.iter_mut() {
+            if let Peripheral::Input(input) = &mut node.peripheral {
+                recorder.declare(key, &node.metadata.name)?;
+                let inner = std::mem::replace(input, Box::new(ReplayInput::default()));
+                *input = Box::new(recorder.recording(key, inner));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How a recording is played back
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pacing {
+    /// Block each read until the sample's original time has passed
+    #[default]
+    Original,
+    /// Return every sample immediately
+    AsFastAsPossible,
+}
+
+/// A single recorded stream
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedStream {
+    /// The recorded node's name
+    pub name: String,
+    /// Every recorded read and when it happened
+    pub samples: Vec<(Duration, Vec<u8>)>,
+}
+
+/// A recording loaded from a log file, keyed by the recorded nodes' keys
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    /// Every recorded stream
+    pub streams: HashMap<PeripheralKey, RecordedStream>,
+    /// Stream keys in the order they were declared
+    order: Vec<PeripheralKey>,
+}
+
+impl Recording {
+    /// Loads a recording from a log file
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let len = bytes.len() as u64;
+        let mut cursor = Cursor::new(bytes);
+        let mut recording = Self::default();
+
+        while cursor.position() < len {
+            let (_, frame) = Frame::from_reader((&mut cursor, 0)).map_err(deku_to_io)?;
+            match frame {
+                Frame::Stream { key, name, .. } => {
+                    let key = PeripheralKey::from(KeyData::from_ffi(key));
+                    recording.order.push(key);
+                    recording.streams.insert(
+                        key,
+                        RecordedStream {
+                            name: String::from_utf8_lossy(&name).into_owned(),
+                            samples: vec![],
+                        },
+                    );
+                }
+                Frame::Sample {
+                    key, micros, bytes, ..
+                } => {
+                    let key = PeripheralKey::from(KeyData::from_ffi(key));
+                    if let Some(stream) = recording.streams.get_mut(&key) {
+                        stream.samples.push((Duration::from_micros(micros), bytes));
+                    }
+                }
+            }
+        }
+
+        Ok(recording)
+    }
+
+    /// Rebuilds a body with one replaying input node per recorded stream, in the order the
+    /// streams were recorded and named as they were recorded. All nodes share the same clock, so
+    /// with `Pacing::Original` they play back relative to one another just as they were recorded
+    pub fn into_body(mut self, pacing: Pacing) -> Body {
+        let started = Instant::now();
+        let mut builder = Body::builder();
+        for key in self.order {
+            if let Some(stream) = self.streams.remove(&key) {
+                let replay = ReplayInput {
+                    samples: stream.samples,
+                    next: 0,
+                    pacing,
+                    started,
+                };
+                let node = PeripheralNode::from(Peripheral::Input(Box::new(replay)))
+                    .with_metadata(Metadata::new(stream.name));
+                builder = builder.with_node(node);
+            }
+        }
+
+        builder.build()
+    }
+}
+
+/// An input that plays back a recorded stream
+pub struct ReplayInput {
+    /// The samples to play back
+    samples: Vec<(Duration, Vec<u8>)>,
+    /// The next sample to play
+    next: usize,
+    /// How samples are paced
+    pacing: Pacing,
+    /// When playback started
+    started: Instant,
+}
+
+impl Default for ReplayInput {
+    fn default() -> Self {
+        Self::new(RecordedStream::default(), Pacing::default())
+    }
+}
+
+impl ReplayInput {
+    /// Creates a new input that plays back a recorded stream from now
+    pub fn new(stream: RecordedStream, pacing: Pacing) -> Self {
+        Self {
+            samples: stream.samples,
+            next: 0,
+            pacing,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Input for ReplayInput {
+    type Error = PeripheralError;
+    fn read_input(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let (at, bytes) = self
+                .samples
+                .get(self.next)
+                .ok_or(PeripheralError::EndOfRecording)?;
+
+            if self.pacing == Pacing::Original {
+                if let Some(wait) = at.checked_sub(self.started.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+            }
+
+            let len = bytes.len().min(buf.len() - filled);
+            buf[filled..filled + len].copy_from_slice(&bytes[..len]);
+            filled += len;
+            self.next += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{
+        inputs::virtual_inputs::ScriptedInput, metadata::Metadata, Body, Peripheral, PeripheralKey,
+        PeripheralNode,
+    };
+
+    use super::{Pacing, Recorder, Recording, ReplayInput};
+
+    #[test]
+    fn recorded_body_replays_identically() {
+        let path = std::env::temp_dir().join("earthmover_recorded_body_replays_identically.log");
+
+        let mut body = Body::builder()
+            .with_node(
+                PeripheralNode::from(Peripheral::Input(Box::new(
+                    ScriptedInput::from_values(vec![1.0, 2.0, 3.0]).expect("Values to replay"),
+                )))
+                .with_metadata(Metadata::new("light")),
+            )
+            .build();
+        let recorder = Recorder::create(&path).expect("Create recording");
+        body.record_inputs(&recorder).expect("Wrap inputs");
+
+        let mut recorded = vec![];
+        for node in body.peripheral_graph.values_mut() {
+            if let Peripheral::Input(input) = &mut node.peripheral {
+                for _ in 0..3 {
+                    let mut buf = [0u8; 4];
+                    input.read_input(&mut buf).expect("Read recorded input");
+                    recorded.push(buf);
+                }
+            }
+        }
+        recorder.flush().expect("Flush recording");
+
+        let recording = Recording::load(&path).expect("Load recording");
+        let mut replayed_body = recording.into_body(Pacing::AsFastAsPossible);
+        let node = replayed_body.peripheral_graph.values_mut().next().unwrap();
+        assert_eq!(node.metadata.name, "light");
+        let Peripheral::Input(input) = &mut node.peripheral else {
+            panic!("Replayed node should be an input")
+        };
+
+        for expected in recorded {
+            let mut buf = [0u8; 4];
+            input.read_input(&mut buf).expect("Read replayed input");
+            assert_eq!(buf, expected);
+        }
+        assert!(input.read_input(&mut [0u8; 4]).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn overlong_names_are_rejected() {
+        let path = std::env::temp_dir().join("earthmover_overlong_names_are_rejected.log");
+        let recorder = Recorder::create(&path).expect("Create recording");
+
+        let name = "a".repeat(u16::MAX as usize + 1);
+        let wrapped = recorder.wrap(
+            PeripheralKey::default(),
+            &name,
+            Box::new(ReplayInput::default()),
+        );
+        assert_eq!(
+            wrapped.err().map(|err| err.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+
+        // A body whose input can't be recorded keeps reading from it
+        let mut body = Body::builder()
+            .with_node(
+                PeripheralNode::from(Peripheral::constant_input(2.0))
+                    .with_metadata(Metadata::new(&name)),
+            )
+            .build();
+        assert!(body.record_inputs(&recorder).is_err());
+        let input = body.root[0];
+        assert_eq!(
+            body.get_by_id_mut(input).unwrap().read_sample().unwrap(),
+            2.0
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+}
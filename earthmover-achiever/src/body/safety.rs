@@ -0,0 +1,523 @@
+//! Per-peripheral safety limits checked on every write, and a deadman watchdog that brings every
+//! output to its safe state when the agent loop or the server connection stalls
+
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::brain::executor::StopSignal;
+
+use super::{Body, Peripheral, PeripheralError, PeripheralNode, SharedBody};
+
+/// How the value a range limit checks is encoded in a write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// The first byte, as written to a digital pin
+    U8,
+    /// A big endian f32 in the first four bytes, as in actuator payloads
+    F32Be,
+    /// A big endian i32 in the first four bytes, as in stepper payloads
+    I32Be,
+    /// The native endian f64 duty cycle in the last eight bytes of a PWM write
+    DutyCycle,
+}
+
+impl Encoding {
+    /// Where the value lives in a write
+    fn span(self, len: usize) -> Option<std::ops::Range<usize>> {
+        let span = match self {
+            Self::U8 => 0..1,
+            Self::F32Be | Self::I32Be => 0..4,
+            Self::DutyCycle => len.checked_sub(8)?..len,
+        };
+        (span.end <= len).then_some(span)
+    }
+
+    /// Reads the value out of a write
+    fn decode(self, bytes: &[u8]) -> Option<f64> {
+        let bytes = &bytes[self.span(bytes.len())?];
+        Some(match self {
+            Self::U8 => bytes[0] as f64,
+            Self::F32Be => f32::from_be_bytes(bytes.try_into().ok()?) as f64,
+            Self::I32Be => i32::from_be_bytes(bytes.try_into().ok()?) as f64,
+            Self::DutyCycle => f64::from_ne_bytes(bytes.try_into().ok()?),
+        })
+    }
+
+    /// Overwrites the value in a write
+    fn encode_into(self, value: f64, bytes: &mut [u8]) {
+        let Some(span) = self.span(bytes.len()) else {
+            return;
+        };
+        match self {
+            Self::U8 => bytes[span][0] = value as u8,
+            Self::F32Be => bytes[span].copy_from_slice(&(value as f32).to_be_bytes()),
+            Self::I32Be => bytes[span].copy_from_slice(&(value as i32).to_be_bytes()),
+            Self::DutyCycle => bytes[span].copy_from_slice(&value.to_ne_bytes()),
+        }
+    }
+}
+
+/// The range of values an output may be written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValueRange {
+    /// How the value is encoded
+    pub encoding: Encoding,
+    /// The smallest allowed value
+    pub min: f64,
+    /// The largest allowed value
+    pub max: f64,
+}
+
+/// What happens to a write that breaks a limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnViolation {
+    /// Fail the write with `PeripheralError::SafetyLimit`
+    #[default]
+    Reject,
+    /// Clamp the value into range, drop writes that come too fast, and fall back to the safe
+    /// state once the on-time runs out
+    Clamp,
+}
+
+/// Limits on what may be written to an output
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SafetyLimits {
+    /// The allowed range of values
+    pub range: Option<ValueRange>,
+    /// The longest an output may stay out of its safe state
+    pub max_on_time: Option<Duration>,
+    /// The shortest time allowed between two writes
+    pub min_write_interval: Option<Duration>,
+    /// The bytes that bring the output to rest
+    pub safe_state: Option<Vec<u8>>,
+    /// What happens to a write that breaks a limit
+    pub on_violation: OnViolation,
+}
+
+impl SafetyLimits {
+    /// Creates a set of limits that allows everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the written value to `min..=max`
+    pub fn with_range(mut self, encoding: Encoding, min: f64, max: f64) -> Self {
+        self.range = Some(ValueRange { encoding, min, max });
+        self
+    }
+
+    /// Restricts how long the output may stay out of its safe state. Only enforced when a safe
+    /// state is set
+    pub fn with_max_on_time(mut self, max_on_time: Duration) -> Self {
+        self.max_on_time = Some(max_on_time);
+        self
+    }
+
+    /// Restricts how often the output may be written
+    pub fn with_min_write_interval(mut self, min_write_interval: Duration) -> Self {
+        self.min_write_interval = Some(min_write_interval);
+        self
+    }
+
+    /// Sets the bytes that bring the output to rest
+    pub fn with_safe_state(mut self, safe_state: impl Into<Vec<u8>>) -> Self {
+        self.safe_state = Some(safe_state.into());
+        self
+    }
+
+    /// Clamps violating writes instead of rejecting them
+    pub fn clamping(mut self) -> Self {
+        self.on_violation = OnViolation::Clamp;
+        self
+    }
+}
+
+/// What a node's limits need to remember between writes
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct GuardState {
+    /// When the output was last written
+    last_write: Option<Instant>,
+    /// When the output left its safe state, if it isn't in it
+    on_since: Option<Instant>,
+}
+
+impl PeripheralNode {
+    /// Writes to the node's output, checking the write against the node's limits. Writing the
+    /// safe state is never rate limited, so bringing an output to rest always goes through
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), PeripheralError> {
+        let Peripheral::Output(output) = &mut self.peripheral else {
+            return Err(PeripheralError::NotAnOutput);
+        };
+        let Some(limits) = &self.limits else {
+            return output.write(bytes);
+        };
+
+        let now = Instant::now();
+        let clamp = limits.on_violation == OnViolation::Clamp;
+        let resting = limits.safe_state.as_deref() == Some(bytes);
+
+        if let (Some(min), Some(last), false) =
+            (limits.min_write_interval, self.guard.last_write, resting)
+        {
+            if now - last < min {
+                return match clamp {
+                    true => Ok(()),
+                    false => Err(PeripheralError::SafetyLimit(format!(
+                        "written again within {min:?}"
+                    ))),
+                };
+            }
+        }
+
+        if let (Some(max), Some(since)) = (limits.max_on_time, self.guard.on_since) {
+            if now - since > max {
+                self.make_safe()?;
+                return match clamp {
+                    true => Ok(()),
+                    false => Err(PeripheralError::SafetyLimit(format!(
+                        "on for longer than {max:?}"
+                    ))),
+                };
+            }
+        }
+
+        let mut bytes = Cow::Borrowed(bytes);
+        if let Some(range) = limits.range {
+            let value = range
+                .encoding
+                .decode(&bytes)
+                .ok_or(PeripheralError::InvalidPayload(
+                    "payload is too short for its safety range",
+                ))?;
+
+            if !(range.min..=range.max).contains(&value) {
+                if !clamp || value.is_nan() {
+                    return Err(PeripheralError::SafetyLimit(format!(
+                        "{value} is outside {}..={}",
+                        range.min, range.max
+                    )));
+                }
+                range
+                    .encoding
+                    .encode_into(value.clamp(range.min, range.max), bytes.to_mut());
+            }
+        }
+
+        output.write(&bytes)?;
+
+        self.guard.last_write = Some(now);
+        self.guard.on_since = match &limits.safe_state {
+            Some(safe) if *safe == *bytes => None,
+            _ => self.guard.on_since.or(Some(now)),
+        };
+        Ok(())
+    }
+
+    /// Checks a write against the output and the node's limits without writing it. Writes that
+    /// would be clamped pass, as do rate and on-time limits since they depend on when the write
+    /// happens
+    pub fn check(&self, bytes: &[u8]) -> Result<(), PeripheralError> {
+        let Peripheral::Output(output) = &self.peripheral else {
+            return Err(PeripheralError::NotAnOutput);
+        };
+        output.validate(bytes)?;
+
+        let Some(limits) = &self.limits else {
+            return Ok(());
+        };
+        if let Some(range) = limits.range {
+            let value = range
+                .encoding
+                .decode(bytes)
+                .ok_or(PeripheralError::InvalidPayload(
+                    "payload is too short for its safety range",
+                ))?;
+
+            let clamps = limits.on_violation == OnViolation::Clamp && !value.is_nan();
+            if !clamps && !(range.min..=range.max).contains(&value) {
+                return Err(PeripheralError::SafetyLimit(format!(
+                    "{value} is outside {}..={}",
+                    range.min, range.max
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the node's safe state, if it's an output with one. Returns true if it was written
+    pub fn make_safe(&mut self) -> Result<bool, PeripheralError> {
+        let (Peripheral::Output(output), Some(safe)) = (
+            &mut self.peripheral,
+            self.limits
+                .as_ref()
+                .and_then(|limits| limits.safe_state.as_ref()),
+        ) else {
+            return Ok(false);
+        };
+
+        output.write(safe)?;
+        self.guard.last_write = Some(Instant::now());
+        self.guard.on_since = None;
+        Ok(true)
+    }
+
+    /// Returns true if the node has been out of its safe state for longer than its limits allow
+    fn overstayed(&self, now: Instant) -> bool {
+        let max = self.limits.as_ref().and_then(|limits| limits.max_on_time);
+        matches!((max, self.guard.on_since), (Some(max), Some(since)) if now - since > max)
+    }
+}
+
+impl Body {
+    /// Writes every output's safe state, carrying on past failures and returning the first
+    pub fn make_safe(&mut self) -> Result<(), PeripheralError> {
+        let mut result = Ok(());
+        for (_, node) in self.peripheral_graph.iter_mut() {
+            if let Err(err) = node.make_safe() {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    /// Writes the safe state of every output that's been on for longer than its limits allow
+    pub fn enforce_limits(&mut self) -> Result<(), PeripheralError> {
+        let now = Instant::now();
+        let mut result = Ok(());
+        for (_, node) in self.peripheral_graph.iter_mut() {
+            if node.overstayed(now) {
+                if let Err(err) = node.make_safe() {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Keeps a watchdog from tripping. Clones feed the same watchdog, so one can be handed to the
+/// agent loop and another to the server connection
+#[derive(Clone)]
+pub struct Feeder {
+    /// When the watchdog was last fed
+    last_fed: Arc<Mutex<Instant>>,
+}
+
+impl Feeder {
+    /// Tells the watchdog everything is still alive
+    pub fn feed(&self) {
+        *self.last_fed.lock().unwrap() = Instant::now();
+    }
+}
+
+/// A deadman switch that brings every output to its safe state if it isn't fed in time
+pub struct Watchdog {
+    /// How long the watchdog waits without being fed before tripping
+    timeout: Duration,
+    /// When the watchdog was last fed
+    last_fed: Arc<Mutex<Instant>>,
+    /// Stopped when the watchdog trips, so a running executor halts too
+    stop: Option<StopSignal>,
+    /// Whether the watchdog has tripped since it was last fed
+    tripped: bool,
+}
+
+impl Watchdog {
+    /// Creates a watchdog that trips if it isn't fed for `timeout`, starting fed
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_fed: Arc::new(Mutex::new(Instant::now())),
+            stop: None,
+            tripped: false,
+        }
+    }
+
+    /// Stops an executor's signal whenever the watchdog trips
+    pub fn with_stop_signal(mut self, stop: StopSignal) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// A handle to feed the watchdog with
+    pub fn feeder(&self) -> Feeder {
+        Feeder {
+            last_fed: self.last_fed.clone(),
+        }
+    }
+
+    /// Enforces on-time limits and trips if the watchdog is starving. Tripping stops the stop
+    /// signal and writes every safe state, once until the watchdog is fed again. Returns true if
+    /// the watchdog tripped on this check
+    pub fn check(&mut self, body: &mut Body) -> Result<bool, PeripheralError> {
+        let tripped = self.trip_if_starving();
+        self.settle(body, tripped)?;
+        Ok(tripped)
+    }
+
+    /// Checks the body every `period` on a new thread until the body is dropped. A trip stops the
+    /// stop signal before waiting on the body, so an executor holding it between writes halts and
+    /// lets go
+    pub fn spawn(mut self, body: &SharedBody, period: Duration) -> JoinHandle<()> {
+        let body = Arc::downgrade(body);
+        std::thread::spawn(move || {
+            while let Some(body) = body.upgrade() {
+                let tripped = self.trip_if_starving();
+                if let Ok(mut body) = body.lock() {
+                    // There's nobody to report to here, so failures are retried next period
+                    let _ = self.settle(&mut body, tripped);
+                }
+                drop(body);
+                std::thread::sleep(period);
+            }
+        })
+    }
+
+    /// Trips if the watchdog is starving and hasn't tripped since it was last fed, stopping the
+    /// stop signal. Returns true if it tripped
+    fn trip_if_starving(&mut self) -> bool {
+        let starving = self.last_fed.lock().unwrap().elapsed() > self.timeout;
+        if !starving {
+            self.tripped = false;
+            return false;
+        }
+        if self.tripped {
+            return false;
+        }
+
+        self.tripped = true;
+        if let Some(stop) = &self.stop {
+            stop.stop();
+        }
+        true
+    }
+
+    /// Writes every safe state after a trip, or enforces on-time limits otherwise. A trip whose
+    /// safe states fail to write is retried on the next check
+    fn settle(&mut self, body: &mut Body, tripped: bool) -> Result<(), PeripheralError> {
+        if !tripped {
+            return body.enforce_limits();
+        }
+
+        body.make_safe().inspect_err(|_| self.tripped = false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        body::{Body, Peripheral, PeripheralError},
+        brain::{
+            executor::{Executor, Outcome, Timeline},
+            instruction::Instruction,
+        },
+    };
+
+    use super::{Encoding, SafetyLimits, Watchdog};
+
+    #[test]
+    fn out_of_range_writes_are_rejected_or_clamped() {
+        let (pwm, log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new().with_range(Encoding::DutyCycle, 0.0, 0.5);
+        let mut body = Body::builder()
+            .with_limited_node(pwm, limits.clone())
+            .build();
+        let node = body.get_by_id_mut(body.root[0]).unwrap();
+
+        assert!(matches!(
+            node.write(&1.0f64.to_ne_bytes()),
+            Err(PeripheralError::SafetyLimit(_))
+        ));
+        assert!(log.is_empty());
+
+        node.limits = Some(limits.clamping());
+        node.write(&1.0f64.to_ne_bytes()).unwrap();
+        assert_eq!(log.last(), Some(0.5f64.to_ne_bytes().to_vec()));
+    }
+
+    #[test]
+    fn fast_writes_are_rate_limited() {
+        let (pin, _log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new().with_min_write_interval(Duration::from_secs(60));
+        let mut body = Body::builder().with_limited_node(pin, limits).build();
+        let node = body.get_by_id_mut(body.root[0]).unwrap();
+
+        node.write(&[1]).unwrap();
+        assert!(node.write(&[0]).is_err());
+    }
+
+    #[test]
+    fn safe_state_writes_skip_the_rate_limit() {
+        let (pin, log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new()
+            .with_min_write_interval(Duration::from_secs(60))
+            .with_safe_state([0]);
+        let mut body = Body::builder().with_limited_node(pin, limits).build();
+        let node = body.get_by_id_mut(body.root[0]).unwrap();
+
+        node.write(&[1]).unwrap();
+        node.write(&[0]).unwrap();
+        assert_eq!(log.last(), Some(vec![0]));
+        assert!(node.write(&[1]).is_err());
+    }
+
+    #[test]
+    fn starving_watchdog_makes_outputs_safe() {
+        let (motor, log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new().with_safe_state([0, 0, 0, 0]);
+        let mut body = Body::builder().with_limited_node(motor, limits).build();
+        let key = body.root[0];
+        body.get_by_id_mut(key)
+            .unwrap()
+            .write(&[1, 0, 0, 0])
+            .unwrap();
+
+        let mut watchdog = Watchdog::new(Duration::from_millis(10));
+        let feeder = watchdog.feeder();
+        assert!(!watchdog.check(&mut body).unwrap());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(watchdog.check(&mut body).unwrap());
+        assert_eq!(log.last(), Some(vec![0, 0, 0, 0]));
+
+        // Tripping only happens once until the watchdog is fed again
+        assert!(!watchdog.check(&mut body).unwrap());
+        feeder.feed();
+        assert!(!watchdog.check(&mut body).unwrap());
+    }
+
+    #[test]
+    fn spawned_watchdog_halts_a_running_plan() {
+        let (motor, log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new().with_safe_state([0, 0, 0, 0]);
+        let body = Body::builder().with_limited_node(motor, limits).build();
+        let motor = body.root[0];
+        let body = Arc::new(Mutex::new(body));
+
+        let executor = Executor::new();
+        let _watchdog = Watchdog::new(Duration::from_millis(20))
+            .with_stop_signal(executor.stop_signal())
+            .spawn(&body, Duration::from_millis(5));
+
+        let timeline = Timeline::sequential([Instruction::new(motor, 5_000, [1, 0, 0, 0])]);
+        let started = Instant::now();
+        let run = executor.run(&body, &timeline).unwrap();
+
+        assert_eq!(run.outcome, Outcome::Stopped(1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(log.last(), Some(vec![0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,352 @@
+//! Polling a Body's inputs at their own rates and assembling the readings into fixed-width points
+//! for the data buffer
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::brain::buffer::DataBuffer;
+
+use super::{metadata::ChannelSchema, Body, PeripheralError, PeripheralKey};
+
+/// How often a channel is sampled when no period is configured for it
+pub const DEFAULT_PERIOD: Duration = Duration::from_millis(100);
+
+/// A single timestamped sample from an input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    /// The node that was read
+    pub node: PeripheralKey,
+    /// The node's column in the schema
+    pub column: usize,
+    /// When the sample was taken, from when the sampler was created
+    pub at: Duration,
+    /// The sample in its node's unit
+    pub value: f32,
+}
+
+/// A data point with one value per channel, in column order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    /// When the last of the point's readings was taken, from when the sampler was created
+    pub at: Duration,
+    /// The channel values in column order
+    pub values: Vec<f32>,
+}
+
+/// Anything that assembles points from a body's inputs for the data buffer
+pub trait PointSource {
+    /// Reads the body until a point is assembled, or gives up with `None` once `deadline` passes
+    fn next_point(&mut self, body: &Mutex<Body>, deadline: Instant) -> Option<Point>;
+}
+
+/// How sampling a single channel has gone
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Samples read
+    pub read: u64,
+    /// Samples replaced by a newer one before making it into a point
+    pub overwritten: u64,
+    /// Samples skipped because the sampler was polled too late to take them
+    pub missed: u64,
+    /// Reads that failed
+    pub failed: u64,
+}
+
+impl ChannelStats {
+    /// Every sample that never made it into a point
+    pub fn dropped(&self) -> u64 {
+        self.overwritten + self.missed + self.failed
+    }
+}
+
+/// The sampling state of a single channel
+struct ChannelState {
+    /// The input feeding this channel
+    node: PeripheralKey,
+    /// Time between samples
+    period: Duration,
+    /// When the next sample is due
+    next_due: Instant,
+    /// The newest sample not yet in a point
+    latest: Option<Reading>,
+    /// How sampling has gone
+    stats: ChannelStats,
+}
+
+/// Reads each channel of a schema at its own rate. A point is assembled once every channel has a
+/// fresh sample, so points arrive at the rate of the slowest channel and faster channels
+/// contribute their newest sample
+pub struct Sampler {
+    /// When sampling started
+    started: Instant,
+    /// Every channel in column order
+    channels: Vec<ChannelState>,
+    /// A point that didn't fit the last buffer filled
+    pending: Option<Point>,
+}
+
+impl Sampler {
+    /// Creates a sampler over every channel of a schema, each sampled every `DEFAULT_PERIOD`
+    pub fn new(schema: &ChannelSchema) -> Self {
+        let started = Instant::now();
+        Self {
+            started,
+            channels: schema
+                .channels
+                .iter()
+                .map(|channel| ChannelState {
+                    node: channel.node,
+                    period: DEFAULT_PERIOD,
+                    next_due: started,
+                    latest: None,
+                    stats: ChannelStats::default(),
+                })
+                .collect(),
+            pending: None,
+        }
+    }
+
+    /// Sets how often a node is sampled
+    pub fn with_period(mut self, node: PeripheralKey, period: Duration) -> Self {
+        for channel in self.channels.iter_mut().filter(|c| c.node == node) {
+            channel.period = period;
+        }
+        self
+    }
+
+    /// Sets how many times a second a node is sampled. `None` unless the rate is finite and
+    /// positive
+    pub fn with_rate(self, node: PeripheralKey, hertz: f64) -> Option<Self> {
+        if !(hertz.is_finite() && hertz > 0.0) {
+            return None;
+        }
+        let period = Duration::try_from_secs_f64(1.0 / hertz).ok()?;
+        Some(self.with_period(node, period))
+    }
+
+    /// How sampling each channel has gone, in column order
+    pub fn stats(&self) -> Vec<ChannelStats> {
+        self.channels.iter().map(|channel| channel.stats).collect()
+    }
+
+    /// When the next channel is due to be sampled
+    pub fn next_due(&self) -> Option<Instant> {
+        self.channels.iter().map(|channel| channel.next_due).min()
+    }
+
+    /// How many channels are sampled
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// How long it's been since sampling started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Samples every channel that's due, returning each sample read. Failed reads are counted in
+    /// the stats rather than returned, so one bad sensor doesn't stop the others
+    pub fn read_due(&mut self, body: &mut Body) -> Vec<Reading> {
+        let now = Instant::now();
+
+        let mut readings = vec![];
+        for (column, channel) in self.channels.iter_mut().enumerate() {
+            if now < channel.next_due {
+                continue;
+            }
+
+            let late = now - channel.next_due;
+            let missed = (late.as_nanos() / channel.period.as_nanos().max(1)) as u32;
+            channel.stats.missed += missed as u64;
+            channel.next_due += channel.period * (missed + 1);
+
+            let sample = body
+                .get_by_id_mut(channel.node)
+                .ok_or(PeripheralError::MissingNode)
+                .and_then(|node| node.read_sample());
+            match sample {
+                Ok(value) => {
+                    channel.stats.read += 1;
+                    readings.push(Reading {
+                        node: channel.node,
+                        column,
+                        at: now - self.started,
+                        value,
+                    });
+                }
+                Err(_) => channel.stats.failed += 1,
+            }
+        }
+
+        readings
+    }
+
+    /// Samples every channel that's due and returns a point if every channel now has a fresh
+    /// sample
+    pub fn poll(&mut self, body: &mut Body) -> Option<Point> {
+        for reading in self.read_due(body) {
+            let channel = &mut self.channels[reading.column];
+            if channel.latest.is_some() {
+                channel.stats.overwritten += 1;
+            }
+            channel.latest = Some(reading);
+        }
+
+        if self.channels.is_empty() || self.channels.iter().any(|c| c.latest.is_none()) {
+            return None;
+        }
+
+        let readings: Vec<_> = self
+            .channels
+            .iter_mut()
+            .filter_map(|channel| channel.latest.take())
+            .collect();
+        Some(Point {
+            at: readings.iter().map(|reading| reading.at).max()?,
+            values: readings.iter().map(|reading| reading.value).collect(),
+        })
+    }
+
+    /// Sleeps until the next channel is due, or until `deadline` if that's sooner. Returns false
+    /// without sleeping once `deadline` has passed
+    pub fn wait_for_due(&self, deadline: Instant) -> bool {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+
+        let due = self.next_due().unwrap_or(deadline).min(deadline);
+        std::thread::sleep(due.saturating_duration_since(now));
+        true
+    }
+
+    /// Keeps a point to be returned by the next `next_point`, such as one a full buffer refused
+    pub fn put_back(&mut self, point: Point) {
+        self.pending = Some(point);
+    }
+
+    /// Samples until the buffer is full, returning false if `deadline` passed first. A point the
+    /// buffer rejected is kept for the next buffer
+    pub fn fill<const BUFFER_SIZE: usize>(
+        &mut self,
+        body: &Mutex<Body>,
+        buffer: &mut DataBuffer<BUFFER_SIZE>,
+        deadline: Instant,
+    ) -> bool {
+        while !buffer.is_full() {
+            let Some(point) = self.next_point(body, deadline) else {
+                return false;
+            };
+            if buffer.add_data(&point.values).is_none() {
+                self.put_back(point);
+                return true;
+            }
+        }
+
+        true
+    }
+}
+
+impl PointSource for Sampler {
+    /// Samples until a point is assembled, sleeping between samples, or gives up with `None` once
+    /// `deadline` passes, such as when a channel keeps failing. The body is only locked while it's
+    /// being polled
+    fn next_point(&mut self, body: &Mutex<Body>, deadline: Instant) -> Option<Point> {
+        if let Some(point) = self.pending.take() {
+            return Some(point);
+        }
+        if self.channels.is_empty() {
+            return None;
+        }
+
+        loop {
+            if let Some(point) = self.poll(&mut body.lock().unwrap()) {
+                return Some(point);
+            }
+            if !self.wait_for_due(deadline) {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        body::{metadata::Metadata, Body, Peripheral, PeripheralNode},
+        brain::buffer::DataBuffer,
+    };
+
+    use super::Sampler;
+
+    #[test]
+    fn points_wait_for_the_slowest_channel() {
+        let fast = PeripheralNode::from(Peripheral::constant_input(1.0))
+            .with_metadata(Metadata::new("fast").with_column(0));
+        let slow = PeripheralNode::from(Peripheral::constant_input(2.0))
+            .with_metadata(Metadata::new("slow").with_column(1));
+        let mut body = Body::builder().with_node(fast).with_node(slow).build();
+        let (fast, slow) = (body.root[0], body.root[1]);
+
+        let mut sampler = Sampler::new(&body.channel_schema().unwrap())
+            .with_period(fast, Duration::from_millis(5))
+            .with_period(slow, Duration::from_millis(30));
+
+        let first = sampler.poll(&mut body).unwrap();
+        assert_eq!(first.values, vec![1.0, 2.0]);
+
+        let mut second = None;
+        while second.is_none() {
+            std::thread::sleep(Duration::from_millis(5));
+            second = sampler.poll(&mut body);
+        }
+
+        let stats = sampler.stats();
+        assert!(stats[0].read > stats[1].read);
+        assert!(stats[0].dropped() > 0);
+        assert_eq!(stats[1].dropped(), 0);
+    }
+
+    #[test]
+    fn failing_channels_give_up_at_the_deadline() {
+        let light = PeripheralNode::from(Peripheral::constant_input(1.0))
+            .with_metadata(Metadata::new("light").with_column(0));
+        let broken = PeripheralNode::from(Peripheral::recording_output().0)
+            .with_metadata(Metadata::new("broken").with_column(1));
+        let body = Body::builder().with_node(light).with_node(broken).build();
+        let mut sampler = Sampler::new(&body.channel_schema().unwrap());
+        let body = Mutex::new(body);
+
+        let mut buffer = DataBuffer::<8>::default().with_dims(2);
+        let deadline = Instant::now() + Duration::from_millis(30);
+        assert!(!sampler.fill(&body, &mut buffer, deadline));
+        assert!(Instant::now() >= deadline);
+        assert!(buffer.is_empty());
+        assert!(sampler.stats()[1].failed > 0);
+    }
+
+    #[test]
+    fn rates_must_be_positive() {
+        let body = Body::builder()
+            .with_node(
+                PeripheralNode::from(Peripheral::constant_input(1.0))
+                    .with_metadata(Metadata::new("light").with_column(0)),
+            )
+            .build();
+        let light = body.root[0];
+        let schema = body.channel_schema().unwrap();
+
+        for hertz in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            assert!(Sampler::new(&schema).with_rate(light, hertz).is_none());
+        }
+        let sampler = Sampler::new(&schema).with_rate(light, 20.0).unwrap();
+        assert_eq!(sampler.channels[0].period, Duration::from_millis(50));
+    }
+}
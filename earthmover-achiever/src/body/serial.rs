@@ -0,0 +1,191 @@
+//! A generic serial port for any Linux termios tty, such as USB serial adapters on x86 or the
+//! Jetson's UARTs
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    time::Duration,
+};
+
+use nix::{
+    fcntl::OFlag,
+    sys::termios::{self, BaudRate, ControlFlags, FlushArg, SetArg, SpecialCharacterIndices},
+};
+
+use super::PeripheralError;
+
+/// Parity checking for a serial port
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit
+    #[default]
+    None,
+    /// Even parity
+    Even,
+    /// Odd parity
+    Odd,
+}
+
+/// How a serial port should be configured. Data bits are always 8
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// The baud rate in bits per second
+    pub baud_rate: u32,
+    /// Parity checking
+    pub parity: Parity,
+    /// Use two stop bits instead of one
+    pub two_stop_bits: bool,
+    /// How long a read may wait for the next byte before failing. `None` blocks forever. The tty
+    /// counts in tenths of a second, so this is truncated to a tenth and kept within 0.1s..=25.5s
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            parity: Parity::None,
+            two_stop_bits: false,
+            timeout: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl SerialConfig {
+    /// Creates a config for a baud rate with no parity, one stop bit and a one second timeout
+    pub fn with_baud_rate(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the parity
+    pub fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Sets whether two stop bits are used
+    pub fn with_two_stop_bits(mut self, two_stop_bits: bool) -> Self {
+        self.two_stop_bits = two_stop_bits;
+        self
+    }
+
+    /// Sets the read timeout
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Maps a baud rate in bits per second to its termios constant
+fn baud_rate(baud: u32) -> Option<BaudRate> {
+    let rate = match baud {
+        50 => BaudRate::B50,
+        75 => BaudRate::B75,
+        110 => BaudRate::B110,
+        134 => BaudRate::B134,
+        150 => BaudRate::B150,
+        200 => BaudRate::B200,
+        300 => BaudRate::B300,
+        600 => BaudRate::B600,
+        1_200 => BaudRate::B1200,
+        1_800 => BaudRate::B1800,
+        2_400 => BaudRate::B2400,
+        4_800 => BaudRate::B4800,
+        9_600 => BaudRate::B9600,
+        19_200 => BaudRate::B19200,
+        38_400 => BaudRate::B38400,
+        57_600 => BaudRate::B57600,
+        115_200 => BaudRate::B115200,
+        230_400 => BaudRate::B230400,
+        460_800 => BaudRate::B460800,
+        500_000 => BaudRate::B500000,
+        576_000 => BaudRate::B576000,
+        921_600 => BaudRate::B921600,
+        1_000_000 => BaudRate::B1000000,
+        1_152_000 => BaudRate::B1152000,
+        1_500_000 => BaudRate::B1500000,
+        2_000_000 => BaudRate::B2000000,
+        _ => return None,
+    };
+
+    Some(rate)
+}
+
+/// A configured serial port on a termios tty
+pub struct SerialPort {
+    /// The open tty
+    file: File,
+}
+
+impl SerialPort {
+    /// Opens and configures the tty at `path`, such as `/dev/ttyUSB0` or `/dev/ttyTHS1`
+    pub fn open(path: impl AsRef<Path>, config: SerialConfig) -> Result<Self, PeripheralError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(OFlag::O_NOCTTY.bits())
+            .open(path)?;
+
+        Self::from_file(file, config)
+    }
+
+    /// Configures an already opened tty
+    pub fn from_file(file: File, config: SerialConfig) -> Result<Self, PeripheralError> {
+        let mut tio = termios::tcgetattr(&file)?;
+        termios::cfmakeraw(&mut tio);
+        termios::cfsetspeed(
+            &mut tio,
+            baud_rate(config.baud_rate).ok_or(nix::Error::EINVAL)?,
+        )?;
+
+        tio.control_flags |= ControlFlags::CLOCAL | ControlFlags::CREAD;
+        tio.control_flags
+            .remove(ControlFlags::PARENB | ControlFlags::PARODD);
+        match config.parity {
+            Parity::None => {}
+            Parity::Even => tio.control_flags.insert(ControlFlags::PARENB),
+            Parity::Odd => tio
+                .control_flags
+                .insert(ControlFlags::PARENB | ControlFlags::PARODD),
+        }
+        tio.control_flags
+            .set(ControlFlags::CSTOPB, config.two_stop_bits);
+
+        let (vmin, vtime) = match config.timeout {
+            None => (1, 0),
+            Some(timeout) => (0, (timeout.as_millis() / 100).clamp(1, 255) as u8),
+        };
+        tio.control_chars[SpecialCharacterIndices::VMIN as usize] = vmin;
+        tio.control_chars[SpecialCharacterIndices::VTIME as usize] = vtime;
+
+        termios::tcsetattr(&file, SetArg::TCSANOW, &tio)?;
+        termios::tcflush(&file, FlushArg::TCIOFLUSH)?;
+
+        Ok(Self { file })
+    }
+
+    /// Reads until `buf` is full, failing if the timeout passes between bytes
+    pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), PeripheralError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.file.read(&mut buf[filled..])? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into()),
+                read => filled += read,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes all of `bytes` and waits until they have been transmitted
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<(), PeripheralError> {
+        self.file.write_all(bytes)?;
+        termios::tcdrain(&self.file)?;
+        Ok(())
+    }
+}
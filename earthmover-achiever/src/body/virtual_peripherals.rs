@@ -0,0 +1,66 @@
+//! Extensions of the Peripheral Enum to construct virtual Peripherals that need no hardware
+
+use std::{path::Path, time::Duration};
+
+use super::{
+    inputs::virtual_inputs::ScriptedInput,
+    outputs::virtual_outputs::{RecordingOutput, WriteLog},
+    Peripheral,
+};
+
+impl Peripheral {
+    /// Creates a Peripheral Input Node that always reads the same value
+    pub fn constant_input(value: f32) -> Self {
+        Self::Input(Box::new(ScriptedInput::constant(value)))
+    }
+
+    /// Creates a Peripheral Input Node that reads a sine wave over time
+    pub fn sine_input(amplitude: f32, frequency: f32, offset: f32) -> Self {
+        Self::Input(Box::new(ScriptedInput::sine(amplitude, frequency, offset)))
+    }
+
+    /// Creates a Peripheral Input Node that replays a column of a CSV file
+    pub fn csv_input(path: impl AsRef<Path>, column: usize) -> std::io::Result<Self> {
+        Ok(Self::Input(Box::new(ScriptedInput::from_csv(
+            path, column,
+        )?)))
+    }
+
+    /// Creates a Peripheral Input Node whose readings come from a closure of elapsed time
+    pub fn fn_input(script: impl FnMut(Duration) -> f32 + Send + 'static) -> Self {
+        Self::Input(Box::new(ScriptedInput::from_fn(script)))
+    }
+
+    /// Creates a Peripheral Output Node that records every write, alongside a handle to its log
+    pub fn recording_output() -> (Self, WriteLog) {
+        let output = RecordingOutput::new();
+        let log = output.log();
+
+        (Self::Output(Box::new(output)), log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::{Body, Peripheral};
+
+    #[test]
+    fn virtual_body_reads_inputs_and_records_outputs() {
+        let (output, log) = Peripheral::recording_output();
+        let mut body = Body::builder()
+            .with_node(Peripheral::constant_input(1.5))
+            .with_node(output)
+            .build();
+
+        let mut buf = [0u8; 4];
+        for (_, node) in body.peripheral_graph.iter_mut() {
+            match &mut node.peripheral {
+                Peripheral::Input(input) => input.read_input(&mut buf).expect("Read input"),
+                Peripheral::Output(output) => output.write(&[42]).expect("Write output"),
+            }
+        }
+
+        assert_eq!(f32::from_be_bytes(buf), 1.5);
+        assert_eq!(log.last(), Some(vec![42]));
+    }
+}
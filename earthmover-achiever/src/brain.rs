@@ -2,6 +2,13 @@
 
 pub mod agent;
 pub mod buffer;
+pub mod executor;
+pub mod feedback;
+pub mod fusion;
+pub mod history;
 pub mod instruction;
+pub mod journal;
+pub mod pareto;
+pub mod validate;
 
 pub use agent::AgentSession;
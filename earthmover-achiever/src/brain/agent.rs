@@ -1,13 +1,21 @@
 //! An Agent's behavior, session states, and builder
 
-use std::{error::Error, marker::PhantomData, time::Duration};
+use std::{error::Error, marker::PhantomData, time::Instant};
 
 use crate::{
-    body::{Body, Peripheral},
+    body::{sampling::PointSource, PeripheralError, SharedBody},
     goals::Rewardable,
 };
 
-use super::{buffer::DataBuffer, instruction::Instruction};
+use super::{
+    buffer::DataBuffer,
+    executor::{Executor, Run},
+    feedback::ExecutionFeedback,
+    fusion::SPATIAL_DIMS,
+    history::{Convergence, ConvergenceDetector, RewardHistory},
+    instruction::Plan,
+    journal::Journal,
+};
 
 /// TypeState for a newly untrained session
 pub struct Untrained;
@@ -22,72 +30,219 @@ pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 /// we're using. After sufficient data from the environment has been collected, the goal, agent
 /// body, and data buf will all be sent to the remote simulation engine to before parralellized RL
 /// The response will then be a finished AgentSession that can be attempted to run in the field!
-pub struct AgentSession<'agent, REWARD: Rewardable, STATE, const BUFFER_SIZE: usize> {
+pub struct AgentSession<REWARD: Rewardable, STATE, const BUFFER_SIZE: usize> {
     /// The goal of the agent
     goal: REWARD,
-    /// A reference to the agent's hardware
-    body: &'agent mut Body,
+    /// The agent's hardware, shared with anything that must reach it while the agent is busy
+    body: SharedBody,
     /// The data collection buffer
     buffer: DataBuffer<BUFFER_SIZE>,
-    /// Instruction sets on completed training
-    directions: Option<Vec<Instruction>>,
+    /// Where collected points are logged to disk, if anywhere
+    journal: Option<Journal>,
+    /// The reward after each observed reading
+    history: RewardHistory,
+    /// Decides when the reward history has converged
+    convergence: ConvergenceDetector,
+    /// The plan from the last completed training
+    plan: Option<Plan>,
     /// PhantomData for state :)
     _spooky_ghost: PhantomData<STATE>,
 }
 
-impl<'agent, REWARD: Rewardable, const BUFFER_SIZE: usize>
-    AgentSession<'agent, REWARD, Untrained, BUFFER_SIZE>
-{
+impl<REWARD: Rewardable, const BUFFER_SIZE: usize> AgentSession<REWARD, Untrained, BUFFER_SIZE> {
     /// Creates a new builder for an agent's session
-    pub fn builder() -> Builder<'agent, REWARD, BUFFER_SIZE> {
+    pub fn builder() -> Builder<REWARD, BUFFER_SIZE> {
         Builder::default()
     }
 }
 
-impl<REWARD: Rewardable, STATE, const BUFFER_SIZE: usize>
-    AgentSession<'_, REWARD, STATE, BUFFER_SIZE>
-{
+impl<REWARD: Rewardable, STATE, const BUFFER_SIZE: usize> AgentSession<REWARD, STATE, BUFFER_SIZE> {
     /// Gets the current reward of the agent session
     pub fn get_reward(&self) -> f64 {
         self.goal.to_reward()
     }
 
-    /// Returns a reference to the agent's hardware
-    pub fn get_body(&self) -> &Body {
-        self.body
+    /// Feeds a live reading into the agent's reward and records the resulting reward in its
+    /// history
+    pub fn observe(&mut self, reading: &[f32]) -> f64 {
+        let reading = reading.iter().map(|&val| val as f64).collect::<Vec<_>>();
+        self.goal.observe(&reading);
+
+        let reward = self.goal.to_reward();
+        self.history.push(reward);
+        reward
+    }
+
+    /// The reward after each observed reading, oldest first
+    pub fn history(&self) -> &RewardHistory {
+        &self.history
+    }
+
+    /// Whether the reward history has converged, and why
+    pub fn convergence(&self) -> Option<Convergence> {
+        self.convergence.check(&self.history)
+    }
+
+    /// Forgets the reward history, such as after retraining
+    pub fn reset_history(&mut self) {
+        self.history.clear()
+    }
+
+    /// Returns the agent's hardware
+    pub fn get_body(&self) -> &SharedBody {
+        &self.body
+    }
+
+    /// Returns the agent's on-disk log of collected points, if it has one
+    pub fn journal_mut(&mut self) -> Option<&mut Journal> {
+        self.journal.as_mut()
+    }
+
+    /// Reads every channel of the body's schema once, in column order
+    pub fn read_channels(&self) -> Result<Vec<f32>> {
+        let mut body = self.body.lock().unwrap();
+        let schema = body.channel_schema()?;
+
+        let mut readings = Vec::with_capacity(schema.dims());
+        for channel in &schema.channels {
+            let node = body
+                .get_by_id_mut(channel.node)
+                .ok_or(PeripheralError::MissingNode)?;
+            readings.push(node.read_sample()?);
+        }
+        Ok(readings)
+    }
+
+    /// Moves the session into another state, carrying everything but its plan over
+    fn into_state<NEXT>(self, plan: Option<Plan>) -> AgentSession<REWARD, NEXT, BUFFER_SIZE> {
+        AgentSession {
+            goal: self.goal,
+            body: self.body,
+            buffer: self.buffer,
+            journal: self.journal,
+            history: self.history,
+            convergence: self.convergence,
+            plan,
+            _spooky_ghost: PhantomData,
+        }
     }
 }
 
-impl<REWARD: Rewardable, const BUFFER_SIZE: usize>
-    AgentSession<'_, REWARD, Untrained, BUFFER_SIZE>
-{
+impl<REWARD: Rewardable, const BUFFER_SIZE: usize> AgentSession<REWARD, Untrained, BUFFER_SIZE> {
+    /// Takes on a freshly trained plan, ready to be performed
+    pub fn review(self, plan: Plan) -> AgentSession<REWARD, InReview, BUFFER_SIZE> {
+        self.into_state(Some(plan))
+    }
+
+    /// Reads points from the body's inputs, recording every one, until the buffer is full. Returns
+    /// false if `deadline` passed first. Fails if a point isn't as wide as the buffer's points
+    pub fn collect(
+        &mut self,
+        points: &mut impl PointSource,
+        deadline: Instant,
+    ) -> std::io::Result<bool> {
+        while !self.buffer.is_full() {
+            let Some(point) = points.next_point(&self.body, deadline) else {
+                return Ok(false);
+            };
+            if self.record(&point.values)?.is_none() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "A point of {} values doesn't fit a buffer of {} dimensional points",
+                        point.values.len(),
+                        self.buffer.dims()
+                    ),
+                ));
+            }
+        }
+
+        Ok(true)
+    }
+
     /// Adds a slice of data to the buffer, if that slice is too large `None` is returned
     pub fn add_data(&mut self, buf: &[f32]) -> Option<()> {
         self.buffer.add_data(buf)
     }
 
+    /// Appends a point to the journal, if there is one, observes it, then adds it to the buffer.
+    /// The point is kept on disk even if the buffer refuses it
+    pub fn record(&mut self, point: &[f32]) -> std::io::Result<Option<()>> {
+        if let Some(journal) = &mut self.journal {
+            journal.append(point)?;
+        }
+        self.observe(point);
+        Ok(self.buffer.add_data(point))
+    }
+
+    /// Empties the buffer, returning everything it held
     pub fn export(&mut self) -> Vec<f32> {
-        let res = self.buffer.export();
-        self.buffer = DataBuffer::default();
+        self.buffer.drain()
+    }
 
-        res
+    /// Empties the buffer in chunks of up to `points` points each, for uploading a piece at a time
+    pub fn export_chunks(&mut self, points: usize) -> impl Iterator<Item = Vec<f32>> + '_ {
+        self.buffer.drain_chunks(points)
     }
 }
 
-impl<REWARD: Rewardable, const BUFFER_SIZE: usize> AgentSession<'_, REWARD, InReview, BUFFER_SIZE> {
-    /// Gets the directions of a newly trained Agent
-    pub fn act(&mut self) -> Result<()> {
-        if let Some(instructions) = &self.directions {
-            for instruction in instructions {
-                if let Some(node) = self.body.get_by_id_mut(instruction.node) {
-                    if let Peripheral::Output(output) = &mut node.peripheral {
-                        output.write(&instruction.instructions)?
-                    }
-                }
-                std::thread::sleep(Duration::from_millis(instruction.lasts_for_ms as u64))
-            }
-        }
-        Ok(())
+impl<REWARD: Rewardable, const BUFFER_SIZE: usize> AgentSession<REWARD, InReview, BUFFER_SIZE> {
+    /// Performs the directions of a newly trained Agent one after another on an executor, which
+    /// decides whether they're validated first, whether failed writes halt the plan, and can stop
+    /// it mid-way
+    pub fn act(&mut self, executor: &Executor) -> Result<Run> {
+        let directions = self
+            .plan
+            .as_ref()
+            .map(|plan| plan.instructions.as_slice())
+            .unwrap_or_default();
+        Ok(executor.perform(&self.body, directions)?)
+    }
+
+    /// Performs the directions of a newly trained Agent like `act`, waiting between instructions
+    /// on the runtime so network IO carries on while the body moves, even on a single thread
+    pub async fn act_async(&mut self, executor: &Executor) -> Result<Run> {
+        let directions = self
+            .plan
+            .as_ref()
+            .map(|plan| plan.instructions.as_slice())
+            .unwrap_or_default();
+        Ok(executor.perform_async(&self.body, directions).await?)
+    }
+
+    /// A run recording every instruction of this agent's plan as failed, for a plan `act` couldn't
+    /// perform, so the hivemind still hears how it went
+    pub fn unperformed(&self, reason: &dyn Error) -> Run {
+        let directions = self
+            .plan
+            .as_ref()
+            .map(|plan| plan.instructions.as_slice())
+            .unwrap_or_default();
+        Run::unperformed(directions, &reason.to_string())
+    }
+
+    /// Feedback on a run of this agent's plan. Every channel is read once the run is over and
+    /// laid out after `position` like a collected point, then observed, so the feedback carries
+    /// the readings and the reward they give
+    pub fn report(
+        &mut self,
+        run: &Run,
+        position: [f32; SPATIAL_DIMS],
+    ) -> Result<ExecutionFeedback> {
+        let mut readings = position.to_vec();
+        readings.extend(self.read_channels()?);
+        let reward = self.observe(&readings);
+
+        Ok(run
+            .feedback()
+            .with_plan(self.plan.as_ref().map_or(0, |plan| plan.id))
+            .with_readings(readings)
+            .with_reward(reward))
+    }
+
+    /// Sets the plan aside to collect data and train again
+    pub fn retrain(self) -> AgentSession<REWARD, Untrained, BUFFER_SIZE> {
+        self.into_state(None)
     }
 }
 
@@ -95,23 +250,35 @@ impl<REWARD: Rewardable, const BUFFER_SIZE: usize> AgentSession<'_, REWARD, InRe
 /// AgentSession will always be untrained. Only when receiving a new Agent back from the simulation
 /// server will we receive an agent tagged as Trained. Untrained agents do not have access to the
 /// directions bytes, preventing them from being runnable
-pub struct Builder<'agent, REWARD: Rewardable, const BUFFER_SIZE: usize> {
+pub struct Builder<REWARD: Rewardable, const BUFFER_SIZE: usize> {
     /// The goal
     goal: Option<REWARD>,
-    /// A reference to the agent's hardware
-    body: Option<&'agent mut Body>,
+    /// The agent's hardware
+    body: Option<SharedBody>,
+    /// The data collection buffer
+    buffer: DataBuffer<BUFFER_SIZE>,
+    /// Where collected points are logged to disk
+    journal: Option<Journal>,
+    /// The reward history
+    history: RewardHistory,
+    /// Decides when the reward history has converged
+    convergence: ConvergenceDetector,
 }
 
-impl<REWARD: Rewardable, const BUFFER_SIZE: usize> Default for Builder<'_, REWARD, BUFFER_SIZE> {
+impl<REWARD: Rewardable, const BUFFER_SIZE: usize> Default for Builder<REWARD, BUFFER_SIZE> {
     fn default() -> Self {
         Self {
             goal: None,
             body: None,
+            buffer: DataBuffer::default(),
+            journal: None,
+            history: RewardHistory::default(),
+            convergence: ConvergenceDetector::default(),
         }
     }
 }
 
-impl<'agent, REWARD: Rewardable, const BUFFER_SIZE: usize> Builder<'agent, REWARD, BUFFER_SIZE> {
+impl<REWARD: Rewardable, const BUFFER_SIZE: usize> Builder<REWARD, BUFFER_SIZE> {
     /// Set an agent's goal
     pub fn with_goal(mut self, goal: REWARD) -> Self {
         self.goal = Some(goal);
@@ -119,22 +286,205 @@ impl<'agent, REWARD: Rewardable, const BUFFER_SIZE: usize> Builder<'agent, REWAR
     }
 
     /// Set an agent's body
-    pub fn with_body(mut self, body: &'agent mut Body) -> Self {
+    pub fn with_body(mut self, body: SharedBody) -> Self {
         self.body = Some(body);
         self
     }
 
+    /// Set how an agent buffers collected data
+    pub fn with_buffer(mut self, buffer: DataBuffer<BUFFER_SIZE>) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    /// Set where an agent logs collected points to disk
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Set how many rewards an agent remembers
+    pub fn with_history(mut self, history: RewardHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Set when an agent's reward counts as converged
+    pub fn with_convergence(mut self, convergence: ConvergenceDetector) -> Self {
+        self.convergence = convergence;
+        self
+    }
+
     /// Build a fully configured `AgentSession`
-    pub fn build(self) -> Option<AgentSession<'agent, REWARD, Untrained, BUFFER_SIZE>> {
+    pub fn build(self) -> Option<AgentSession<REWARD, Untrained, BUFFER_SIZE>> {
         match (self.goal, self.body) {
             (Some(goal), Some(body)) => Some(AgentSession {
                 goal,
                 body,
-                buffer: DataBuffer::default(),
-                directions: None,
+                buffer: self.buffer,
+                journal: self.journal,
+                history: self.history,
+                convergence: self.convergence,
+                plan: None,
                 _spooky_ghost: PhantomData,
             }),
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        marker::PhantomData,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        body::{metadata::Metadata, sampling::Sampler, Body, Peripheral, PeripheralNode},
+        brain::{
+            buffer::DataBuffer,
+            executor::Executor,
+            history::{Convergence, ConvergenceDetector, RewardHistory},
+            instruction::{Instruction, Plan},
+            journal::Journal,
+        },
+        goals::{multi_dim::PositionContextualReward, ChannelGoal},
+    };
+
+    use super::{AgentSession, InReview};
+
+    #[tokio::test]
+    async fn act_async_writes_every_instruction() {
+        let (output, log) = Peripheral::recording_output();
+        let body = Body::builder().with_node(output).build();
+        let node = body.root[0];
+
+        let directions = vec![
+            Instruction::new(node, 5, [1, 0, 0, 0]),
+            Instruction::new(node, 5, [2, 0, 0, 0]),
+        ];
+        let mut agent: AgentSession<f64, InReview, 1> = AgentSession {
+            goal: 0.0,
+            body: Arc::new(Mutex::new(body)),
+            buffer: DataBuffer::default(),
+            journal: None,
+            history: RewardHistory::default(),
+            convergence: ConvergenceDetector::default(),
+            plan: Some(Plan::new(0, directions)),
+            _spooky_ghost: PhantomData,
+        };
+
+        agent
+            .act_async(&Executor::new())
+            .await
+            .expect("Act out directions");
+
+        let records = log.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].bytes, vec![2, 0, 0, 0]);
+        assert!(records[1].at - records[0].at >= std::time::Duration::from_millis(5));
+    }
+
+    #[test]
+    fn reports_carry_readings_and_reward_after_the_plan() {
+        let (output, _log) = Peripheral::recording_output();
+        let light = PeripheralNode::from(Peripheral::constant_input(1.0))
+            .with_metadata(Metadata::new("light").with_column(0));
+        let body = Body::builder().with_node(output).with_node(light).build();
+        let (output, input) = (body.root[0], body.root[1]);
+
+        let mut goal = PositionContextualReward::<4>::default();
+        goal.update(vec![ChannelGoal::target(3, 3.0)]).unwrap();
+        let agent = AgentSession::<_, _, 8>::builder()
+            .with_body(Arc::new(Mutex::new(body)))
+            .with_goal(goal)
+            .build()
+            .unwrap();
+
+        let plan = Plan::new(
+            7,
+            vec![
+                Instruction::new(input, 5, [1, 0, 0, 0]),
+                Instruction::new(output, 5, [2, 0, 0, 0]),
+            ],
+        );
+        let mut agent = agent.review(plan);
+        let run = agent
+            .act(&Executor::new().with_carry_on(true))
+            .expect("Act out directions");
+        let feedback = agent
+            .report(&run, [0.5, 0.0, 0.0])
+            .expect("Read channels after the plan");
+
+        assert_eq!(feedback.plan, 7);
+        assert!(!feedback.succeeded());
+        assert!(feedback.outcomes[0].error.is_some());
+        assert!(feedback.outcomes[1].succeeded());
+        assert!(feedback.outcomes[1].ended_at >= feedback.outcomes[1].started_at);
+        assert_eq!(feedback.readings, vec![0.5, 0.0, 0.0, 1.0]);
+        assert_eq!(feedback.reward, -2.0);
+        assert_eq!(agent.history().len(), 1);
+
+        let agent = agent.retrain();
+        assert!(agent.plan.is_none());
+    }
+
+    #[test]
+    fn recorded_points_feed_the_reward_history() {
+        let body = Body::builder().build();
+        let mut goal = PositionContextualReward::<2>::default();
+        goal.update(vec![ChannelGoal::target(1, 5.0)]).unwrap();
+
+        let mut agent = AgentSession::<_, _, 8>::builder()
+            .with_body(Arc::new(Mutex::new(body)))
+            .with_goal(goal)
+            .with_buffer(DataBuffer::default().with_dims(2))
+            .with_convergence(ConvergenceDetector::default().with_threshold(0.0))
+            .build()
+            .unwrap();
+
+        agent.record(&[0.0, 2.0]).unwrap();
+        assert_eq!(agent.convergence(), None);
+        agent.record(&[0.0, 5.0]).unwrap();
+
+        assert_eq!(
+            agent.history().window(2).collect::<Vec<_>>(),
+            vec![-3.0, 0.0]
+        );
+        assert_eq!(agent.convergence(), Some(Convergence::Threshold(0.0)));
+    }
+
+    #[test]
+    fn collected_points_are_recorded() {
+        let light = PeripheralNode::from(Peripheral::constant_input(2.0))
+            .with_metadata(Metadata::new("light").with_column(0));
+        let body = Body::builder().with_node(light).build();
+        let mut sampler = Sampler::new(&body.channel_schema().unwrap())
+            .with_period(body.root[0], Duration::from_millis(1));
+        let dir =
+            std::env::temp_dir().join(format!("earthmover_agent_collected_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut agent = AgentSession::<_, _, 4>::builder()
+            .with_body(Arc::new(Mutex::new(body)))
+            .with_goal(0.0)
+            .with_buffer(DataBuffer::default().with_dims(1))
+            .with_journal(Journal::open(&dir).unwrap())
+            .build()
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert!(agent.collect(&mut sampler, deadline).unwrap());
+        assert_eq!(agent.history().len(), 4);
+        assert_eq!(agent.export(), vec![2.0; 4]);
+
+        // Nothing is journaled that the buffer didn't take
+        let journal = agent.journal_mut().unwrap();
+        journal.rotate().unwrap();
+        let sealed = journal.pending()[0].id;
+        assert_eq!(journal.read_segment(sealed).unwrap(), vec![vec![2.0]; 4]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,59 +1,169 @@
-//! Special structs for marking a buffer with a special position, allows us to fill a buffer and
-//! keep track of our spot without using a `Vec<u8>`
+//! A heap-backed buffer for collected data, counted in whole points of a fixed width, with a
+//! choice of what happens when it fills up
 
-use std::ops::Deref;
+use std::{collections::VecDeque, fmt, ops::Deref};
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-/// A DataBuffer with known position
+/// What a DataBuffer does with data that doesn't fit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Refuse the new data, leaving the buffer as it is
+    #[default]
+    Reject,
+    /// Drop the oldest points to make room
+    OverwriteOldest,
+    /// Hand everything buffered to the flush callback, then start afresh
+    Flush,
+}
+
+/// Receives the buffer's contents when it's flushed
+pub type FlushCallback = Box<dyn FnMut(Vec<f32>) + Send>;
+
+/// A buffer of at most `BUFFER_SIZE` values, kept on the heap, made up of points of `dims` values
+/// each
 pub struct DataBuffer<const BUFFER_SIZE: usize> {
-    /// The data
-    data: [f32; BUFFER_SIZE],
-    /// Where we are in that data
-    marker: BufferMarker<BUFFER_SIZE>,
+    /// The data, oldest first
+    data: VecDeque<f32>,
+    /// How many values make up a point
+    dims: usize,
+    /// What happens when new data doesn't fit
+    overflow: Overflow,
+    /// Where flushed data goes
+    on_flush: Option<FlushCallback>,
+    /// How many points have been overwritten or rejected
+    dropped: usize,
 }
 
 impl<const BUFFER_SIZE: usize> Default for DataBuffer<BUFFER_SIZE> {
     fn default() -> Self {
         Self {
-            data: [0f32; BUFFER_SIZE],
-            marker: BufferMarker::default(),
+            data: VecDeque::with_capacity(BUFFER_SIZE),
+            dims: 1,
+            overflow: Overflow::default(),
+            on_flush: None,
+            dropped: 0,
         }
     }
 }
 
-impl<const BUFFER_SIZE: usize> AsRef<[f32]> for DataBuffer<BUFFER_SIZE> {
-    fn as_ref(&self) -> &[f32] {
-        if *self.marker == BUFFER_SIZE - 1 {
-            &self.data
-        } else {
-            &self.data[0..*self.marker]
-        }
+impl<const BUFFER_SIZE: usize> fmt::Debug for DataBuffer<BUFFER_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataBuffer")
+            .field("len", &self.data.len())
+            .field("dims", &self.dims)
+            .field("overflow", &self.overflow)
+            .field("dropped", &self.dropped)
+            .finish()
     }
 }
 
 impl<const BUFFER_SIZE: usize> DataBuffer<BUFFER_SIZE> {
-    /// Adds a buffer of bytes to the internal data buffer. If the length of the data to add is
-    /// greater than what's left in the buffer, we close early and return `None`
+    /// Sets how many values make up a point. Data is only ever added and dropped in whole points
+    pub fn with_dims(mut self, dims: usize) -> Self {
+        self.dims = dims.max(1);
+        self
+    }
+
+    /// Sets what happens when new data doesn't fit
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Flushes the buffer's contents to `callback` whenever new data doesn't fit
+    pub fn with_flush(mut self, callback: impl FnMut(Vec<f32>) + Send + 'static) -> Self {
+        self.overflow = Overflow::Flush;
+        self.on_flush = Some(Box::new(callback));
+        self
+    }
+
+    /// How many values make up a point
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// How many points the buffer can hold
+    pub fn capacity(&self) -> usize {
+        BUFFER_SIZE / self.dims
+    }
+
+    /// How many points are buffered
+    pub fn points(&self) -> usize {
+        self.data.len() / self.dims
+    }
+
+    /// How many more points fit before the buffer overflows
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.points()
+    }
+
+    /// Returns true if no more points fit
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns true if nothing is buffered
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// How many points have been overwritten or rejected
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Adds whole points to the buffer, overflowing by its policy if they don't fit. `None` is
+    /// returned if the data isn't a whole number of points, could never fit, or was rejected
     pub fn add_data(&mut self, buf: &[f32]) -> Option<()> {
-        if buf.len() <= self.data.len() - *self.marker {
-            for byte in buf {
-                *self.get()? = *byte;
+        if !buf.len().is_multiple_of(self.dims) || buf.len() / self.dims > self.capacity() {
+            return None;
+        }
+
+        let incoming = buf.len() / self.dims;
+        if incoming > self.remaining() {
+            match self.overflow {
+                Overflow::Reject => {
+                    self.dropped += incoming;
+                    return None;
+                }
+                Overflow::OverwriteOldest => {
+                    let excess = incoming - self.remaining();
+                    self.data.drain(..excess * self.dims);
+                    self.dropped += excess;
+                }
+                Overflow::Flush => {
+                    let flushed = self.drain();
+                    match &mut self.on_flush {
+                        Some(callback) => callback(flushed),
+                        None => self.dropped += flushed.len() / self.dims,
+                    }
+                }
             }
-            Some(())
-        } else {
-            None
         }
+
+        self.data.extend(buf);
+        Some(())
     }
 
+    /// Copies every buffered value out, oldest first
     pub fn export(&self) -> Vec<f32> {
-        self.data[0..(*self.marker)].to_vec()
+        self.data.iter().copied().collect()
+    }
+
+    /// Removes every buffered value, oldest first
+    pub fn drain(&mut self) -> Vec<f32> {
+        self.data.drain(..).collect()
+    }
+
+    /// Removes up to `points` of the oldest points, for uploading a large buffer a piece at a time
+    pub fn drain_points(&mut self, points: usize) -> Vec<f32> {
+        let values = (points * self.dims).min(self.data.len());
+        self.data.drain(..values).collect()
     }
 
-    /// Returns a mutable reference to the current byte, then increments the marker
-    fn get(&mut self) -> Option<&mut f32> {
-        let mut_byte = &mut self.data[*self.marker];
-        let _ = self.marker.inc();
-        Some(mut_byte)
+    /// Drains the buffer in chunks of up to `points` points each, oldest first
+    pub fn drain_chunks(&mut self, points: usize) -> impl Iterator<Item = Vec<f32>> + '_ {
+        let points = points.max(1);
+        std::iter::from_fn(move || (!self.is_empty()).then(|| self.drain_points(points)))
     }
 }
 
@@ -64,7 +174,7 @@ pub struct BufferMarker<const BUFFER_SIZE: usize>(usize);
 impl<const BUFFER_SIZE: usize> BufferMarker<BUFFER_SIZE> {
     /// Increment the buffer marker, returning None if the buffer is full
     pub fn inc(&mut self) -> Option<usize> {
-        if self.0 + 1 >= BUFFER_SIZE {
+        if self.0 >= BUFFER_SIZE {
             None
         } else {
             self.0 += 1;
@@ -86,7 +196,9 @@ impl<const BUFFER_SIZE: usize> Deref for BufferMarker<BUFFER_SIZE> {
 
 #[cfg(test)]
 mod tests {
-    use super::DataBuffer;
+    use std::sync::{Arc, Mutex};
+
+    use super::{BufferMarker, DataBuffer, Overflow};
 
     #[test]
     fn data_buffer_can_be_fully_filled() {
@@ -94,7 +206,8 @@ mod tests {
         let data = [10f32; 10];
 
         buf.add_data(&data).expect("Fill buf all the way with data");
-        assert_eq!(buf.as_ref(), &data);
+        assert_eq!(buf.export(), data);
+        assert!(buf.is_full());
     }
 
     #[test]
@@ -111,8 +224,50 @@ mod tests {
         let mut buf: DataBuffer<100> = DataBuffer::default();
         let small_chunk = [1f32, 2f32];
         buf.add_data(&small_chunk).expect("Add to a buffer");
-        let word: &[f32] = buf.as_ref();
 
-        assert_eq!(word, small_chunk)
+        assert_eq!(buf.export(), small_chunk)
+    }
+
+    #[test]
+    fn marker_counts_the_last_slot() {
+        let mut marker = BufferMarker::<2>::default();
+        assert_eq!(marker.inc(), Some(1));
+        assert_eq!(marker.inc(), Some(2));
+        assert_eq!(marker.inc(), None);
+    }
+
+    #[test]
+    fn points_are_counted_and_overwritten_whole() {
+        let mut buf = DataBuffer::<7>::default()
+            .with_dims(3)
+            .with_overflow(Overflow::OverwriteOldest);
+        assert_eq!(buf.capacity(), 2);
+        assert!(buf.add_data(&[1.0, 2.0]).is_none());
+
+        for point in 0..3 {
+            buf.add_data(&[point as f32; 3]).unwrap();
+        }
+        assert_eq!(buf.points(), 2);
+        assert_eq!(buf.dropped(), 1);
+        assert_eq!(buf.export(), vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn full_buffers_flush_and_drain_in_chunks() {
+        let flushed = Arc::new(Mutex::new(vec![]));
+        let sink = flushed.clone();
+        let mut buf = DataBuffer::<4>::default()
+            .with_dims(2)
+            .with_flush(move |data| sink.lock().unwrap().push(data));
+
+        for point in 0..3 {
+            buf.add_data(&[point as f32; 2]).unwrap();
+        }
+        assert_eq!(*flushed.lock().unwrap(), vec![vec![0.0, 0.0, 1.0, 1.0]]);
+
+        buf.add_data(&[3.0; 2]).unwrap();
+        let chunks: Vec<_> = buf.drain_chunks(1).collect();
+        assert_eq!(chunks, vec![vec![2.0, 2.0], vec![3.0, 3.0]]);
+        assert!(buf.is_empty());
     }
 }
@@ -0,0 +1,673 @@
+//! Executing instructions on a timeline, so different nodes can move at the same time, and halting
+//! a plan mid-way with an emergency stop
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tokio::sync::Notify;
+
+use crate::body::{Body, Peripheral, PeripheralError, PeripheralKey, SharedBody};
+
+use super::{
+    feedback::{ExecutionFeedback, InstructionOutcome},
+    instruction::Instruction,
+    validate::{PlanRejected, Report, Validator},
+};
+
+/// An instruction scheduled to start at an offset from the start of a timeline
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scheduled {
+    /// When the instruction starts, relative to the start of the timeline
+    pub starts_at: Duration,
+    /// The instruction to perform
+    pub instruction: Instruction,
+}
+
+/// A plan of instructions on a timeline. Instructions on different nodes run concurrently, while
+/// instructions on the same node run in order, each waiting for the last to finish
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timeline {
+    /// Every scheduled instruction
+    entries: Vec<Scheduled>,
+}
+
+impl Timeline {
+    /// Creates an empty timeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules an instruction to start `starts_at` after the timeline starts
+    pub fn with(mut self, starts_at: Duration, instruction: Instruction) -> Self {
+        self.push(starts_at, instruction);
+        self
+    }
+
+    /// Schedules an instruction to start `starts_at` after the timeline starts
+    pub fn push(&mut self, starts_at: Duration, instruction: Instruction) {
+        self.entries.push(Scheduled {
+            starts_at,
+            instruction,
+        })
+    }
+
+    /// Creates a timeline that runs instructions strictly one after another, the way
+    /// `Executor::perform` does
+    pub fn sequential(instructions: impl IntoIterator<Item = Instruction>) -> Self {
+        let mut timeline = Self::new();
+        let mut starts_at = Duration::ZERO;
+        for instruction in instructions {
+            let lasts_for = Duration::from_millis(instruction.lasts_for_ms as u64);
+            timeline.push(starts_at, instruction);
+            starts_at += lasts_for;
+        }
+        timeline
+    }
+
+    /// The scheduled instructions
+    pub fn entries(&self) -> &[Scheduled] {
+        &self.entries
+    }
+
+    /// When every instruction actually starts once per node ordering is applied, in the order they
+    /// start
+    pub fn plan(&self) -> Vec<Scheduled> {
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|entry| entry.starts_at);
+
+        let mut free_at = HashMap::<PeripheralKey, Duration>::new();
+        for entry in &mut entries {
+            let free = free_at.entry(entry.instruction.node).or_default();
+            entry.starts_at = entry.starts_at.max(*free);
+            *free = entry.starts_at + Duration::from_millis(entry.instruction.lasts_for_ms as u64);
+        }
+
+        entries.sort_by_key(|entry| entry.starts_at);
+        entries
+    }
+}
+
+/// A signal that halts every executor watching it. Clones share the same signal, so one can be
+/// handed to another thread as an emergency stop
+#[derive(Clone, Default)]
+pub struct StopSignal {
+    /// Whether a stop has been requested, and a condvar to wake waiting executors
+    inner: Arc<(Mutex<bool>, Condvar)>,
+    /// Wakes executors waiting on a runtime rather than a thread
+    notify: Arc<Notify>,
+}
+
+impl StopSignal {
+    /// Creates a new signal that hasn't been stopped
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops every executor watching this signal
+    pub fn stop(&self) {
+        let (stopped, condvar) = &*self.inner;
+        *stopped.lock().unwrap() = true;
+        condvar.notify_all();
+        self.notify.notify_waiters();
+    }
+
+    /// Clears a stop so the signal can be reused for another run
+    pub fn reset(&self) {
+        *self.inner.0.lock().unwrap() = false;
+    }
+
+    /// Returns true if a stop has been requested
+    pub fn is_stopped(&self) -> bool {
+        *self.inner.0.lock().unwrap()
+    }
+
+    /// Sleeps until `deadline`, waking early if stopped. Returns true if stopped
+    pub(crate) fn wait_until(&self, deadline: Instant) -> bool {
+        let (stopped, condvar) = &*self.inner;
+        let mut guard = stopped.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if *guard || now >= deadline {
+                return *guard;
+            }
+            guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Sleeps until `deadline` without blocking the runtime, waking early if stopped. Returns true
+    /// if stopped
+    pub(crate) async fn wait_until_async(&self, deadline: Instant) -> bool {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            // Registers for a wakeup before checking, so a stop in between isn't missed
+            notified.as_mut().enable();
+            if self.is_stopped() {
+                return true;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return self.is_stopped(),
+                _ = notified => {}
+            }
+        }
+    }
+}
+
+/// How a timeline run ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every instruction ran to completion
+    Completed,
+    /// The run was stopped after this many instructions had started
+    Stopped(usize),
+    /// A write failed, so the run was halted after this many instructions had started, or the plan
+    /// was never performed at all
+    Failed(usize),
+}
+
+/// Everything that happened during a run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    /// How the run ended
+    pub outcome: Outcome,
+    /// What validation found, empty if the plan wasn't validated
+    pub report: Report,
+    /// How each instruction that started went, in the order they started
+    pub outcomes: Vec<InstructionOutcome>,
+}
+
+impl Run {
+    /// A run that wasn't validated
+    fn new(outcome: Outcome, outcomes: Vec<InstructionOutcome>) -> Self {
+        Self {
+            outcome,
+            report: Report::default(),
+            outcomes,
+        }
+    }
+
+    /// A run of a plan that was never performed, such as one validation rejected, recording
+    /// every instruction as failed for `reason`
+    pub fn unperformed(plan: &[Instruction], reason: &str) -> Self {
+        let outcomes = plan
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| InstructionOutcome {
+                index,
+                node: instruction.node,
+                started_at: Duration::ZERO,
+                ended_at: Duration::ZERO,
+                error: Some(reason.to_owned()),
+            })
+            .collect();
+        Self::new(Outcome::Failed(0), outcomes)
+    }
+
+    /// Feedback on how each instruction went, to report back to the hivemind
+    pub fn feedback(&self) -> ExecutionFeedback {
+        ExecutionFeedback::new(self.outcomes.clone())
+    }
+}
+
+/// Anything that stops a plan from being performed
+#[derive(Debug, Error)]
+pub enum ExecutionError {
+    #[error("{0}")]
+    /// Validation rejected the plan before anything moved
+    Rejected(#[from] PlanRejected),
+    #[error("{0}")]
+    /// Outputs couldn't be brought to rest after a stop
+    Peripheral(#[from] PeripheralError),
+}
+
+/// Runs timelines against a Body, either blocking the calling thread or as a task on a runtime.
+/// Each output's write should return quickly and leave the move to run for the instruction's
+/// duration. The body is only locked for each write, leaving it free for a `Watchdog` while
+/// instructions run
+#[derive(Clone, Default)]
+pub struct Executor {
+    /// The stop signal this executor watches
+    stop: StopSignal,
+    /// Bytes written to an output to bring it to rest when a run halts early
+    safe_values: HashMap<PeripheralKey, Vec<u8>>,
+    /// Bytes written on halting to outputs without their own safe value
+    default_safe_value: Option<Vec<u8>>,
+    /// Checks plans before they're performed, if set
+    validator: Option<Validator>,
+    /// Whether a failed write is recorded and skipped rather than halting the run
+    carry_on: bool,
+}
+
+impl Executor {
+    /// Creates an executor with its own stop signal and no safe values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watches an existing stop signal, such as one shared by several executors
+    pub fn with_stop_signal(mut self, stop: StopSignal) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Sets the bytes written to `node` when a run halts early
+    pub fn with_safe_value(mut self, node: PeripheralKey, bytes: impl Into<Vec<u8>>) -> Self {
+        self.safe_values.insert(node, bytes.into());
+        self
+    }
+
+    /// Sets the bytes written when a run halts early to every output without its own safe value
+    pub fn with_default_safe_value(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.default_safe_value = Some(bytes.into());
+        self
+    }
+
+    /// Validates plans before performing them, running only as much of each as the validator's
+    /// policy allows
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// Sets whether a failed write is recorded and skipped rather than halting the run
+    pub fn with_carry_on(mut self, carry_on: bool) -> Self {
+        self.carry_on = carry_on;
+        self
+    }
+
+    /// A handle to this executor's stop signal
+    pub fn stop_signal(&self) -> StopSignal {
+        self.stop.clone()
+    }
+
+    /// Validates a plan if the executor has a validator, then runs what's left of it one
+    /// instruction after another
+    pub fn perform(&self, body: &Mutex<Body>, plan: &[Instruction]) -> Result<Run, ExecutionError> {
+        let (runnable, report) = match &self.validator {
+            Some(validator) => validator.apply(&body.lock().unwrap(), plan)?,
+            None => (plan, Report::default()),
+        };
+
+        let mut run = self.run(body, &Timeline::sequential(runnable.iter().cloned()))?;
+        run.report = report;
+        Ok(run)
+    }
+
+    /// Validates a plan like `perform`, then runs what's left of it like `run_async`
+    pub async fn perform_async(
+        &self,
+        body: &SharedBody,
+        plan: &[Instruction],
+    ) -> Result<Run, ExecutionError> {
+        let (runnable, report) = match &self.validator {
+            Some(validator) => validator.apply(&body.lock().unwrap(), plan)?,
+            None => (plan, Report::default()),
+        };
+
+        let mut run = self
+            .run_async(body, &Timeline::sequential(runnable.iter().cloned()))
+            .await?;
+        run.report = report;
+        Ok(run)
+    }
+
+    /// Runs a timeline to completion or until stopped, waiting out the last instruction's
+    /// duration before returning. Instructions for missing nodes or inputs are skipped and
+    /// recorded as failed. If the run is stopped or a write fails, every output is sent its safe
+    /// value, unless the executor carries on past failed writes. A failed write is recorded in the
+    /// run rather than returned, so what ran before it is still reported. Writes are checked
+    /// against each node's safety limits
+    pub fn run(&self, body: &Mutex<Body>, timeline: &Timeline) -> Result<Run, PeripheralError> {
+        let mut progress = Progress::new(timeline);
+
+        for (index, entry) in timeline.plan().into_iter().enumerate() {
+            if self.stop.wait_until(progress.started + entry.starts_at) {
+                self.halt(body)?;
+                return Ok(Run::new(Outcome::Stopped(index), progress.outcomes));
+            }
+
+            let started_at = progress.started.elapsed();
+            let written = write(body, &entry.instruction);
+            if progress.record(index, &entry, started_at, written, self.carry_on) {
+                // The failed write is already recorded, and matters more than any error from
+                // bringing things to rest
+                let _ = self.halt(body);
+                return Ok(Run::new(Outcome::Failed(index + 1), progress.outcomes));
+            }
+        }
+
+        if self.stop.wait_until(progress.ends_at) {
+            self.halt(body)?;
+            return Ok(Run::new(Outcome::Stopped(progress.len), progress.outcomes));
+        }
+
+        Ok(Run::new(Outcome::Completed, progress.outcomes))
+    }
+
+    /// Runs a timeline like `run`, waiting between instructions on the runtime instead of blocking
+    /// it. Writes and halts lock the body, so they run on the blocking pool
+    pub async fn run_async(
+        &self,
+        body: &SharedBody,
+        timeline: &Timeline,
+    ) -> Result<Run, PeripheralError> {
+        let mut progress = Progress::new(timeline);
+
+        for (index, entry) in timeline.plan().into_iter().enumerate() {
+            if self
+                .stop
+                .wait_until_async(progress.started + entry.starts_at)
+                .await
+            {
+                self.halt_async(body).await?;
+                return Ok(Run::new(Outcome::Stopped(index), progress.outcomes));
+            }
+
+            let started_at = progress.started.elapsed();
+            let (shared, instruction) = (body.clone(), entry.instruction.clone());
+            let written = tokio::task::spawn_blocking(move || write(&shared, &instruction))
+                .await
+                .unwrap_or_else(|err| Err(err.into()));
+            if progress.record(index, &entry, started_at, written, self.carry_on) {
+                let _ = self.halt_async(body).await;
+                return Ok(Run::new(Outcome::Failed(index + 1), progress.outcomes));
+            }
+        }
+
+        if self.stop.wait_until_async(progress.ends_at).await {
+            self.halt_async(body).await?;
+            return Ok(Run::new(Outcome::Stopped(progress.len), progress.outcomes));
+        }
+
+        Ok(Run::new(Outcome::Completed, progress.outcomes))
+    }
+
+    /// Halts like `halt`, on the blocking pool
+    async fn halt_async(&self, body: &SharedBody) -> Result<(), PeripheralError> {
+        let (executor, body) = (self.clone(), body.clone());
+        tokio::task::spawn_blocking(move || executor.halt(&body)).await?
+    }
+
+    /// Sends every output its safe value, or the safe state from its limits if the executor has
+    /// none for it, carrying on past failures and returning the first
+    fn halt(&self, body: &Mutex<Body>) -> Result<(), PeripheralError> {
+        let mut result = Ok(());
+        for (key, node) in body.lock().unwrap().peripheral_graph.iter_mut() {
+            let written = match (&mut node.peripheral, self.safe_value(key)) {
+                (Peripheral::Output(output), Some(safe)) => output.write(safe),
+                _ => node.make_safe().map(|_| ()),
+            };
+
+            if let Err(err) = written {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    /// The safe value the executor sends a node when halting
+    fn safe_value(&self, key: PeripheralKey) -> Option<&Vec<u8>> {
+        self.safe_values
+            .get(&key)
+            .or(self.default_safe_value.as_ref())
+    }
+}
+
+/// How far a run has got, shared by the blocking and async ways of running a timeline
+struct Progress {
+    /// When the run started
+    started: Instant,
+    /// When the last instruction written so far finishes
+    ends_at: Instant,
+    /// How many instructions the timeline holds
+    len: usize,
+    /// How each instruction that started went
+    outcomes: Vec<InstructionOutcome>,
+}
+
+impl Progress {
+    /// A run of `timeline` starting now
+    fn new(timeline: &Timeline) -> Self {
+        let started = Instant::now();
+        Self {
+            started,
+            ends_at: started,
+            len: timeline.entries().len(),
+            outcomes: vec![],
+        }
+    }
+
+    /// Records how writing an entry went. Returns true if the failed write should halt the run
+    fn record(
+        &mut self,
+        index: usize,
+        entry: &Scheduled,
+        started_at: Duration,
+        written: Result<(), PeripheralError>,
+        carry_on: bool,
+    ) -> bool {
+        let instruction = &entry.instruction;
+        let lasts_for = Duration::from_millis(instruction.lasts_for_ms as u64);
+        let (error, halts) = match written {
+            Ok(()) => {
+                self.ends_at = self.ends_at.max(self.started + entry.starts_at + lasts_for);
+                (None, false)
+            }
+            Err(err @ (PeripheralError::MissingNode | PeripheralError::NotAnOutput)) => {
+                (Some(err), false)
+            }
+            Err(err) => (Some(err), !carry_on),
+        };
+
+        self.outcomes.push(InstructionOutcome {
+            index,
+            node: instruction.node,
+            started_at,
+            ended_at: match error {
+                None => started_at + lasts_for,
+                Some(_) => started_at,
+            },
+            error: error.map(|err| err.to_string()),
+        });
+        halts
+    }
+}
+
+/// Writes an instruction's payload to its node, locking the body only for the write
+fn write(body: &Mutex<Body>, instruction: &Instruction) -> Result<(), PeripheralError> {
+    match body.lock().unwrap().get_by_id_mut(instruction.node) {
+        Some(node) => node.write(instruction.payload.as_bytes()),
+        None => Err(PeripheralError::MissingNode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        body::{safety::SafetyLimits, Body, Peripheral},
+        brain::{
+            instruction::Instruction,
+            validate::{Policy, Validator},
+        },
+    };
+
+    use super::{ExecutionError, Executor, Outcome, Run, Timeline};
+
+    #[test]
+    fn nodes_run_concurrently_but_in_order_per_node() {
+        let (a, a_log) = Peripheral::recording_output();
+        let (b, b_log) = Peripheral::recording_output();
+        let body = Body::builder().with_node(a).with_node(b).build();
+        let (a, b) = (body.root[0], body.root[1]);
+        let body = Mutex::new(body);
+
+        let timeline = Timeline::new()
+            .with(Duration::ZERO, Instruction::new(a, 40, [1, 0, 0, 0]))
+            .with(Duration::ZERO, Instruction::new(a, 10, [2, 0, 0, 0]))
+            .with(Duration::ZERO, Instruction::new(b, 10, [3, 0, 0, 0]));
+
+        let run = Executor::new().run(&body, &timeline).unwrap();
+        assert_eq!(run.outcome, Outcome::Completed);
+        assert_eq!(run.outcomes.len(), 3);
+
+        let a_records = a_log.records();
+        let b_records = b_log.records();
+        // The second write is due 40ms after the timeline starts, and the first lands a moment
+        // after it starts, so the gap can fall just short of 40ms
+        let gap = a_records[1].at - a_records[0].at;
+        assert!(gap >= Duration::from_millis(35), "{gap:?}");
+        assert!(b_records[0].at < a_records[1].at);
+    }
+
+    #[test]
+    fn stop_halts_and_writes_safe_values() {
+        let (motor, log) = Peripheral::recording_output();
+        let body = Body::builder().with_node(motor).build();
+        let motor = body.root[0];
+        let body = Mutex::new(body);
+
+        let timeline = Timeline::sequential([
+            Instruction::new(motor, 5_000, [1, 0, 0, 0]),
+            Instruction::new(motor, 5_000, [2, 0, 0, 0]),
+        ]);
+        let executor = Executor::new().with_safe_value(motor, [0, 0, 0, 0]);
+        let stop = executor.stop_signal();
+
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            stop.stop();
+        });
+
+        let started = Instant::now();
+        let run = executor.run(&body, &timeline).unwrap();
+        stopper.join().unwrap();
+
+        assert_eq!(run.outcome, Outcome::Stopped(1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(log.last(), Some(vec![0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn perform_validates_then_runs_the_allowed_prefix() {
+        let (motor, log) = Peripheral::recording_output();
+        let body = Body::builder()
+            .with_node(motor)
+            .with_node(Peripheral::constant_input(1.0))
+            .build();
+        let (motor, input) = (body.root[0], body.root[1]);
+        let body = Mutex::new(body);
+
+        let plan = [
+            Instruction::new(motor, 1, [1, 0, 0, 0]),
+            Instruction::new(input, 1, [2, 0, 0, 0]),
+            Instruction::new(motor, 1, [3, 0, 0, 0]),
+        ];
+
+        let rejecting = Executor::new().with_validator(Validator::new());
+        let Err(err @ ExecutionError::Rejected(_)) = rejecting.perform(&body, &plan) else {
+            panic!("The plan writes to an input");
+        };
+        assert!(log.records().is_empty());
+
+        let unperformed = Run::unperformed(&plan, &err.to_string());
+        assert_eq!(unperformed.outcome, Outcome::Failed(0));
+        assert_eq!(unperformed.outcomes.len(), plan.len());
+        assert!(!unperformed.feedback().succeeded());
+
+        let prefix =
+            Executor::new().with_validator(Validator::new().with_policy(Policy::ValidPrefix));
+        let run = prefix.perform(&body, &plan).unwrap();
+        assert_eq!(run.report.valid_prefix, 1);
+        assert_eq!(run.outcomes.len(), 1);
+        assert_eq!(log.records().len(), 1);
+    }
+
+    #[test]
+    fn carrying_on_records_failures_and_keeps_going() {
+        let (motor, log) = Peripheral::recording_output();
+        let body = Body::builder()
+            .with_node(motor)
+            .with_node(Peripheral::constant_input(1.0))
+            .build();
+        let (motor, input) = (body.root[0], body.root[1]);
+        let body = Mutex::new(body);
+
+        let plan = [
+            Instruction::new(input, 5, [1, 0, 0, 0]),
+            Instruction::new(motor, 5, [2, 0, 0, 0]),
+        ];
+        let run = Executor::new()
+            .with_carry_on(true)
+            .perform(&body, &plan)
+            .unwrap();
+
+        assert_eq!(run.outcome, Outcome::Completed);
+        assert!(!run.feedback().succeeded());
+        assert!(run.outcomes[0].error.is_some());
+        assert!(run.outcomes[1].succeeded());
+        assert!(run.outcomes[1].ended_at >= run.outcomes[1].started_at);
+        assert_eq!(log.records().len(), 1);
+    }
+
+    #[test]
+    fn failed_writes_halt_but_keep_what_ran() {
+        let (motor, log) = Peripheral::recording_output();
+        let limits = SafetyLimits::new()
+            .with_min_write_interval(Duration::from_secs(60))
+            .with_safe_state([0, 0, 0, 0]);
+        let body = Body::builder().with_limited_node(motor, limits).build();
+        let motor = body.root[0];
+        let body = Mutex::new(body);
+
+        let timeline = Timeline::sequential([
+            Instruction::new(motor, 1, [1, 0, 0, 0]),
+            Instruction::new(motor, 1, [2, 0, 0, 0]),
+            Instruction::new(motor, 1, [3, 0, 0, 0]),
+        ]);
+        let run = Executor::new().run(&body, &timeline).unwrap();
+
+        assert_eq!(run.outcome, Outcome::Failed(2));
+        assert_eq!(run.outcomes.len(), 2);
+        assert!(run.outcomes[0].succeeded());
+        assert!(run.outcomes[1].error.is_some());
+        assert_eq!(log.last(), Some(vec![0, 0, 0, 0]));
+    }
+
+    #[tokio::test]
+    async fn async_runs_leave_the_runtime_free_to_stop_them() {
+        let (motor, log) = Peripheral::recording_output();
+        let body = Body::builder().with_node(motor).build();
+        let motor = body.root[0];
+        let body = Arc::new(Mutex::new(body));
+
+        let timeline = Timeline::sequential([Instruction::new(motor, 5_000, [1, 0, 0, 0])]);
+        let executor = Executor::new().with_safe_value(motor, [0, 0, 0, 0]);
+        let stop = executor.stop_signal();
+
+        // Only runs if waiting on the plan doesn't block this single threaded runtime
+        let stopper = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stop.stop();
+        });
+
+        let started = Instant::now();
+        let run = executor.run_async(&body, &timeline).await.unwrap();
+        stopper.await.unwrap();
+
+        assert_eq!(run.outcome, Outcome::Stopped(1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(log.last(), Some(vec![0, 0, 0, 0]));
+    }
+}
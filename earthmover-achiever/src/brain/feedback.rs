@@ -0,0 +1,79 @@
+//! What actually happened when an Agent performed a plan, reported back to the hivemind so it can
+//! compare its predicted scores with real outcomes
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::body::PeripheralKey;
+
+use super::instruction::PlanId;
+
+/// How a single instruction of a plan went
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstructionOutcome {
+    /// Where the instruction is in the plan
+    pub index: usize,
+    /// The node the instruction was for
+    pub node: PeripheralKey,
+    /// When the instruction started, from the start of the plan
+    pub started_at: Duration,
+    /// When the instruction finished, from the start of the plan
+    pub ended_at: Duration,
+    /// Why the instruction failed, if it did
+    pub error: Option<String>,
+}
+
+impl InstructionOutcome {
+    /// Returns true if the instruction was performed without error
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Everything an agent reports about the last plan it performed
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionFeedback {
+    /// The plan that was performed
+    pub plan: PlanId,
+    /// How each instruction went, in plan order
+    pub outcomes: Vec<InstructionOutcome>,
+    /// The agent's position and every channel's reading after the plan finished, laid out like
+    /// a collected point
+    pub readings: Vec<f32>,
+    /// The reward the agent measured after the plan finished
+    pub reward: f64,
+}
+
+impl ExecutionFeedback {
+    /// Creates feedback from the outcome of every instruction
+    pub fn new(outcomes: Vec<InstructionOutcome>) -> Self {
+        Self {
+            outcomes,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the plan that was performed
+    pub fn with_plan(mut self, plan: PlanId) -> Self {
+        self.plan = plan;
+        self
+    }
+
+    /// Sets the sensor readings taken after the plan finished
+    pub fn with_readings(mut self, readings: Vec<f32>) -> Self {
+        self.readings = readings;
+        self
+    }
+
+    /// Sets the reward measured after the plan finished
+    pub fn with_reward(mut self, reward: f64) -> Self {
+        self.reward = reward;
+        self
+    }
+
+    /// Returns true if every instruction was performed without error
+    pub fn succeeded(&self) -> bool {
+        self.outcomes.iter().all(InstructionOutcome::succeeded)
+    }
+}
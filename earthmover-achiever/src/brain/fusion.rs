@@ -0,0 +1,454 @@
+//! Joining lidar-derived xyz points with time-aligned readings from other sensors into complete
+//! points for upload
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::body::{
+    sampling::{Point, PointSource, Sampler},
+    Body,
+};
+
+/// How many readings each stream keeps by default while waiting to be joined
+pub const DEFAULT_HISTORY: usize = 256;
+
+/// How many of a point's leading dimensions are its xyz position
+pub const SPATIAL_DIMS: usize = 3;
+
+/// What a point's spatial dimensions are called in goals and reward expressions
+pub const SPATIAL_NAMES: [&str; SPATIAL_DIMS] = ["x", "y", "z"];
+
+/// A lidar-derived point in 3-space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpatialSample {
+    /// When the point was measured
+    pub at: Duration,
+    /// The point's position
+    pub xyz: [f32; 3],
+}
+
+/// How a sensor's value is chosen for a point measured between two of its readings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// The reading closest in time
+    #[default]
+    Nearest,
+    /// Linearly interpolated between the readings either side, falling back to the nearest when
+    /// there's only one side within tolerance
+    Interpolate,
+}
+
+/// Fuses spatial samples with other sensors' readings into `dims` dimensional points. The first
+/// three dimensions are always xyz, every further dimension is fed by its own stream of readings
+pub struct Fuser {
+    /// How far a reading may be from a point in time and still be joined with it
+    tolerance: Duration,
+    /// How readings are chosen
+    alignment: Alignment,
+    /// How many readings each stream keeps
+    history: usize,
+    /// Readings in time order for each dimension past xyz
+    streams: Vec<VecDeque<(Duration, f32)>>,
+}
+
+impl Fuser {
+    /// Creates a fuser building `dims` dimensional points from readings within `tolerance` of
+    /// each point
+    pub fn new(dims: usize, tolerance: Duration) -> Self {
+        Self {
+            tolerance,
+            alignment: Alignment::default(),
+            history: DEFAULT_HISTORY,
+            streams: vec![VecDeque::new(); dims.saturating_sub(SPATIAL_DIMS)],
+        }
+    }
+
+    /// How many dimensions each point has
+    pub fn dims(&self) -> usize {
+        SPATIAL_DIMS + self.streams.len()
+    }
+
+    /// Sets how readings are chosen
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Sets how many readings each stream keeps
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history.max(1);
+        self
+    }
+
+    /// Adds a reading for dimension `dim`, which must be past xyz. Returns `None` if there's no
+    /// such dimension
+    pub fn push(&mut self, dim: usize, at: Duration, value: f32) -> Option<()> {
+        let stream = self.streams.get_mut(dim.checked_sub(SPATIAL_DIMS)?)?;
+
+        let index = stream.partition_point(|&(time, _)| time <= at);
+        stream.insert(index, (at, value));
+        if stream.len() > self.history {
+            stream.pop_front();
+        }
+        Some(())
+    }
+
+    /// Drops every reading taken before `at`
+    pub fn prune_before(&mut self, at: Duration) {
+        for stream in &mut self.streams {
+            while stream.front().is_some_and(|&(time, _)| time < at) {
+                stream.pop_front();
+            }
+        }
+    }
+
+    /// Joins a spatial sample with every stream, returning `None` if any stream has no reading
+    /// within tolerance of it
+    pub fn fuse(&self, sample: SpatialSample) -> Option<Vec<f32>> {
+        let mut point = sample.xyz.to_vec();
+        for stream in &self.streams {
+            point.push(self.align(stream, sample.at)?);
+        }
+        Some(point)
+    }
+
+    /// A stream's value at `at`
+    fn align(&self, stream: &VecDeque<(Duration, f32)>, at: Duration) -> Option<f32> {
+        let after = stream.partition_point(|&(time, _)| time < at);
+        let within = |&&(time, _): &&(Duration, f32)| time.abs_diff(at) <= self.tolerance;
+        let before = after
+            .checked_sub(1)
+            .and_then(|i| stream.get(i))
+            .filter(within);
+        let after = stream.get(after).filter(within);
+
+        match (before, after, self.alignment) {
+            (Some(&(t0, v0)), Some(&(t1, v1)), Alignment::Interpolate) if t1 > t0 => {
+                let ratio = (at - t0).as_secs_f32() / (t1 - t0).as_secs_f32();
+                Some(v0 + (v1 - v0) * ratio)
+            }
+            (Some(&(t0, v0)), Some(&(t1, v1)), _) => Some(if at - t0 <= t1 - at { v0 } else { v1 }),
+            (Some(&(_, value)), None, _) | (None, Some(&(_, value)), _) => Some(value),
+            (None, None, _) => None,
+        }
+    }
+}
+
+/// Where the xyz position of each point comes from
+pub trait SpatialSource: Send {
+    /// A position measured since the last one given, if there is one. `now` is how long it's been
+    /// since sampling started
+    fn next_sample(&mut self, now: Duration) -> Option<SpatialSample>;
+}
+
+/// A position that never changes, for agents that sense from one spot. A sample is given every
+/// `period`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedPosition {
+    /// Where the agent is
+    xyz: [f32; 3],
+    /// Time between samples
+    period: Duration,
+    /// When the next sample is due
+    next_at: Duration,
+}
+
+impl FixedPosition {
+    /// Creates a source always at `xyz`, sampled every `period`
+    pub fn new(xyz: [f32; 3], period: Duration) -> Self {
+        Self {
+            xyz,
+            period,
+            next_at: Duration::ZERO,
+        }
+    }
+}
+
+impl SpatialSource for FixedPosition {
+    fn next_sample(&mut self, now: Duration) -> Option<SpatialSample> {
+        if now < self.next_at {
+            return None;
+        }
+
+        self.next_at = now + self.period;
+        Some(SpatialSample {
+            at: now,
+            xyz: self.xyz,
+        })
+    }
+}
+
+impl<SOURCE: SpatialSource + ?Sized> SpatialSource for Box<SOURCE> {
+    fn next_sample(&mut self, now: Duration) -> Option<SpatialSample> {
+        (**self).next_sample(now)
+    }
+}
+
+/// A single lidar return, with the lidar's roll and pitch when it was taken, all in degrees
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LidarReading {
+    /// When the return was read
+    pub at: Instant,
+    /// The lidar's roll
+    pub roll: f32,
+    /// The lidar's pitch
+    pub pitch: f32,
+    /// The angle of the return around the lidar
+    pub angle: f32,
+    /// How far away the return was
+    pub distance: f32,
+}
+
+impl LidarReading {
+    /// Where the return was in 3-space
+    pub fn xyz(&self) -> [f32; 3] {
+        let (x, y, z) = earthmover_lidar::convert_raw_lidar_to_vector_space(
+            self.roll,
+            self.pitch,
+            self.angle,
+            self.distance,
+        );
+        [x, y, z]
+    }
+}
+
+/// Positions from a lidar's returns, sent over a channel by whatever thread reads the lidar. A
+/// sample is given for every return, placed in time by when it was read
+pub struct LidarSource {
+    /// Returns not yet given as samples
+    readings: Receiver<LidarReading>,
+    /// When sampling started, worked out from the first sample asked for
+    started: Option<Instant>,
+}
+
+impl LidarSource {
+    /// Creates a source taking returns from `readings`
+    pub fn new(readings: Receiver<LidarReading>) -> Self {
+        Self {
+            readings,
+            started: None,
+        }
+    }
+
+    /// Creates a source along with the sender the lidar's returns are fed through
+    pub fn channel() -> (Sender<LidarReading>, Self) {
+        let (sender, readings) = mpsc::channel();
+        (sender, Self::new(readings))
+    }
+}
+
+impl SpatialSource for LidarSource {
+    fn next_sample(&mut self, now: Duration) -> Option<SpatialSample> {
+        let started = *self.started.get_or_insert_with(|| {
+            let read_at = Instant::now();
+            read_at.checked_sub(now).unwrap_or(read_at)
+        });
+
+        let reading = self.readings.try_recv().ok()?;
+        Some(SpatialSample {
+            at: reading.at.saturating_duration_since(started),
+            xyz: reading.xyz(),
+        })
+    }
+}
+
+/// Samples a body's channels and joins them with the positions of a spatial source, giving points
+/// of xyz followed by every channel in column order
+pub struct SpatialSampler<SOURCE: SpatialSource> {
+    /// Reads the body's channels
+    sampler: Sampler,
+    /// Joins the channels' readings with each position
+    fuser: Fuser,
+    /// Where positions come from
+    source: SOURCE,
+    /// A position still waiting on readings to be joined with
+    waiting: Option<SpatialSample>,
+}
+
+impl<SOURCE: SpatialSource> SpatialSampler<SOURCE> {
+    /// Joins every channel of `sampler` with positions from `source`, taking readings within
+    /// `tolerance` of each position
+    pub fn new(sampler: Sampler, source: SOURCE, tolerance: Duration) -> Self {
+        Self {
+            fuser: Fuser::new(SPATIAL_DIMS + sampler.channels(), tolerance),
+            sampler,
+            source,
+            waiting: None,
+        }
+    }
+
+    /// Sets how readings are chosen for each position
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.fuser = self.fuser.with_alignment(alignment);
+        self
+    }
+
+    /// How many dimensions each point has
+    pub fn dims(&self) -> usize {
+        self.fuser.dims()
+    }
+
+    /// The sampler reading the body's channels
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+}
+
+impl<SOURCE: SpatialSource> PointSource for SpatialSampler<SOURCE> {
+    /// Samples until a position can be joined with a reading of every channel, or gives up with
+    /// `None` once `deadline` passes. Positions no reading arrives in time for are dropped
+    fn next_point(&mut self, body: &Mutex<Body>, deadline: Instant) -> Option<Point> {
+        loop {
+            for reading in self.sampler.read_due(&mut body.lock().unwrap()) {
+                self.fuser
+                    .push(SPATIAL_DIMS + reading.column, reading.at, reading.value);
+            }
+
+            let now = self.sampler.elapsed();
+            if self.waiting.is_none() {
+                self.waiting = self.source.next_sample(now);
+            }
+            if let Some(sample) = self.waiting {
+                if let Some(values) = self.fuser.fuse(sample) {
+                    self.waiting = None;
+                    return Some(Point {
+                        at: sample.at,
+                        values,
+                    });
+                }
+                if now > sample.at + self.fuser.tolerance {
+                    self.waiting = None;
+                }
+            }
+
+            if !self.sampler.wait_for_due(deadline) {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use crate::body::{
+        metadata::Metadata,
+        sampling::{PointSource, Sampler},
+        Body, Peripheral, PeripheralNode,
+    };
+
+    use super::{
+        Alignment, FixedPosition, Fuser, LidarReading, LidarSource, SpatialSample, SpatialSampler,
+        SpatialSource,
+    };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn sample(at: u64) -> SpatialSample {
+        SpatialSample {
+            at: ms(at),
+            xyz: [1.0, 2.0, 3.0],
+        }
+    }
+
+    #[test]
+    fn joins_nearest_reading_within_tolerance() {
+        let mut fuser = Fuser::new(4, ms(10));
+        fuser.push(3, ms(100), 5.0).unwrap();
+        fuser.push(3, ms(112), 7.0).unwrap();
+
+        assert_eq!(fuser.fuse(sample(104)), Some(vec![1.0, 2.0, 3.0, 5.0]));
+        assert_eq!(fuser.fuse(sample(109)), Some(vec![1.0, 2.0, 3.0, 7.0]));
+        assert_eq!(fuser.fuse(sample(130)), None);
+        assert!(fuser.push(2, ms(0), 0.0).is_none());
+    }
+
+    #[test]
+    fn interpolates_between_readings() {
+        let mut fuser = Fuser::new(5, ms(20)).with_alignment(Alignment::Interpolate);
+        fuser.push(3, ms(100), 0.0).unwrap();
+        fuser.push(3, ms(120), 10.0).unwrap();
+        fuser.push(4, ms(105), 1.0).unwrap();
+
+        let point = fuser.fuse(sample(115)).unwrap();
+        assert_eq!(point[3], 7.5);
+        assert_eq!(point[4], 1.0);
+    }
+
+    #[test]
+    fn out_of_order_readings_are_sorted_and_pruned() {
+        let mut fuser = Fuser::new(4, ms(5)).with_history(2);
+        fuser.push(3, ms(20), 2.0).unwrap();
+        fuser.push(3, ms(10), 1.0).unwrap();
+        fuser.push(3, ms(30), 3.0).unwrap();
+
+        // The oldest reading fell out of the history
+        assert_eq!(fuser.fuse(sample(10)), None);
+        assert_eq!(fuser.fuse(sample(21)).unwrap()[3], 2.0);
+
+        fuser.prune_before(ms(25));
+        assert_eq!(fuser.fuse(sample(21)), None);
+    }
+
+    #[test]
+    fn sampled_channels_follow_the_position() {
+        let light = PeripheralNode::from(Peripheral::constant_input(4.0))
+            .with_metadata(Metadata::new("light").with_column(0));
+        let temp = PeripheralNode::from(Peripheral::constant_input(22.0))
+            .with_metadata(Metadata::new("temp").with_column(1));
+        let body = Body::builder().with_node(light).with_node(temp).build();
+        let sampler = Sampler::new(&body.channel_schema().unwrap());
+        let body = Mutex::new(body);
+
+        let position = FixedPosition::new([1.0, 2.0, 3.0], ms(1));
+        let mut points = SpatialSampler::new(sampler, position, ms(50));
+        assert_eq!(points.dims(), 5);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let point = points.next_point(&body, deadline).unwrap();
+        assert_eq!(point.values, vec![1.0, 2.0, 3.0, 4.0, 22.0]);
+    }
+
+    #[test]
+    fn lidar_returns_become_positions_in_order() {
+        let (lidar, mut source) = LidarSource::channel();
+        assert_eq!(source.next_sample(ms(0)), None);
+
+        let reading = LidarReading {
+            at: Instant::now(),
+            roll: 45.0,
+            pitch: -90.0,
+            angle: 30.0,
+            distance: 1.0,
+        };
+        lidar.send(reading).unwrap();
+        lidar
+            .send(LidarReading {
+                distance: 8.0,
+                ..reading
+            })
+            .unwrap();
+
+        let first = source.next_sample(ms(5)).unwrap();
+        let expected = [1.608, 0.59328, 0.10817];
+        for (got, expected) in first.xyz.iter().zip(expected) {
+            assert!((got - expected).abs() < 1e-4, "{got} != {expected}");
+        }
+        let second = source.next_sample(ms(5)).unwrap();
+        assert_ne!(second.xyz, first.xyz);
+        assert!(second.at <= ms(5));
+        assert_eq!(source.next_sample(ms(5)), None);
+    }
+}
@@ -0,0 +1,231 @@
+//! A rolling history of an agent's reward, with statistics over recent windows and detection of
+//! when the reward has stopped changing in a useful way
+
+use std::collections::VecDeque;
+
+/// How many rewards a history keeps by default
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Why a reward is considered converged
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Convergence {
+    /// The latest reward reached the threshold
+    Threshold(f64),
+    /// The reward hasn't moved by more than epsilon over the plateau window
+    Plateau,
+    /// The reward keeps swinging back and forth without settling
+    Oscillating,
+}
+
+/// A fixed size record of the most recent rewards, oldest first
+#[derive(Clone, Debug, PartialEq)]
+pub struct RewardHistory {
+    /// The rewards, oldest first
+    rewards: VecDeque<f64>,
+    /// The most rewards kept before the oldest are dropped
+    capacity: usize,
+}
+
+impl Default for RewardHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RewardHistory {
+    /// Creates an empty history keeping at most `capacity` rewards
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rewards: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Records a reward, dropping the oldest if the history is full
+    pub fn push(&mut self, reward: f64) {
+        if self.rewards.len() == self.capacity {
+            self.rewards.pop_front();
+        }
+        self.rewards.push_back(reward)
+    }
+
+    /// How many rewards are held
+    pub fn len(&self) -> usize {
+        self.rewards.len()
+    }
+
+    /// Whether no rewards have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.rewards.is_empty()
+    }
+
+    /// The most recent reward
+    pub fn latest(&self) -> Option<f64> {
+        self.rewards.back().copied()
+    }
+
+    /// Forgets every reward, such as after retraining
+    pub fn clear(&mut self) {
+        self.rewards.clear()
+    }
+
+    /// Iterates over the last `window` rewards, oldest first. Shorter if fewer are held
+    pub fn window(&self, window: usize) -> impl Iterator<Item = f64> + '_ {
+        let skip = self.rewards.len().saturating_sub(window);
+        self.rewards.iter().skip(skip).copied()
+    }
+
+    /// The mean of the last `window` rewards
+    pub fn mean(&self, window: usize) -> Option<f64> {
+        let len = self.window(window).count();
+        (len > 0).then(|| self.window(window).sum::<f64>() / len as f64)
+    }
+
+    /// The population standard deviation of the last `window` rewards
+    pub fn std_dev(&self, window: usize) -> Option<f64> {
+        let mean = self.mean(window)?;
+        let len = self.window(window).count();
+        let variance = self
+            .window(window)
+            .map(|reward| (reward - mean).powi(2))
+            .sum::<f64>()
+            / len as f64;
+        Some(variance.sqrt())
+    }
+
+    /// The lowest and highest of the last `window` rewards
+    pub fn range(&self, window: usize) -> Option<(f64, f64)> {
+        self.window(window).fold(None, |range, reward| match range {
+            None => Some((reward, reward)),
+            Some((lo, hi)) => Some((lo.min(reward), hi.max(reward))),
+        })
+    }
+
+    /// How many times the last `window` rewards change direction, ignoring steps no bigger than
+    /// `epsilon`
+    pub fn reversals(&self, window: usize, epsilon: f64) -> usize {
+        let rewards = self.window(window).collect::<Vec<_>>();
+        let mut last_rising = None;
+        let mut reversals = 0;
+
+        for step in rewards.windows(2) {
+            let delta = step[1] - step[0];
+            if delta.abs() <= epsilon {
+                continue;
+            }
+            let rising = delta > 0.0;
+            if last_rising.is_some_and(|last| last != rising) {
+                reversals += 1;
+            }
+            last_rising = Some(rising);
+        }
+
+        reversals
+    }
+}
+
+/// Decides when a reward history has converged. Each check is off until configured, and only
+/// judged once its window is full
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConvergenceDetector {
+    /// The reward that counts as good enough
+    threshold: Option<f64>,
+    /// The window and spread of a plateau
+    plateau: Option<(usize, f64)>,
+    /// The window, reversals and step size of an oscillation
+    oscillation: Option<(usize, usize, f64)>,
+}
+
+impl ConvergenceDetector {
+    /// Converges once the latest reward is at least `threshold`
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Converges once the last `window` rewards all lie within `epsilon` of each other
+    pub fn with_plateau(mut self, window: usize, epsilon: f64) -> Self {
+        self.plateau = Some((window.max(2), epsilon));
+        self
+    }
+
+    /// Converges once the last `window` rewards change direction at least `reversals` times by
+    /// steps bigger than `epsilon`
+    pub fn with_oscillation(mut self, window: usize, reversals: usize, epsilon: f64) -> Self {
+        self.oscillation = Some((window.max(3), reversals.max(1), epsilon));
+        self
+    }
+
+    /// Checks a history for convergence, the threshold first, then a plateau, then oscillation
+    pub fn check(&self, history: &RewardHistory) -> Option<Convergence> {
+        let latest = history.latest()?;
+
+        if let Some(threshold) = self.threshold {
+            if latest >= threshold {
+                return Some(Convergence::Threshold(latest));
+            }
+        }
+
+        if let Some((window, epsilon)) = self.plateau {
+            if history.len() >= window {
+                let (lo, hi) = history.range(window)?;
+                if hi - lo <= epsilon {
+                    return Some(Convergence::Plateau);
+                }
+            }
+        }
+
+        if let Some((window, reversals, epsilon)) = self.oscillation {
+            if history.len() >= window && history.reversals(window, epsilon) >= reversals {
+                return Some(Convergence::Oscillating);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Convergence, ConvergenceDetector, RewardHistory};
+
+    #[test]
+    fn history_keeps_the_most_recent_rewards() {
+        let mut history = RewardHistory::new(3);
+        for reward in [1.0, 2.0, 3.0, 4.0] {
+            history.push(reward);
+        }
+
+        assert_eq!(history.window(10).collect::<Vec<_>>(), vec![2.0, 3.0, 4.0]);
+        assert_eq!(history.mean(2), Some(3.5));
+        assert_eq!(history.std_dev(2), Some(0.5));
+        assert_eq!(history.range(3), Some((2.0, 4.0)));
+    }
+
+    #[test]
+    fn detects_each_kind_of_convergence() {
+        let detector = ConvergenceDetector::default()
+            .with_threshold(10.0)
+            .with_plateau(4, 0.1)
+            .with_oscillation(6, 3, 0.5);
+
+        let mut history = RewardHistory::default();
+        for reward in [1.0, 2.0, 3.0] {
+            history.push(reward);
+        }
+        assert_eq!(detector.check(&history), None);
+
+        for reward in [3.05, 3.0, 2.98] {
+            history.push(reward);
+        }
+        assert_eq!(detector.check(&history), Some(Convergence::Plateau));
+
+        for reward in [5.0, 1.0, 5.0, 1.0, 5.0, 1.0] {
+            history.push(reward);
+        }
+        assert_eq!(detector.check(&history), Some(Convergence::Oscillating));
+
+        history.push(12.0);
+        assert_eq!(detector.check(&history), Some(Convergence::Threshold(12.0)));
+    }
+}
@@ -1,30 +1,312 @@
 //! Movement instructions for an Agent's Body
+//!
+//! An instruction's binary encoding is versioned. Version 1, with all values big endian:
+//!
+//! | Field          | Size          | Notes                                       |
+//! |----------------|---------------|---------------------------------------------|
+//! | version        | `u8`          | Always `1`                                  |
+//! | node           | `u64`         | The `PeripheralKey` as ffi                  |
+//! | lasts_for_ms   | `u32`         |                                             |
+//! | payload kind   | `u8`          | `0` for compact, `1` for extended           |
+//! | payload        | 4 or `2 + n`  | Compact is 4 bytes, extended a `u16` length |
 
+use deku::prelude::*;
 use serde::{Deserialize, Serialize};
+use slotmap::{Key, KeyData};
+use thiserror::Error;
 
 use crate::body::PeripheralKey;
 
+/// The current version of the binary instruction encoding
+pub const INSTRUCTION_VERSION: u8 = 1;
+
+/// The largest payload a single instruction may carry, in bytes
+pub const MAX_PAYLOAD_LEN: usize = 1024;
+
+/// An error creating, encoding or decoding an instruction
+#[derive(Debug, Error)]
+pub enum InstructionError {
+    /// The payload is longer than `MAX_PAYLOAD_LEN`
+    #[error("Payload of {0} bytes exceeds the {MAX_PAYLOAD_LEN} byte limit")]
+    PayloadTooLarge(usize),
+    /// The encoded instruction is of a version this build doesn't understand
+    #[error("Unsupported instruction version {0}")]
+    UnsupportedVersion(u8),
+    /// The encoded instruction couldn't be parsed
+    #[error("Malformed instruction: {0}")]
+    Malformed(#[from] DekuError),
+}
+
+/// Result type for instructions
+pub type Result<T> = std::result::Result<T, InstructionError>;
+
+/// The bytes an instruction writes to its node. Four byte commands, which covers every actuator
+/// command, are kept inline without allocating
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "PayloadRepr", into = "PayloadRepr")]
+pub enum Payload {
+    /// A four byte command
+    Compact([u8; 4]),
+    /// Any other length of command, up to `MAX_PAYLOAD_LEN` bytes
+    Extended(Vec<u8>),
+}
+
+impl Payload {
+    /// Creates a payload from arbitrary bytes, taking the compact form for exactly four bytes
+    pub fn new(bytes: &[u8]) -> Result<Self> {
+        match <[u8; 4]>::try_from(bytes) {
+            Ok(word) => Ok(Self::Compact(word)),
+            Err(_) if bytes.len() > MAX_PAYLOAD_LEN => {
+                Err(InstructionError::PayloadTooLarge(bytes.len()))
+            }
+            Err(_) => Ok(Self::Extended(bytes.to_vec())),
+        }
+    }
+
+    /// The payload's bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Compact(word) => word,
+            Self::Extended(bytes) => bytes,
+        }
+    }
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Self::Compact([0; 4])
+    }
+}
+
+impl From<[u8; 4]> for Payload {
+    fn from(word: [u8; 4]) -> Self {
+        Self::Compact(word)
+    }
+}
+
+/// The serialized form of a payload, checked against the size limit on the way in
+#[derive(Serialize, Deserialize)]
+enum PayloadRepr {
+    /// A four byte command
+    Compact([u8; 4]),
+    /// Any other length of command
+    Extended(Vec<u8>),
+}
+
+impl TryFrom<PayloadRepr> for Payload {
+    type Error = InstructionError;
+    fn try_from(repr: PayloadRepr) -> Result<Self> {
+        match repr {
+            PayloadRepr::Compact(word) => Ok(Self::Compact(word)),
+            PayloadRepr::Extended(bytes) => Self::new(&bytes),
+        }
+    }
+}
+
+impl From<Payload> for PayloadRepr {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Compact(word) => Self::Compact(word),
+            Payload::Extended(bytes) => Self::Extended(bytes),
+        }
+    }
+}
+
 /// A single instruction of movement for an Agent
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Instruction {
     /// The node affected by the instruction
     pub node: PeripheralKey,
     /// The timespan this action lasts for
     pub lasts_for_ms: u32,
     /// The arbitrary data to send to the agent
-    pub instructions: [u8; 4],
+    pub payload: Payload,
+}
+
+impl Instruction {
+    /// Creates an instruction with a compact four byte payload
+    pub fn new(node: PeripheralKey, lasts_for_ms: u32, payload: [u8; 4]) -> Self {
+        Self {
+            node,
+            lasts_for_ms,
+            payload: Payload::Compact(payload),
+        }
+    }
+
+    /// Creates an instruction with a payload of any length up to `MAX_PAYLOAD_LEN`
+    pub fn with_bytes(node: PeripheralKey, lasts_for_ms: u32, payload: &[u8]) -> Result<Self> {
+        Ok(Self {
+            node,
+            lasts_for_ms,
+            payload: Payload::new(payload)?,
+        })
+    }
+
+    /// Encodes the instruction in the current binary version. An extended payload built directly
+    /// past `MAX_PAYLOAD_LEN` can't be encoded
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let payload = match &self.payload {
+            Payload::Compact(word) => WirePayload::Compact { word: *word },
+            Payload::Extended(bytes) if bytes.len() > MAX_PAYLOAD_LEN => {
+                return Err(InstructionError::PayloadTooLarge(bytes.len()))
+            }
+            Payload::Extended(bytes) => WirePayload::Extended {
+                len: bytes.len() as u16,
+                bytes: bytes.clone(),
+            },
+        };
+
+        Ok(WireInstruction {
+            version: INSTRUCTION_VERSION,
+            node: self.node.data().as_ffi(),
+            lasts_for_ms: self.lasts_for_ms,
+            payload,
+        }
+        .to_bytes()?)
+    }
+
+    /// Decodes a binary instruction from the start of `bytes`, returning it alongside the number
+    /// of bytes it took up
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize)> {
+        if let Some(&version) = bytes.first() {
+            if version != INSTRUCTION_VERSION {
+                return Err(InstructionError::UnsupportedVersion(version));
+            }
+        }
+
+        let ((rest, _), wire) = WireInstruction::from_bytes((bytes, 0))?;
+        let payload = match wire.payload {
+            WirePayload::Compact { word } => Payload::Compact(word),
+            WirePayload::Extended { bytes, .. } => Payload::new(&bytes)?,
+        };
+
+        Ok((
+            Self {
+                node: PeripheralKey::from(KeyData::from_ffi(wire.node)),
+                lasts_for_ms: wire.lasts_for_ms,
+                payload,
+            },
+            bytes.len() - rest.len(),
+        ))
+    }
+}
+
+/// The binary layout of an instruction
+#[derive(Debug, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+struct WireInstruction {
+    /// The encoding version
+    version: u8,
+    /// The node's key
+    node: u64,
+    /// The timespan this action lasts for
+    lasts_for_ms: u32,
+    /// The data to send
+    payload: WirePayload,
+}
+
+/// The binary layout of an instruction's payload
+#[derive(Debug, DekuRead, DekuWrite)]
+#[deku(id_type = "u8", endian = "endian", ctx = "endian: deku::ctx::Endian")]
+enum WirePayload {
+    /// A four byte command
+    #[deku(id = 0)]
+    Compact {
+        /// The command
+        word: [u8; 4],
+    },
+    /// A length prefixed command
+    #[deku(id = 1)]
+    Extended {
+        /// Length of the command in bytes
+        #[deku(assert = "usize::from(*len) <= MAX_PAYLOAD_LEN")]
+        len: u16,
+        /// The command
+        #[deku(count = "len")]
+        bytes: Vec<u8>,
+    },
+}
+
+/// Identifies a plan within a hivemind session, so feedback can be matched with the plan it's on
+pub type PlanId = u64;
+
+/// An instruction set from a hivemind, performed one instruction after another
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    /// The plan's id within its session
+    pub id: PlanId,
+    /// The instructions to perform, in order
+    pub instructions: Vec<Instruction>,
+}
+
+impl Plan {
+    /// Creates a plan from its id and instructions
+    pub fn new(id: PlanId, instructions: Vec<Instruction>) -> Self {
+        Self { id, instructions }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Instruction;
+    use slotmap::SlotMap;
+
+    use crate::body::{actuators::pwm_bytes, PeripheralKey};
+
+    use super::{Instruction, InstructionError, Payload, MAX_PAYLOAD_LEN};
+
+    #[test]
+    fn compact_instructions_stay_small() {
+        let instruction = Instruction::new(PeripheralKey::default(), 10, [1, 2, 3, 4]);
+        let bytes = instruction.to_bytes().unwrap();
+
+        assert_eq!(bytes.len(), 18);
+        assert_eq!(Instruction::from_bytes(&bytes).unwrap(), (instruction, 18));
+    }
 
     #[test]
-    fn instruction_has_no_padding() {
-        let size = std::mem::size_of::<Instruction>();
-        let align = std::mem::align_of::<Instruction>();
+    fn pwm_instruction_round_trips() {
+        let mut keys = SlotMap::<PeripheralKey, ()>::with_key();
+        let node = keys.insert(());
+        let instruction = Instruction::with_bytes(node, 20, &pwm_bytes(50.0, 0.075)).unwrap();
+
+        let bytes = instruction.to_bytes().unwrap();
+        let (decoded, _) = Instruction::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, instruction);
+        assert_eq!(decoded.payload.as_bytes().len(), 16);
+
+        let json = serde_json::to_string(&instruction).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Instruction>(&json).unwrap(),
+            instruction
+        );
+    }
+
+    #[test]
+    fn oversized_and_unknown_instructions_are_rejected() {
+        let huge = vec![0; MAX_PAYLOAD_LEN + 1];
+        assert!(matches!(
+            Payload::new(&huge),
+            Err(InstructionError::PayloadTooLarge(_))
+        ));
+
+        let json = format!(r#"{{"Extended":{huge:?}}}"#);
+        assert!(serde_json::from_str::<Payload>(&json).is_err());
+
+        // 65,537 bytes would wrap the length prefix round to 1 if it weren't checked
+        let built = Instruction {
+            payload: Payload::Extended(vec![0; u16::MAX as usize + 2]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            built.to_bytes(),
+            Err(InstructionError::PayloadTooLarge(65_537))
+        ));
 
-        assert_eq!(size, 16);
-        assert_eq!(align, 4);
+        let mut bytes = Instruction::default().to_bytes().unwrap();
+        bytes[0] = 2;
+        assert!(matches!(
+            Instruction::from_bytes(&bytes),
+            Err(InstructionError::UnsupportedVersion(2))
+        ));
     }
 }
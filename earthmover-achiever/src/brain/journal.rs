@@ -0,0 +1,426 @@
+//! A durable on-disk log of collected points, so data survives a crash or power loss and can be
+//! uploaded to the hivemind later. The log is split into numbered segment files; once a segment is
+//! sealed and the hivemind acknowledges it, it's pruned
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use deku::prelude::*;
+
+/// How large a segment grows before a new one is started, in bytes
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1 << 20;
+/// How large the whole log may grow before the oldest segments are evicted, in bytes
+pub const DEFAULT_MAX_SIZE: u64 = 64 << 20;
+/// How many points are appended between syncs to disk by default
+pub const DEFAULT_SYNC_EVERY: usize = 256;
+
+/// The extension of segment files
+const SEGMENT_EXTENSION: &str = "seg";
+/// The file holding the newest acknowledged segment
+const ACKED_FILE: &str = "acked";
+
+/// A single point in a segment, checksummed so a torn write can be told apart from real data
+#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+struct Record {
+    /// How many values the point has
+    len: u32,
+    /// FNV-1a over the values' bytes
+    checksum: u32,
+    /// The point's values
+    #[deku(count = "len")]
+    values: Vec<f32>,
+}
+
+impl Record {
+    /// Wraps a point in a record
+    fn new(values: &[f32]) -> Self {
+        Self {
+            len: values.len() as u32,
+            checksum: checksum(values),
+            values: values.to_vec(),
+        }
+    }
+
+    /// Returns true if the values match the checksum
+    fn is_intact(&self) -> bool {
+        self.checksum == checksum(&self.values)
+    }
+}
+
+/// FNV-1a over the big endian bytes of some values
+fn checksum(values: &[f32]) -> u32 {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+/// Converts a deku error into an IO error
+fn deku_to_io(err: DekuError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Reads every intact point from the start of a segment's bytes, along with how many bytes they
+/// take up. Anything after the first torn or corrupt record is ignored
+fn read_records(bytes: Vec<u8>) -> (Vec<Vec<f32>>, u64) {
+    let len = bytes.len() as u64;
+    let mut cursor = Cursor::new(bytes);
+    let mut points = vec![];
+    let mut valid = 0;
+
+    while cursor.position() < len {
+        match Record::from_reader((&mut cursor, 0)) {
+            Ok((_, record)) if record.is_intact() => {
+                points.push(record.values);
+                valid = cursor.position();
+            }
+            _ => break,
+        }
+    }
+    (points, valid)
+}
+
+/// A segment file in the log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// The segment's number, increasing with age
+    pub id: u64,
+    /// How many bytes it holds
+    pub len: u64,
+}
+
+/// An append-only log of points on disk
+pub struct Journal {
+    /// The directory holding the segments
+    dir: PathBuf,
+    /// How large a segment grows before a new one is started
+    segment_size: u64,
+    /// How large the whole log may grow
+    max_size: u64,
+    /// Every segment oldest first, the last being the one appended to
+    segments: Vec<Segment>,
+    /// The segment being appended to
+    writer: BufWriter<File>,
+    /// How many points are appended between syncs to disk
+    sync_every: usize,
+    /// How many points have been appended since the last sync
+    unsynced: usize,
+    /// The newest segment the hivemind has acknowledged
+    acked: Option<u64>,
+    /// How many unacknowledged segments were evicted to stay under the size cap
+    evicted: u64,
+}
+
+impl Journal {
+    /// Opens the log in `dir`, creating it if needed. Any torn write left at the end of a segment
+    /// by a crash is cut off, and appending carries on in the newest segment
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut ids = vec![];
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+
+        let mut segments = vec![];
+        for id in ids {
+            let path = segment_path(&dir, id);
+            let (_, len) = read_records(std::fs::read(&path)?);
+            let file = OpenOptions::new().write(true).open(&path)?;
+            if file.metadata()?.len() != len {
+                file.set_len(len)?;
+                file.sync_all()?;
+            }
+            segments.push(Segment { id, len });
+        }
+
+        let acked = match std::fs::read_to_string(dir.join(ACKED_FILE)) {
+            Ok(acked) => acked.trim().parse().ok(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let active = match segments.last() {
+            Some(segment) => segment.id,
+            None => {
+                let id = acked.map_or(0, |acked: u64| acked + 1);
+                segments.push(Segment { id, len: 0 });
+                id
+            }
+        };
+
+        Ok(Self {
+            writer: open_segment(&dir, active)?,
+            sync_every: DEFAULT_SYNC_EVERY,
+            unsynced: 0,
+            dir,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            max_size: DEFAULT_MAX_SIZE,
+            segments,
+            acked,
+            evicted: 0,
+        })
+    }
+
+    /// Sets how large a segment grows before a new one is started, in bytes
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size.max(1);
+        self
+    }
+
+    /// Sets how large the whole log may grow before the oldest segments are evicted, in bytes
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets how many points are appended between syncs to disk, bounding how many a power loss
+    /// can take
+    pub fn with_sync_every(mut self, points: usize) -> Self {
+        self.sync_every = points.max(1);
+        self
+    }
+
+    /// Appends a point, syncing once enough have been appended since the last sync and starting
+    /// a new segment once the current one is full. The oldest segments are evicted, acknowledged
+    /// or not, if the log grows past its size cap
+    pub fn append(&mut self, point: &[f32]) -> std::io::Result<()> {
+        let bytes = Record::new(point).to_bytes().map_err(deku_to_io)?;
+        self.writer.write_all(&bytes)?;
+        self.unsynced += 1;
+        if self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        if let Some(active) = self.segments.last_mut() {
+            active.len += bytes.len() as u64;
+            if active.len >= self.segment_size {
+                self.rotate()?;
+            }
+        }
+        self.enforce_cap()
+    }
+
+    /// Flushes every appended point to disk
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Seals the current segment so it can be uploaded, and starts a new one. Does nothing if the
+    /// current segment is empty
+    pub fn rotate(&mut self) -> std::io::Result<()> {
+        let Some(&active) = self.segments.last() else {
+            return Ok(());
+        };
+        if active.len == 0 {
+            return Ok(());
+        }
+
+        self.sync()?;
+        let id = active.id + 1;
+        self.writer = open_segment(&self.dir, id)?;
+        self.segments.push(Segment { id, len: 0 });
+        Ok(())
+    }
+
+    /// Every sealed segment the hivemind hasn't acknowledged, oldest first
+    pub fn pending(&self) -> Vec<Segment> {
+        let sealed = self.segments.len().saturating_sub(1);
+        self.segments[..sealed]
+            .iter()
+            .filter(|segment| self.acked.is_none_or(|acked| segment.id > acked))
+            .copied()
+            .collect()
+    }
+
+    /// Reads every point in a segment
+    pub fn read_segment(&self, id: u64) -> std::io::Result<Vec<Vec<f32>>> {
+        let (points, _) = read_records(std::fs::read(segment_path(&self.dir, id))?);
+        Ok(points)
+    }
+
+    /// Records that the hivemind has every segment up to and including `id`, and prunes them. The
+    /// segment being appended to is never pruned
+    pub fn acknowledge(&mut self, id: u64) -> std::io::Result<()> {
+        let id = self.acked.map_or(id, |acked| acked.max(id));
+
+        let tmp = self.dir.join(format!("{ACKED_FILE}.tmp"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(id.to_string().as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, self.dir.join(ACKED_FILE))?;
+        self.acked = Some(id);
+
+        while self.segments.len() > 1 && self.segments[0].id <= id {
+            self.remove_oldest()?;
+        }
+        Ok(())
+    }
+
+    /// The newest acknowledged segment
+    pub fn acknowledged(&self) -> Option<u64> {
+        self.acked
+    }
+
+    /// Every segment oldest first, the last being the one appended to
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// How many bytes the log takes up
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.len).sum()
+    }
+
+    /// How many unacknowledged segments were evicted to stay under the size cap
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Evicts the oldest segments until the log is under its size cap
+    fn enforce_cap(&mut self) -> std::io::Result<()> {
+        while self.segments.len() > 1 && self.size() > self.max_size {
+            let oldest = self.segments[0].id;
+            if self.acked.is_none_or(|acked| oldest > acked) {
+                self.evicted += 1;
+            }
+            self.remove_oldest()?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the oldest segment
+    fn remove_oldest(&mut self) -> std::io::Result<()> {
+        let oldest = self.segments.remove(0);
+        match std::fs::remove_file(segment_path(&self.dir, oldest.id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where a segment is stored
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{id:020}.{SEGMENT_EXTENSION}"))
+}
+
+/// Opens a segment for appending, creating it if needed
+fn open_segment(dir: &Path, id: u64) -> std::io::Result<BufWriter<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, id))?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{segment_path, Journal};
+
+    fn fresh_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("earthmover_journal_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn recovers_from_a_torn_write() {
+        let dir = fresh_dir("torn_write");
+        {
+            let mut journal = Journal::open(&dir).unwrap();
+            journal.append(&[1.0, 2.0]).unwrap();
+            journal.append(&[3.0, 4.0]).unwrap();
+            journal.sync().unwrap();
+        }
+
+        // A crash partway through writing a third point
+        let mut segment = std::fs::OpenOptions::new()
+            .append(true)
+            .open(segment_path(&dir, 0))
+            .unwrap();
+        segment.write_all(&[0, 0, 0, 2, 1, 2]).unwrap();
+
+        let mut journal = Journal::open(&dir).unwrap();
+        journal.append(&[5.0, 6.0]).unwrap();
+        journal.rotate().unwrap();
+
+        let points = journal.read_segment(0).unwrap();
+        assert_eq!(points, vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn acknowledged_segments_are_pruned() {
+        let dir = fresh_dir("acknowledged");
+        let mut journal = Journal::open(&dir).unwrap().with_segment_size(1);
+        journal.append(&[1.0]).unwrap();
+        journal.append(&[2.0]).unwrap();
+
+        let pending: Vec<_> = journal.pending().iter().map(|s| s.id).collect();
+        assert_eq!(pending, vec![0, 1]);
+
+        journal.acknowledge(0).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(journal.acknowledged(), Some(0));
+        assert_eq!(journal.pending().len(), 1);
+        assert_eq!(journal.read_segment(1).unwrap(), vec![vec![2.0]]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn oldest_segments_are_evicted_past_the_cap() {
+        let dir = fresh_dir("evicted");
+        // Each single value record is 12 bytes
+        let mut journal = Journal::open(&dir)
+            .unwrap()
+            .with_segment_size(12)
+            .with_max_size(24);
+        for value in 0..4 {
+            journal.append(&[value as f32]).unwrap();
+        }
+
+        assert!(journal.size() <= 24);
+        assert_eq!(journal.evicted(), 2);
+        assert_eq!(journal.pending()[0].id, 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn points_reach_disk_every_few_appends() {
+        let dir = fresh_dir("synced");
+        let mut journal = Journal::open(&dir).unwrap().with_sync_every(2);
+        let on_disk = || std::fs::metadata(segment_path(&dir, 0)).unwrap().len();
+
+        // Each single value record is 12 bytes
+        journal.append(&[1.0]).unwrap();
+        assert_eq!(on_disk(), 0);
+        journal.append(&[2.0]).unwrap();
+        assert_eq!(on_disk(), 24);
+        assert!(journal.pending().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
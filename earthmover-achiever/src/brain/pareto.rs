@@ -0,0 +1,168 @@
+//! Pareto fronts of instruction sets scored against several objectives at once, so conflicting
+//! objectives can be traded off when a plan is chosen rather than when it's trained
+
+use serde::{Deserialize, Serialize};
+
+use super::instruction::{Instruction, Plan, PlanId};
+
+/// An instruction set alongside its score on each objective, higher is better
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    /// The plan's id within its session, given by the hivemind
+    #[serde(default)]
+    pub id: PlanId,
+    /// The score on each objective
+    pub objectives: Vec<f64>,
+    /// The instructions that achieve these scores
+    pub instructions: Vec<Instruction>,
+}
+
+impl Candidate {
+    /// Creates a candidate from its objective scores and instructions
+    pub fn new(objectives: Vec<f64>, instructions: Vec<Instruction>) -> Self {
+        Self {
+            id: 0,
+            objectives,
+            instructions,
+        }
+    }
+
+    /// The candidate's instructions as a plan under its id
+    pub fn plan(&self) -> Plan {
+        Plan::new(self.id, self.instructions.clone())
+    }
+
+    /// Whether this candidate is at least as good on every objective and better on one.
+    /// Candidates with different numbers of objectives never dominate each other
+    pub fn dominates(&self, other: &Self) -> bool {
+        if self.objectives.len() != other.objectives.len() {
+            return false;
+        }
+
+        let pairs = self.objectives.iter().zip(&other.objectives);
+        pairs.clone().all(|(ours, theirs)| ours >= theirs)
+            && pairs.clone().any(|(ours, theirs)| ours > theirs)
+    }
+
+    /// The weighted sum of the objectives. Missing weights count as 0
+    pub fn weighted(&self, weights: &[f64]) -> f64 {
+        self.objectives
+            .iter()
+            .zip(weights)
+            .map(|(objective, weight)| objective * weight)
+            .sum()
+    }
+}
+
+/// The candidates no other candidate dominates
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParetoFront {
+    /// The non-dominated candidates
+    candidates: Vec<Candidate>,
+}
+
+impl FromIterator<Candidate> for ParetoFront {
+    fn from_iter<T: IntoIterator<Item = Candidate>>(iter: T) -> Self {
+        let mut front = Self::default();
+        for candidate in iter {
+            front.insert(candidate);
+        }
+        front
+    }
+}
+
+impl ParetoFront {
+    /// Adds a candidate unless it's dominated, removing any candidates it dominates. Returns
+    /// whether it was added
+    pub fn insert(&mut self, candidate: Candidate) -> bool {
+        if self
+            .candidates
+            .iter()
+            .any(|existing| existing.dominates(&candidate) || *existing == candidate)
+        {
+            return false;
+        }
+
+        self.candidates
+            .retain(|existing| !candidate.dominates(existing));
+        self.candidates.push(candidate);
+        true
+    }
+
+    /// The candidates on the front
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Gives every candidate an id, in front order
+    pub fn number(&mut self, mut next_id: impl FnMut() -> PlanId) {
+        for candidate in &mut self.candidates {
+            candidate.id = next_id();
+        }
+    }
+
+    /// How many candidates are on the front
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Whether the front has no candidates
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Picks the candidate with the highest weighted sum of objectives, the trade-off being set by
+    /// the weights
+    pub fn choose(&self, weights: &[f64]) -> Option<&Candidate> {
+        self.candidates
+            .iter()
+            .max_by(|a, b| a.weighted(weights).total_cmp(&b.weighted(weights)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Candidate, ParetoFront};
+
+    /// A candidate with no instructions
+    fn scored(objectives: &[f64]) -> Candidate {
+        Candidate::new(objectives.to_vec(), vec![])
+    }
+
+    #[test]
+    fn front_keeps_only_non_dominated_candidates() {
+        let front = [
+            scored(&[1.0, 1.0]),
+            scored(&[3.0, 1.0]),
+            scored(&[1.0, 3.0]),
+            scored(&[2.0, 2.0]),
+            scored(&[2.0, 0.5]),
+        ]
+        .into_iter()
+        .collect::<ParetoFront>();
+
+        assert_eq!(
+            front.candidates(),
+            &[
+                scored(&[3.0, 1.0]),
+                scored(&[1.0, 3.0]),
+                scored(&[2.0, 2.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn weights_choose_the_trade_off() {
+        let front = [
+            scored(&[3.0, 1.0]),
+            scored(&[1.0, 3.0]),
+            scored(&[2.0, 2.1]),
+        ]
+        .into_iter()
+        .collect::<ParetoFront>();
+
+        assert_eq!(front.choose(&[1.0, 0.0]), Some(&scored(&[3.0, 1.0])));
+        assert_eq!(front.choose(&[0.1, 1.0]), Some(&scored(&[1.0, 3.0])));
+        assert_eq!(front.choose(&[1.0, 1.0]), Some(&scored(&[2.0, 2.1])));
+    }
+}
@@ -0,0 +1,257 @@
+//! Checking an instruction plan against a Body before anything moves
+
+use std::{fmt::Display, time::Duration};
+
+use thiserror::Error;
+
+use crate::body::{Body, PeripheralKey};
+
+use super::instruction::Instruction;
+
+/// Something wrong with a single instruction in a plan
+#[derive(Clone, Debug, PartialEq)]
+pub enum Issue {
+    /// The instruction's node isn't in the body
+    MissingNode,
+    /// The instruction's node is an input
+    NotAnOutput,
+    /// The output or its limits won't accept the payload
+    InvalidPayload(String),
+    /// The plan runs past its time budget by the end of this instruction
+    OverBudget {
+        /// When the instruction would finish, from the start of the plan
+        ends_at: Duration,
+        /// The plan's budget
+        budget: Duration,
+    },
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingNode => write!(f, "node is not in the body"),
+            Self::NotAnOutput => write!(f, "node is not an output"),
+            Self::InvalidPayload(reason) => write!(f, "invalid payload: {reason}"),
+            Self::OverBudget { ends_at, budget } => {
+                write!(f, "ends at {ends_at:?}, past the {budget:?} budget")
+            }
+        }
+    }
+}
+
+/// An issue with the instruction at `index` in a plan
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// Where the instruction is in the plan
+    pub index: usize,
+    /// The instruction's node
+    pub node: PeripheralKey,
+    /// What's wrong with it
+    pub issue: Issue,
+}
+
+/// The result of validating a plan
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// Every problem found, in plan order
+    pub problems: Vec<Problem>,
+    /// How long the whole plan takes when run one instruction after another
+    pub total_duration: Duration,
+    /// How many instructions from the start of the plan have no problems
+    pub valid_prefix: usize,
+}
+
+impl Report {
+    /// Returns true if the plan has no problems
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} problem(s), {} valid instruction(s) before the first",
+            self.problems.len(),
+            self.valid_prefix
+        )?;
+        for problem in &self.problems {
+            write!(f, "; #{}: {}", problem.index, problem.issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// What to do with a plan that has problems
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Run nothing
+    #[default]
+    Reject,
+    /// Run the instructions before the first problem
+    ValidPrefix,
+}
+
+/// A plan was rejected by validation
+#[derive(Debug, Error)]
+#[error("Plan rejected: {0}")]
+pub struct PlanRejected(pub Report);
+
+/// Validates plans against a body, with an optional time budget
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Validator {
+    /// The longest a plan may take
+    budget: Option<Duration>,
+    /// What to do with a plan that has problems
+    policy: Policy,
+}
+
+impl Validator {
+    /// Creates a validator with no budget that rejects any plan with problems
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the longest a plan may take
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sets what to do with a plan that has problems
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Checks every instruction in a plan against the body without writing anything
+    pub fn validate(&self, body: &Body, plan: &[Instruction]) -> Report {
+        let mut report = Report::default();
+
+        for (index, instruction) in plan.iter().enumerate() {
+            report.total_duration += Duration::from_millis(instruction.lasts_for_ms as u64);
+
+            let issue = match body.get_by_id(instruction.node) {
+                None => Some(Issue::MissingNode),
+                Some(node) if !node.peripheral.is_output() => Some(Issue::NotAnOutput),
+                Some(node) => node
+                    .check(instruction.payload.as_bytes())
+                    .err()
+                    .map(|err| Issue::InvalidPayload(err.to_string())),
+            };
+            let over_budget = self
+                .budget
+                .filter(|budget| report.total_duration > *budget)
+                .map(|budget| Issue::OverBudget {
+                    ends_at: report.total_duration,
+                    budget,
+                });
+
+            for issue in issue.into_iter().chain(over_budget) {
+                report.problems.push(Problem {
+                    index,
+                    node: instruction.node,
+                    issue,
+                });
+            }
+        }
+
+        report.valid_prefix = report
+            .problems
+            .first()
+            .map_or(plan.len(), |problem| problem.index);
+        report
+    }
+
+    /// Validates a plan and returns the part of it the policy allows to run
+    pub fn apply<'plan>(
+        &self,
+        body: &Body,
+        plan: &'plan [Instruction],
+    ) -> Result<(&'plan [Instruction], Report), PlanRejected> {
+        let report = self.validate(body, plan);
+        match self.policy {
+            Policy::Reject if !report.is_valid() => Err(PlanRejected(report)),
+            _ => Ok((&plan[..report.valid_prefix], report)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use slotmap::KeyData;
+
+    use crate::{
+        body::{
+            actuators::{DcMotor, Payload, Speed},
+            outputs::virtual_outputs::RecordingOutput,
+            Body, Peripheral, PeripheralKey,
+        },
+        brain::instruction::Instruction,
+    };
+
+    use super::{Issue, Policy, Validator};
+
+    fn motor() -> Peripheral {
+        let motor = DcMotor::new(
+            RecordingOutput::new(),
+            RecordingOutput::new(),
+            RecordingOutput::new(),
+        );
+        Peripheral::Output(Box::new(motor))
+    }
+
+    #[test]
+    fn report_lists_every_problem() {
+        let body = Body::builder()
+            .with_node(motor())
+            .with_node(Peripheral::constant_input(1.0))
+            .build();
+        let (motor, sensor) = (body.root[0], body.root[1]);
+        let missing = PeripheralKey::from(KeyData::from_ffi(u64::MAX));
+
+        let plan = [
+            Instruction::new(motor, 10, Speed(0.5).encode().unwrap()),
+            Instruction::new(motor, 10, 2.0f32.to_be_bytes()),
+            Instruction::new(sensor, 10, [0; 4]),
+            Instruction::new(missing, 10, [0; 4]),
+        ];
+        let report = Validator::new()
+            .with_budget(Duration::from_millis(30))
+            .validate(&body, &plan);
+
+        let issues: Vec<_> = report
+            .problems
+            .iter()
+            .map(|p| (p.index, &p.issue))
+            .collect();
+        assert!(matches!(issues[0], (1, Issue::InvalidPayload(_))));
+        assert_eq!(issues[1], (2, &Issue::NotAnOutput));
+        assert_eq!(issues[2], (3, &Issue::MissingNode));
+        assert!(matches!(issues[3], (3, Issue::OverBudget { .. })));
+        assert_eq!(report.valid_prefix, 1);
+        assert_eq!(report.total_duration, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn policy_rejects_or_trims_the_plan() {
+        let body = Body::builder().with_node(motor()).build();
+        let motor = body.root[0];
+        let plan = [
+            Instruction::new(motor, 10, Speed(0.5).encode().unwrap()),
+            Instruction::new(motor, 10, f32::NAN.to_be_bytes()),
+        ];
+
+        assert!(Validator::new().apply(&body, &plan).is_err());
+
+        let (runnable, report) = Validator::new()
+            .with_policy(Policy::ValidPrefix)
+            .apply(&body, &plan)
+            .unwrap();
+        assert_eq!(runnable.len(), 1);
+        assert!(!report.is_valid());
+    }
+}
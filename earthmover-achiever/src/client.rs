@@ -0,0 +1,237 @@
+//! Clients for talking to a hivemind, whether it lives on a remote server or in the same process
+
+use std::future::Future;
+
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
+use uuid::Uuid;
+
+use crate::{
+    body::safety::Feeder,
+    brain::{feedback::ExecutionFeedback, instruction::Plan, pareto::ParetoFront},
+    goals::{expr::Expr, ChannelGoal, GoalError},
+    protocol::{AhtpRequest, AhtpResponse},
+};
+
+/// Any error that may come from talking to a hivemind
+#[derive(thiserror::Error, Debug)]
+pub enum ClientError {
+    #[error("HTTP Error: {0}")]
+    /// The initiation request failed
+    Http(#[from] reqwest::Error),
+    #[error("WebSocket Error: {0}")]
+    /// The websocket connection failed
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error("Serialization Error: {0}")]
+    /// A message could not be (de)serialized
+    Serde(#[from] serde_json::Error),
+    #[error("Hivemind rejected the request: {0}")]
+    /// The hivemind responded with an error
+    Rejected(String),
+    #[error("{0}")]
+    /// The hivemind rejected the goals sent
+    Goals(#[from] GoalError),
+    #[error("{len} values can't be split into points of {dims} dimensions")]
+    /// A buffer sent doesn't hold a whole number of points of the session's dimensions
+    Misshapen {
+        /// How many values were in the buffer
+        len: usize,
+        /// The session's dimensions
+        dims: usize,
+    },
+    #[error("Unexpected response from the hivemind")]
+    /// The hivemind responded with something we weren't waiting for
+    UnexpectedResponse,
+    #[error("Connection to the hivemind was closed")]
+    /// The hivemind closed the connection
+    Closed,
+}
+
+/// Result type for hivemind client operations
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// The interface an agent uses to plan with a hivemind. Implemented both by the remote websocket
+/// client and by hiveminds embedded in the same process, so the agent loop doesn't care where its
+/// planning happens
+pub trait Hivemind {
+    /// Sets the dimensionality of the points that will be sent. Fails if the hivemind can't
+    /// simulate points that wide
+    fn set_dims(&mut self, dims: usize) -> impl Future<Output = Result<()>> + Send;
+    /// Sets the goals of the session, one per data channel. Fails without changing anything if
+    /// any goal is invalid
+    fn set_goals(&mut self, goals: Vec<ChannelGoal>) -> impl Future<Output = Result<()>> + Send;
+    /// Sets the reward of the session to an expression over each point's dimensions
+    fn set_reward(&mut self, reward: Expr) -> impl Future<Output = Result<()>> + Send;
+    /// Sends a flat buffer of collected data, every `dims` elements are considered a point
+    fn send_data(&mut self, buf: &[f32]) -> impl Future<Output = Result<()>> + Send;
+    /// Trains on all data sent so far and returns the best instruction set found
+    fn train(&mut self) -> impl Future<Output = Result<Plan>> + Send;
+    /// Trains on all data sent so far and returns every instruction set no other beats on all
+    /// objectives, so the trade-off between them can be chosen later
+    fn train_front(&mut self) -> impl Future<Output = Result<ParetoFront>> + Send;
+    /// Reports how an instruction set went when it was performed, matched to it by the plan id
+    /// in the feedback
+    fn report(&mut self, feedback: ExecutionFeedback) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// A hivemind running on a remote server, communicated with over AHTP
+pub struct RemoteHivemind {
+    /// The session ID given to us on initiation
+    id: Uuid,
+    /// How many values make up each point sent, once set
+    dims: usize,
+    /// Websocket write half
+    writer: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
+    /// Websocket read half
+    reader: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    /// Fed whenever the hivemind is heard from, if set
+    feeder: Option<Feeder>,
+}
+
+impl RemoteHivemind {
+    /// Initiates a session with the hivemind at `server`, describing the agent's body with a URDF
+    /// string, and connects to it over a websocket
+    pub async fn connect(server: &str, urdf: &str) -> Result<Self> {
+        let body_encoded = urlencoding::encode(urdf);
+        let connection = format!("http://{}/initiate?urdf={}", server, body_encoded);
+
+        let response = reqwest::get(connection)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let id = serde_json::from_str::<AhtpResponse>(&response)?
+            .get_init()
+            .ok_or(ClientError::UnexpectedResponse)?;
+
+        let ws_url = format!("ws://{}", server);
+        let (ws, _) = connect_async(ws_url).await?;
+        let (writer, reader) = ws.split();
+
+        let mut hivemind = Self {
+            id,
+            dims: 0,
+            writer,
+            reader,
+            feeder: None,
+        };
+        hivemind.send(AhtpRequest::Connect(id)).await?;
+
+        Ok(hivemind)
+    }
+
+    /// Feeds a watchdog every time the hivemind is heard from, so a stalled connection starves it
+    pub fn with_feeder(mut self, feeder: Feeder) -> Self {
+        self.feeder = Some(feeder);
+        self
+    }
+
+    /// The session ID of this connection
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Sends an AHTP message over the websocket
+    async fn send(&mut self, message: AhtpRequest) -> Result<()> {
+        let text = message.to_json_string()?;
+        self.writer.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// Waits for the next AHTP response from the hivemind
+    async fn recv(&mut self) -> Result<AhtpResponse> {
+        while let Some(msg) = self.reader.next().await {
+            let msg = msg?;
+            if let Some(feeder) = &self.feeder {
+                feeder.feed();
+            }
+            if let Message::Text(txt) = msg {
+                return Ok(serde_json::from_str(&txt)?);
+            }
+        }
+
+        Err(ClientError::Closed)
+    }
+}
+
+impl Hivemind for RemoteHivemind {
+    async fn set_dims(&mut self, dims: usize) -> Result<()> {
+        self.send(AhtpRequest::SetDims(dims)).await?;
+        match self.recv().await? {
+            AhtpResponse::DimsSet => {
+                self.dims = dims;
+                Ok(())
+            }
+            AhtpResponse::Rejected(err) => Err(ClientError::Rejected(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn set_goals(&mut self, goals: Vec<ChannelGoal>) -> Result<()> {
+        self.send(AhtpRequest::Goal(goals)).await?;
+        match self.recv().await? {
+            AhtpResponse::GoalsSet => Ok(()),
+            AhtpResponse::GoalError(err) => Err(err.into()),
+            AhtpResponse::Rejected(err) => Err(ClientError::Rejected(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn set_reward(&mut self, reward: Expr) -> Result<()> {
+        self.send(AhtpRequest::Reward(reward)).await?;
+        match self.recv().await? {
+            AhtpResponse::RewardSet => Ok(()),
+            AhtpResponse::Rejected(err) => Err(ClientError::Rejected(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn send_data(&mut self, buf: &[f32]) -> Result<()> {
+        if self.dims == 0 || !buf.len().is_multiple_of(self.dims) {
+            return Err(ClientError::Misshapen {
+                len: buf.len(),
+                dims: self.dims,
+            });
+        }
+
+        let points = buf.chunks_exact(self.dims).map(<[f32]>::to_vec).collect();
+        self.send(AhtpRequest::Send(points)).await?;
+        match self.recv().await? {
+            AhtpResponse::DataReceived => Ok(()),
+            AhtpResponse::Rejected(err) => Err(ClientError::Rejected(err)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn train(&mut self) -> Result<Plan> {
+        self.send(AhtpRequest::Train).await?;
+        match self.recv().await? {
+            AhtpResponse::Instruction(plan) => Ok(plan),
+            AhtpResponse::TrainError(err) | AhtpResponse::Rejected(err) => {
+                Err(ClientError::Rejected(err))
+            }
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn train_front(&mut self) -> Result<ParetoFront> {
+        self.send(AhtpRequest::TrainFront).await?;
+        match self.recv().await? {
+            AhtpResponse::Front(front) => Ok(front),
+            AhtpResponse::TrainError(err) | AhtpResponse::Rejected(err) => {
+                Err(ClientError::Rejected(err))
+            }
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    async fn report(&mut self, feedback: ExecutionFeedback) -> Result<()> {
+        self.send(AhtpRequest::Feedback(feedback)).await
+    }
+}
@@ -1,6 +1,14 @@
 //! Defining what a goal can be
 
+pub mod expr;
 pub mod multi_dim;
+pub mod spatial;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub use earthmover_derive::Rewardable;
 
 /// Goals will be a modular abstraction over anything that we want the agent to do. It will be
 /// modular as this REWARD can be anything from a boolean to a dynamic reward type. It could be the
@@ -12,20 +20,227 @@ pub mod multi_dim;
 /// Reward would be an f32 to represent the reading from the flame sensor. During data collection
 /// the simulation would become aware of areas of higher flame concentration and infer to go close
 /// to these sources.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Goal {
     /// Maximize this Reward's value
     Maximize,
     /// Minimize this Reward's value
     Minimize,
+    /// Hold this Reward's value at a target, such as a temperature set point
+    Target(f64),
+    /// Keep this Reward's value between a low and a high bound, inclusive
+    Range(f64, f64),
 }
 
 impl Goal {
-    /// Matches the goal and returns a score based on distance away from the point
-    pub fn match_against(&self, val: f64, ceiling: f64) -> f64 {
+    /// How far a value is from meeting the goal, ignoring anything within `tolerance`. Always 0
+    /// for goals with no bound to meet
+    pub fn violation(&self, val: f64, tolerance: f64) -> f64 {
+        let distance = match *self {
+            Self::Maximize | Self::Minimize => 0.0,
+            Self::Target(target) => (val - target).abs(),
+            Self::Range(lo, hi) => (lo - val).max(val - hi).max(0.0),
+        };
+        (distance - tolerance).max(0.0)
+    }
+
+    /// Scores a value against the goal, higher is better. Bounded goals score 0 when met and fall
+    /// off linearly with the violation
+    pub fn score(&self, val: f64, tolerance: f64) -> f64 {
+        match *self {
+            Self::Maximize => val,
+            Self::Minimize => -val,
+            Self::Target(_) | Self::Range(..) => -self.violation(val, tolerance),
+        }
+    }
+
+    /// The problem with the goal's own values, if any, for a goal at `index`
+    fn problem(&self, index: usize) -> Option<GoalProblem> {
         match *self {
-            Self::Maximize => -((ceiling - val).abs()),
-            Self::Minimize => (ceiling - val).abs(),
+            Self::Target(target) if target.is_nan() => Some(GoalProblem::NotANumber(index)),
+            Self::Range(lo, hi) if lo.is_nan() || hi.is_nan() => {
+                Some(GoalProblem::NotANumber(index))
+            }
+            Self::Range(lo, hi) if lo > hi => Some(GoalProblem::InvertedRange(index)),
+            _ => None,
+        }
+    }
+}
+
+/// The penalty added to a score for each hard goal that's violated, large enough to outweigh any
+/// soft goal
+pub const HARD_PENALTY: f64 = 1e6;
+
+/// Whether a goal may be traded off against others
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Enforcement {
+    /// Violations cost in proportion to how far off the value is
+    #[default]
+    Soft,
+    /// Any violation costs `HARD_PENALTY` on top, so it's never worth breaking
+    Hard,
+}
+
+/// A goal for a single data channel, as carried by an AHTP `Goal` message
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelGoal {
+    /// The index of the channel in each data point
+    pub index: usize,
+    /// What the channel should do
+    pub goal: Goal,
+    /// How much the goal counts towards the total reward
+    #[serde(default = "unit_weight")]
+    pub weight: f64,
+    /// How far off a bounded goal may be and still count as met
+    #[serde(default)]
+    pub tolerance: f64,
+    /// Whether the goal may be traded off against others
+    #[serde(default)]
+    pub enforcement: Enforcement,
+}
+
+/// The default weight of a goal
+fn unit_weight() -> f64 {
+    1.0
+}
+
+impl ChannelGoal {
+    /// Creates a soft goal of weight 1 with no tolerance
+    pub fn new(index: usize, goal: Goal) -> Self {
+        Self {
+            index,
+            goal,
+            weight: unit_weight(),
+            tolerance: 0.0,
+            enforcement: Enforcement::Soft,
+        }
+    }
+
+    /// Maximize a channel
+    pub fn maximize(index: usize) -> Self {
+        Self::new(index, Goal::Maximize)
+    }
+
+    /// Minimize a channel
+    pub fn minimize(index: usize) -> Self {
+        Self::new(index, Goal::Minimize)
+    }
+
+    /// Hold a channel at a target
+    pub fn target(index: usize, target: f64) -> Self {
+        Self::new(index, Goal::Target(target))
+    }
+
+    /// Keep a channel between two bounds
+    pub fn range(index: usize, lo: f64, hi: f64) -> Self {
+        Self::new(index, Goal::Range(lo, hi))
+    }
+
+    /// Sets how much the goal counts towards the total reward
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Sets how far off a bounded goal may be and still count as met
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.abs();
+        self
+    }
+
+    /// Makes the goal hard, so violating it is never worth it
+    pub fn hard(mut self) -> Self {
+        self.enforcement = Enforcement::Hard;
+        self
+    }
+
+    /// Returns true if the value meets the goal within tolerance
+    pub fn is_met(&self, val: f64) -> bool {
+        self.goal.violation(val, self.tolerance) == 0.0
+    }
+
+    /// Scores a channel's value, higher is better
+    pub fn score(&self, val: f64) -> f64 {
+        let penalty = match self.enforcement {
+            Enforcement::Hard if !self.is_met(val) => HARD_PENALTY,
+            _ => 0.0,
+        };
+        self.weight * self.goal.score(val, self.tolerance) - penalty
+    }
+}
+
+/// A single problem with a set of goals
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GoalProblem {
+    #[error("index {0} has more than one goal")]
+    /// Two goals were given for the same channel
+    DuplicateIndex(usize),
+    #[error("index {index} is out of range for {dims} dimensions")]
+    /// A goal was given for a channel past the end of each point
+    OutOfRange {
+        /// The goal's index
+        index: usize,
+        /// How many dimensions each point has
+        dims: usize,
+    },
+    #[error("index {0} is both maximized and minimized")]
+    /// A channel was given opposing goals
+    ConflictingDirections(usize),
+    #[error("index {0} has a range whose low bound is above its high bound")]
+    /// A channel's range can never be met
+    InvertedRange(usize),
+    #[error("index {0} has a target or bound that isn't a number")]
+    /// A channel's target or bound is NaN, so nothing compares against it
+    NotANumber(usize),
+}
+
+/// Every problem with a set of goals
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("Invalid goals: {}", .problems.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct GoalError {
+    /// The problems in the order they were found
+    pub problems: Vec<GoalProblem>,
+}
+
+/// Checks a set of goals for points of `dims` dimensions, collecting every problem rather than
+/// stopping at the first
+pub fn validate_goals(goals: &[ChannelGoal], dims: usize) -> Result<(), GoalError> {
+    let mut problems = vec![];
+    let mut by_index: BTreeMap<usize, Vec<Goal>> = BTreeMap::new();
+
+    for goal in goals {
+        problems.extend(goal.goal.problem(goal.index));
+        if goal.index >= dims {
+            problems.push(GoalProblem::OutOfRange {
+                index: goal.index,
+                dims,
+            });
+        } else {
+            by_index.entry(goal.index).or_default().push(goal.goal);
+        }
+    }
+
+    for (index, goals) in by_index {
+        if goals.len() > 1 {
+            let conflicting = goals.contains(&Goal::Maximize) && goals.contains(&Goal::Minimize);
+            problems.push(match conflicting {
+                true => GoalProblem::ConflictingDirections(index),
+                false => GoalProblem::DuplicateIndex(index),
+            });
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(GoalError { problems }),
+    }
+}
+
+impl From<(usize, bool)> for ChannelGoal {
+    fn from((index, maximize): (usize, bool)) -> Self {
+        match maximize {
+            true => Self::maximize(index),
+            false => Self::minimize(index),
         }
     }
 }
@@ -35,6 +250,16 @@ impl Goal {
 pub trait Rewardable: Send + Sync {
     /// Returns an implementation's 'reward value' as an f64
     fn to_reward(&self) -> f64;
+
+    /// Feeds a live reading of every channel into the reward. Ignored by rewards that don't
+    /// depend on readings
+    fn observe(&mut self, _reading: &[f64]) {}
+
+    /// The reward split into a score per objective, higher being better, which sum to the reward.
+    /// A single objective unless the reward is made of separate goals
+    fn objectives(&self) -> Vec<f64> {
+        vec![self.to_reward()]
+    }
 }
 
 impl Rewardable for f32 {
@@ -60,3 +285,70 @@ impl Rewardable for u8 {
         *self as f64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_goals, ChannelGoal, Goal, GoalProblem, HARD_PENALTY};
+
+    #[test]
+    fn bounded_goals_score_their_violation() {
+        assert_eq!(Goal::Target(22.0).score(25.0, 1.0), -2.0);
+        assert_eq!(Goal::Range(0.3, 0.5).score(0.4, 0.0), 0.0);
+        assert!((Goal::Range(0.3, 0.5).score(0.1, 0.0) + 0.2).abs() < 1e-9);
+        assert_eq!(Goal::Minimize.score(3.0, 0.0), -3.0);
+    }
+
+    #[test]
+    fn hard_goals_outweigh_soft_ones() {
+        let wall = ChannelGoal::range(1, 0.3, 0.5).hard();
+        let light = ChannelGoal::maximize(0).with_weight(2.0);
+
+        assert!(wall.is_met(0.5));
+        assert!((wall.score(0.6) + 0.1 + HARD_PENALTY).abs() < 1e-6);
+        assert_eq!(light.score(10.0), 20.0);
+        assert_eq!(ChannelGoal::from((3, false)), ChannelGoal::minimize(3));
+    }
+
+    #[test]
+    fn validation_lists_every_problem() {
+        let goals = [
+            ChannelGoal::maximize(0),
+            ChannelGoal::minimize(0),
+            ChannelGoal::target(1, 2.0),
+            ChannelGoal::range(1, 0.0, 1.0),
+            ChannelGoal::maximize(3),
+        ];
+
+        let err = validate_goals(&goals, 3).unwrap_err();
+        assert_eq!(
+            err.problems,
+            vec![
+                GoalProblem::OutOfRange { index: 3, dims: 3 },
+                GoalProblem::ConflictingDirections(0),
+                GoalProblem::DuplicateIndex(1),
+            ]
+        );
+        assert!(err.to_string().contains("index 1 has more than one goal"));
+        assert!(validate_goals(&goals[1..3], 3).is_ok());
+    }
+
+    #[test]
+    fn unmeetable_bounds_are_problems() {
+        let goals = [
+            ChannelGoal::range(0, 0.5, 0.3),
+            ChannelGoal::target(1, f64::NAN),
+            ChannelGoal::range(2, 0.0, f64::NAN),
+        ];
+
+        let err = validate_goals(&goals, 3).unwrap_err();
+        assert_eq!(
+            err.problems,
+            vec![
+                GoalProblem::InvertedRange(0),
+                GoalProblem::NotANumber(1),
+                GoalProblem::NotANumber(2),
+            ]
+        );
+        assert!(validate_goals(&[ChannelGoal::range(0, 0.3, 0.3)], 1).is_ok());
+    }
+}
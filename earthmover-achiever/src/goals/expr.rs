@@ -0,0 +1,738 @@
+//! A small expression language for rewards over a point's channels, such as
+//! `maximize(light) - 0.5*abs(temp - 22) + 10*within(dist, 0.3, 0.5)`
+//!
+//! Channels are referred to by name, or by index as `#3`. Expressions are type checked as they're
+//! parsed: conditions (`within`, comparisons, `and`, `or`, `not`) count as 1 when true and 0
+//! otherwise in arithmetic, but a number can't be used where a condition is expected
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Rewardable;
+
+/// What an expression evaluates to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// A real number
+    Number,
+    /// True or false
+    Condition,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number => write!(f, "number"),
+            Self::Condition => write!(f, "condition"),
+        }
+    }
+}
+
+/// A problem parsing or type checking an expression. Every variant carries the byte offset in the
+/// source it was found at
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ExprError {
+    #[error("Unexpected character {found:?} at {at}")]
+    /// A character that isn't part of the language
+    UnexpectedChar {
+        /// Where it was found
+        at: usize,
+        /// The character
+        found: char,
+    },
+    #[error("Expected {expected} at {at}, found {found}")]
+    /// A token that doesn't fit the grammar where it appears
+    UnexpectedToken {
+        /// Where it was found
+        at: usize,
+        /// What would have fit
+        expected: &'static str,
+        /// What was found
+        found: String,
+    },
+    #[error("Expected {expected} at {at}, found the end of the expression")]
+    /// The expression stopped early
+    UnexpectedEnd {
+        /// Where it ended
+        at: usize,
+        /// What would have fit
+        expected: &'static str,
+    },
+    #[error("Unknown function {name:?} at {at}")]
+    /// A call to a function that doesn't exist
+    UnknownFunction {
+        /// Where it was called
+        at: usize,
+        /// The function's name
+        name: String,
+    },
+    #[error("Unknown channel {name:?} at {at}")]
+    /// A name that isn't one of the channels
+    UnknownChannel {
+        /// Where it was used
+        at: usize,
+        /// The channel's name
+        name: String,
+    },
+    #[error("Channel #{index} at {at} is out of range, points only have {dims} channels")]
+    /// A channel index past the end of a point
+    ChannelOutOfRange {
+        /// Where it was used
+        at: usize,
+        /// The index used
+        index: usize,
+        /// How many channels there are
+        dims: usize,
+    },
+    #[error("{name} at {at} takes {expected} arguments, found {found}")]
+    /// A call with the wrong number of arguments
+    Arity {
+        /// Where it was called
+        at: usize,
+        /// The function's name
+        name: &'static str,
+        /// How many arguments it takes
+        expected: usize,
+        /// How many it was given
+        found: usize,
+    },
+    #[error("Expected a {expected} at {at}, found a {found}")]
+    /// A number used as a condition
+    TypeMismatch {
+        /// Where it was used
+        at: usize,
+        /// What was needed
+        expected: Type,
+        /// What was given
+        found: Type,
+    },
+}
+
+impl ExprError {
+    /// The byte offset in the source the problem was found at
+    pub fn at(&self) -> usize {
+        match *self {
+            Self::UnexpectedChar { at, .. }
+            | Self::UnexpectedToken { at, .. }
+            | Self::UnexpectedEnd { at, .. }
+            | Self::UnknownFunction { at, .. }
+            | Self::UnknownChannel { at, .. }
+            | Self::ChannelOutOfRange { at, .. }
+            | Self::Arity { at, .. }
+            | Self::TypeMismatch { at, .. } => at,
+        }
+    }
+
+    /// Renders the error under the source with a caret pointing at the problem
+    pub fn pointer(&self, source: &str) -> String {
+        let column = source[..self.at().min(source.len())].chars().count();
+        format!("{source}\n{}^ {self}", " ".repeat(column))
+    }
+}
+
+/// A binary operator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinOp {
+    /// `+`
+    Add,
+    /// `-`
+    Sub,
+    /// `*`
+    Mul,
+    /// `/`
+    Div,
+    /// `<`
+    Lt,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `>=`
+    Ge,
+    /// `and`
+    And,
+    /// `or`
+    Or,
+}
+
+impl BinOp {
+    /// The types the operator takes and gives
+    fn signature(self) -> (Type, Type) {
+        match self {
+            Self::Add | Self::Sub | Self::Mul | Self::Div => (Type::Number, Type::Number),
+            Self::Lt | Self::Gt | Self::Le | Self::Ge => (Type::Number, Type::Condition),
+            Self::And | Self::Or => (Type::Condition, Type::Condition),
+        }
+    }
+}
+
+/// A built in function
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Func {
+    /// `maximize(x)`, the value itself
+    Maximize,
+    /// `minimize(x)`, the negated value
+    Minimize,
+    /// `abs(x)`
+    Abs,
+    /// `min(a, b)`
+    Min,
+    /// `max(a, b)`
+    Max,
+    /// `target(x, t)`, the negated distance from `t`
+    Target,
+    /// `within(x, lo, hi)`, whether `x` is between `lo` and `hi` inclusive
+    Within,
+    /// `not(c)`
+    Not,
+    /// `if(c, a, b)`, `a` when `c` holds and `b` otherwise
+    If,
+}
+
+impl Func {
+    /// Every function, for looking them up by name
+    const ALL: [Self; 9] = [
+        Self::Maximize,
+        Self::Minimize,
+        Self::Abs,
+        Self::Min,
+        Self::Max,
+        Self::Target,
+        Self::Within,
+        Self::Not,
+        Self::If,
+    ];
+
+    /// The function's name in expressions
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Maximize => "maximize",
+            Self::Minimize => "minimize",
+            Self::Abs => "abs",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Target => "target",
+            Self::Within => "within",
+            Self::Not => "not",
+            Self::If => "if",
+        }
+    }
+
+    /// The types of the function's arguments and what it gives
+    fn signature(self) -> (&'static [Type], Type) {
+        use Type::*;
+        match self {
+            Self::Maximize | Self::Minimize | Self::Abs => (&[Number], Number),
+            Self::Min | Self::Max | Self::Target => (&[Number, Number], Number),
+            Self::Within => (&[Number, Number, Number], Condition),
+            Self::Not => (&[Condition], Condition),
+            Self::If => (&[Condition, Number, Number], Number),
+        }
+    }
+
+    /// Looks up a function by name
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|func| func.name() == name)
+    }
+}
+
+/// A parsed and type checked reward expression, with every channel resolved to its index. This is
+/// what's sent over AHTP
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    /// A constant
+    Number(f64),
+    /// A channel's value
+    Channel(usize),
+    /// Negation
+    Neg(Box<Expr>),
+    /// A binary operation
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// A function call
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parses an expression over channels with these names, in column order
+    pub fn parse(source: &str, channels: &[&str]) -> Result<Self, ExprError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: source.len(),
+            channels,
+        };
+
+        let (expr, _) = parser.expr()?;
+        match parser.peek() {
+            Some((at, token)) => Err(ExprError::UnexpectedToken {
+                at,
+                expected: "an operator or the end of the expression",
+                found: token.to_string(),
+            }),
+            None => Ok(expr),
+        }
+    }
+
+    /// The highest channel index the expression reads, if it reads any
+    pub fn max_channel(&self) -> Option<usize> {
+        match self {
+            Self::Number(_) => None,
+            Self::Channel(index) => Some(*index),
+            Self::Neg(inner) => inner.max_channel(),
+            Self::Binary(_, lhs, rhs) => lhs.max_channel().max(rhs.max_channel()),
+            Self::Call(_, args) => args.iter().filter_map(Self::max_channel).max(),
+        }
+    }
+
+    /// Evaluates the expression against a point's readings. Missing channels read as 0
+    pub fn eval(&self, reading: &[f64]) -> f64 {
+        let truth = |holds: bool| if holds { 1.0 } else { 0.0 };
+        match self {
+            Self::Number(value) => *value,
+            Self::Channel(index) => reading.get(*index).copied().unwrap_or_default(),
+            Self::Neg(inner) => -inner.eval(reading),
+            Self::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(reading), rhs.eval(reading));
+                match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                    BinOp::Lt => truth(lhs < rhs),
+                    BinOp::Gt => truth(lhs > rhs),
+                    BinOp::Le => truth(lhs <= rhs),
+                    BinOp::Ge => truth(lhs >= rhs),
+                    BinOp::And => truth(lhs != 0.0 && rhs != 0.0),
+                    BinOp::Or => truth(lhs != 0.0 || rhs != 0.0),
+                }
+            }
+            Self::Call(func, args) => {
+                let arg = |i: usize| args.get(i).map_or(0.0, |arg| arg.eval(reading));
+                match func {
+                    Func::Maximize => arg(0),
+                    Func::Minimize => -arg(0),
+                    Func::Abs => arg(0).abs(),
+                    Func::Min => arg(0).min(arg(1)),
+                    Func::Max => arg(0).max(arg(1)),
+                    Func::Target => -(arg(0) - arg(1)).abs(),
+                    Func::Within => truth(arg(1) <= arg(0) && arg(0) <= arg(2)),
+                    Func::Not => truth(arg(0) == 0.0),
+                    Func::If => match arg(0) != 0.0 {
+                        true => arg(1),
+                        false => arg(2),
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// A reward given by an expression over the agent's current readings
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExprReward {
+    /// The reward expression
+    expr: Expr,
+    /// The current reading of every channel
+    reading: Vec<f64>,
+}
+
+impl ExprReward {
+    /// Creates a reward from an expression, with every channel reading 0
+    pub fn new(expr: Expr) -> Self {
+        Self {
+            expr,
+            reading: vec![],
+        }
+    }
+
+    /// Sets the current reading of every channel
+    pub fn set_reading(&mut self, reading: Vec<f64>) {
+        self.reading = reading
+    }
+}
+
+impl Rewardable for ExprReward {
+    fn to_reward(&self) -> f64 {
+        self.expr.eval(&self.reading)
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        self.set_reading(reading.to_vec())
+    }
+}
+
+/// A lexical token
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A numeric literal
+    Number(f64),
+    /// A function or channel name
+    Ident(String),
+    /// A channel index, `#3`
+    Index(usize),
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `,`
+    Comma,
+    /// Any binary operator written as a symbol
+    Op(BinOp),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Ident(name) => write!(f, "{name:?}"),
+            Self::Index(index) => write!(f, "#{index}"),
+            Self::LParen => write!(f, "'('"),
+            Self::RParen => write!(f, "')'"),
+            Self::Comma => write!(f, "','"),
+            Self::Op(op) => {
+                let symbol = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Lt => "<",
+                    BinOp::Gt => ">",
+                    BinOp::Le => "<=",
+                    BinOp::Ge => ">=",
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                };
+                write!(f, "'{symbol}'")
+            }
+        }
+    }
+}
+
+/// Splits a source into tokens, each with the byte offset it starts at
+fn lex(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((at, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '+' => Token::Op(BinOp::Add),
+            '-' => Token::Op(BinOp::Sub),
+            '*' => Token::Op(BinOp::Mul),
+            '/' => Token::Op(BinOp::Div),
+            '<' | '>' => {
+                let equal = chars.next_if(|&(_, c)| c == '=').is_some();
+                Token::Op(match (c, equal) {
+                    ('<', false) => BinOp::Lt,
+                    ('<', true) => BinOp::Le,
+                    (_, false) => BinOp::Gt,
+                    (_, true) => BinOp::Ge,
+                })
+            }
+            '#' => {
+                let mut end = at + 1;
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = i + c.len_utf8();
+                }
+                match source[at + 1..end].parse() {
+                    Ok(index) => Token::Index(index),
+                    Err(_) => return Err(ExprError::UnexpectedChar { at, found: '#' }),
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = at + 1;
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    end = i + c.len_utf8();
+                }
+                match source[at..end].parse() {
+                    Ok(value) => Token::Number(value),
+                    Err(_) => {
+                        return Err(ExprError::UnexpectedToken {
+                            at,
+                            expected: "a number",
+                            found: format!("{:?}", &source[at..end]),
+                        })
+                    }
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = at + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    end = i + c.len_utf8();
+                }
+                match &source[at..end] {
+                    "and" => Token::Op(BinOp::And),
+                    "or" => Token::Op(BinOp::Or),
+                    name => Token::Ident(name.to_string()),
+                }
+            }
+            found => return Err(ExprError::UnexpectedChar { at, found }),
+        };
+        tokens.push((at, token));
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser that type checks as it goes
+struct Parser<'src> {
+    /// Every token with its offset
+    tokens: Vec<(usize, Token)>,
+    /// The next token to parse
+    next: usize,
+    /// The length of the source, where an unexpected end is reported
+    end: usize,
+    /// Channel names in column order
+    channels: &'src [&'src str],
+}
+
+impl Parser<'_> {
+    /// The next token without consuming it
+    fn peek(&self) -> Option<(usize, Token)> {
+        self.tokens.get(self.next).cloned()
+    }
+
+    /// Consumes the next token, failing at the end of the source
+    fn advance(&mut self, expected: &'static str) -> Result<(usize, Token), ExprError> {
+        let token = self.peek().ok_or(ExprError::UnexpectedEnd {
+            at: self.end,
+            expected,
+        })?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    /// Consumes the next token if it's the expected one
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ExprError> {
+        match self.advance(expected)? {
+            (_, found) if found == token => Ok(()),
+            (at, found) => Err(ExprError::UnexpectedToken {
+                at,
+                expected,
+                found: found.to_string(),
+            }),
+        }
+    }
+
+    /// Consumes the next token if it's one of these operators
+    fn operator(&mut self, ops: &[BinOp]) -> Option<(usize, BinOp)> {
+        match self.peek() {
+            Some((at, Token::Op(op))) if ops.contains(&op) => {
+                self.next += 1;
+                Some((at, op))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a whole expression
+    fn expr(&mut self) -> Result<(Expr, Type), ExprError> {
+        self.binary(0)
+    }
+
+    /// Parses left associative binary operators, loosest first
+    fn binary(&mut self, level: usize) -> Result<(Expr, Type), ExprError> {
+        /// Operators by how loosely they bind
+        const LEVELS: [&[BinOp]; 5] = [
+            &[BinOp::Or],
+            &[BinOp::And],
+            &[BinOp::Lt, BinOp::Gt, BinOp::Le, BinOp::Ge],
+            &[BinOp::Add, BinOp::Sub],
+            &[BinOp::Mul, BinOp::Div],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let start = self.peek().map_or(self.end, |(at, _)| at);
+        let (mut lhs, mut ty) = self.binary(level + 1)?;
+        while let Some((_, op)) = self.operator(ops) {
+            let rhs_at = self.peek().map_or(self.end, |(at, _)| at);
+            let (rhs, rhs_ty) = self.binary(level + 1)?;
+            let (takes, gives) = op.signature();
+            check(takes, ty, start)?;
+            check(takes, rhs_ty, rhs_at)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+            ty = gives;
+        }
+        Ok((lhs, ty))
+    }
+
+    /// Parses negation
+    fn unary(&mut self) -> Result<(Expr, Type), ExprError> {
+        if self.operator(&[BinOp::Sub]).is_some() {
+            let (inner, _) = self.unary()?;
+            return Ok((Expr::Neg(Box::new(inner)), Type::Number));
+        }
+        self.primary()
+    }
+
+    /// Parses a literal, channel, call or parenthesised expression
+    fn primary(&mut self) -> Result<(Expr, Type), ExprError> {
+        const EXPECTED: &str = "a number, channel, function call or '('";
+        match self.advance(EXPECTED)? {
+            (_, Token::Number(value)) => Ok((Expr::Number(value), Type::Number)),
+            (at, Token::Index(index)) => Ok((self.channel(at, index)?, Type::Number)),
+            (_, Token::LParen) => {
+                let inner = self.expr()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(inner)
+            }
+            (at, Token::Ident(name)) => match self.peek() {
+                Some((_, Token::LParen)) => self.call(at, &name),
+                _ => match self.channels.iter().position(|channel| *channel == name) {
+                    Some(index) => Ok((Expr::Channel(index), Type::Number)),
+                    None => Err(ExprError::UnknownChannel { at, name }),
+                },
+            },
+            (at, token) => Err(ExprError::UnexpectedToken {
+                at,
+                expected: EXPECTED,
+                found: token.to_string(),
+            }),
+        }
+    }
+
+    /// Checks a channel index is in range
+    fn channel(&self, at: usize, index: usize) -> Result<Expr, ExprError> {
+        let dims = self.channels.len();
+        if index >= dims {
+            return Err(ExprError::ChannelOutOfRange { at, index, dims });
+        }
+        Ok(Expr::Channel(index))
+    }
+
+    /// Parses a function call's arguments and checks them against its signature
+    fn call(&mut self, at: usize, name: &str) -> Result<(Expr, Type), ExprError> {
+        let func = Func::from_name(name).ok_or_else(|| ExprError::UnknownFunction {
+            at,
+            name: name.to_string(),
+        })?;
+        self.expect(Token::LParen, "'('")?;
+
+        let mut args = vec![];
+        if !matches!(self.peek(), Some((_, Token::RParen))) {
+            loop {
+                let arg_at = self.peek().map_or(self.end, |(at, _)| at);
+                let (arg, ty) = self.expr()?;
+                args.push((arg_at, arg, ty));
+                match self.advance("',' or ')'")? {
+                    (_, Token::Comma) => continue,
+                    (_, Token::RParen) => break,
+                    (at, found) => {
+                        return Err(ExprError::UnexpectedToken {
+                            at,
+                            expected: "',' or ')'",
+                            found: found.to_string(),
+                        })
+                    }
+                }
+            }
+        } else {
+            self.next += 1;
+        }
+
+        let (takes, gives) = func.signature();
+        if takes.len() != args.len() {
+            return Err(ExprError::Arity {
+                at,
+                name: func.name(),
+                expected: takes.len(),
+                found: args.len(),
+            });
+        }
+        for (&expected, &(arg_at, _, found)) in takes.iter().zip(&args) {
+            check(expected, found, arg_at)?;
+        }
+
+        let args = args.into_iter().map(|(_, arg, _)| arg).collect();
+        Ok((Expr::Call(func, args), gives))
+    }
+}
+
+/// Checks a value can be used where a type is expected. Conditions can stand in for numbers, but
+/// not the other way around
+fn check(expected: Type, found: Type, at: usize) -> Result<(), ExprError> {
+    match (expected, found) {
+        (Type::Condition, Type::Number) => Err(ExprError::TypeMismatch {
+            at,
+            expected,
+            found,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::goals::Rewardable;
+
+    use super::{Expr, ExprError, ExprReward, Type};
+
+    const CHANNELS: [&str; 3] = ["light", "temp", "dist"];
+
+    #[test]
+    fn evaluates_weighted_rewards() {
+        let source = "maximize(light) - 0.5*abs(temp - 22) + 10*within(dist, 0.3, 0.5)";
+        let expr = Expr::parse(source, &CHANNELS).unwrap();
+        assert_eq!(expr.max_channel(), Some(2));
+
+        let mut reward = ExprReward::new(expr);
+        reward.set_reading(vec![3.0, 24.0, 0.4]);
+        assert_eq!(reward.to_reward(), 3.0 - 1.0 + 10.0);
+
+        reward.set_reading(vec![3.0, 22.0, 0.6]);
+        assert_eq!(reward.to_reward(), 3.0);
+    }
+
+    #[test]
+    fn channels_by_index_and_precedence() {
+        let expr = Expr::parse("-#0 + #1 * 2 > 3 and not(#2 < 1)", &CHANNELS).unwrap();
+        assert_eq!(expr.eval(&[1.0, 3.0, 2.0]), 1.0);
+        assert_eq!(expr.eval(&[1.0, 1.0, 2.0]), 0.0);
+
+        let wire = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Expr>(&wire).unwrap(), expr);
+    }
+
+    #[test]
+    fn reports_where_expressions_go_wrong() {
+        let parse = |source| Expr::parse(source, &CHANNELS).unwrap_err();
+
+        assert_eq!(
+            parse("maximize(humidity)"),
+            ExprError::UnknownChannel {
+                at: 9,
+                name: "humidity".into()
+            }
+        );
+        assert!(matches!(
+            parse("#3"),
+            ExprError::ChannelOutOfRange { index: 3, .. }
+        ));
+        assert!(matches!(
+            parse("within(temp, 1)"),
+            ExprError::Arity {
+                expected: 3,
+                found: 2,
+                ..
+            }
+        ));
+        assert_eq!(
+            parse("if(temp, 1, 0)"),
+            ExprError::TypeMismatch {
+                at: 3,
+                expected: Type::Condition,
+                found: Type::Number
+            }
+        );
+        assert!(matches!(parse("abs(temp"), ExprError::UnexpectedEnd { .. }));
+
+        let pointed = parse("light + $").pointer("light + $");
+        assert!(pointed.ends_with("        ^ Unexpected character '$' at 8"));
+    }
+}
@@ -1,11 +1,13 @@
 //! A REWARD implementation for a struct wrt an agent's current position
 
-use super::{Goal, Rewardable};
+use super::{expr::Expr, validate_goals, ChannelGoal, GoalError, Rewardable};
 
 /// A REWARD trait impl for when context
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct PositionContextualReward<const N: usize> {
-    goals: [Option<Goal>; N],
+    goals: [Option<ChannelGoal>; N],
+    /// A reward expression used in place of the channel goals when set
+    expr: Option<Expr>,
     curr_reading: [f64; N],
 }
 
@@ -13,6 +15,7 @@ impl<const N: usize> Default for PositionContextualReward<N> {
     fn default() -> Self {
         Self {
             goals: [None; N],
+            expr: None,
             curr_reading: [0.0; N],
         }
     }
@@ -24,29 +27,97 @@ impl<const N: usize> PositionContextualReward<N> {
         self.curr_reading = new_pos
     }
 
-    /// Updates a set of goals based on id-goal pairings
-    pub fn update(&mut self, goals: Vec<(usize, bool)>) {
-        for (idx, maximize) in goals {
-            if N < idx {
-                let goal = match maximize {
-                    true => Goal::Maximize,
-                    false => Goal::Minimize,
-                };
-                self.goals[idx] = Some(goal)
-            }
+    /// Scores readings with an expression instead of the channel goals
+    pub fn set_expression(&mut self, expr: Expr) {
+        self.expr = Some(expr)
+    }
+
+    /// Updates a set of goals, each replacing any earlier goal for its channel. Nothing is
+    /// changed if any goal is invalid
+    pub fn update(&mut self, goals: Vec<ChannelGoal>) -> Result<(), GoalError> {
+        validate_goals(&goals, N)?;
+        for goal in goals {
+            self.goals[goal.index] = Some(goal)
         }
+        Ok(())
     }
 }
 
 impl<const N: usize> Rewardable for PositionContextualReward<N> {
     fn to_reward(&self) -> f64 {
+        if let Some(expr) = &self.expr {
+            return expr.eval(&self.curr_reading);
+        }
+
         let mut final_score = 0.0;
         for (goal, val) in self.goals.iter().zip(self.curr_reading.iter()) {
             if let Some(goal) = goal {
-                final_score += goal.match_against(*val, 1.0);
+                final_score += goal.score(*val);
             }
         }
 
         final_score
     }
+
+    fn objectives(&self) -> Vec<f64> {
+        let scores: Vec<_> = self
+            .goals
+            .iter()
+            .zip(self.curr_reading.iter())
+            .filter_map(|(goal, val)| goal.map(|goal| goal.score(*val)))
+            .collect();
+
+        match self.expr.is_some() || scores.is_empty() {
+            true => vec![self.to_reward()],
+            false => scores,
+        }
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        for (curr, val) in self.curr_reading.iter_mut().zip(reading) {
+            *curr = *val
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::goals::{expr::Expr, ChannelGoal, GoalProblem, Rewardable};
+
+    use super::PositionContextualReward;
+
+    #[test]
+    fn invalid_updates_change_nothing() {
+        let mut reward = PositionContextualReward::<2>::default();
+        reward.set_reading([3.0, 1.0]);
+        reward.update(vec![ChannelGoal::maximize(1)]).unwrap();
+
+        let err = reward
+            .update(vec![ChannelGoal::minimize(0), ChannelGoal::maximize(2)])
+            .unwrap_err();
+        assert_eq!(
+            err.problems,
+            vec![GoalProblem::OutOfRange { index: 2, dims: 2 }]
+        );
+        assert_eq!(reward.to_reward(), 1.0);
+    }
+
+    #[test]
+    fn every_goal_is_its_own_objective() {
+        let mut reward = PositionContextualReward::<3>::default();
+        assert_eq!(reward.objectives(), vec![0.0]);
+
+        reward
+            .update(vec![
+                ChannelGoal::maximize(0),
+                ChannelGoal::target(2, 1.0).with_weight(2.0),
+            ])
+            .unwrap();
+        reward.observe(&[3.0, 9.0, 0.5]);
+        assert_eq!(reward.objectives(), vec![3.0, -1.0]);
+        assert_eq!(reward.objectives().iter().sum::<f64>(), reward.to_reward());
+
+        reward.set_expression(Expr::Channel(1));
+        assert_eq!(reward.objectives(), vec![9.0]);
+    }
 }
@@ -0,0 +1,470 @@
+//! Goals about where the agent is rather than what its peripherals read: reaching a point, entering
+//! or avoiding a region, keeping clear of obstacles in the point cloud, and exploring new space
+
+use std::collections::HashSet;
+
+use super::Rewardable;
+
+/// A position in 3-space
+pub type Position = [f64; 3];
+
+/// A goal scored against the agent's simulated position, which the simulation updates as the agent
+/// moves. Observed readings are laid out like collected points, xyz first, so observing one moves
+/// the agent to its position
+pub trait SpatialGoal: Rewardable {
+    /// Moves the agent to a new position
+    fn set_position(&mut self, position: Position);
+}
+
+/// The position at the front of a reading, if it's long enough to hold one
+fn position_of(reading: &[f64]) -> Option<Position> {
+    reading.get(..3)?.try_into().ok()
+}
+
+/// The distance between two points
+fn distance(a: Position, b: Position) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Reach a coordinate. Scores the negated distance to it, and 0 once within tolerance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reach {
+    /// Where to go
+    target: Position,
+    /// How close counts as arrived
+    tolerance: f64,
+    /// Where the agent is
+    position: Position,
+}
+
+impl Reach {
+    /// Creates a goal to reach a coordinate exactly
+    pub fn new(target: Position) -> Self {
+        Self {
+            target,
+            tolerance: 0.0,
+            position: [0.0; 3],
+        }
+    }
+
+    /// Sets how close counts as arrived
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.abs();
+        self
+    }
+
+    /// Returns true once the agent is within tolerance of the target
+    pub fn is_reached(&self) -> bool {
+        distance(self.position, self.target) <= self.tolerance
+    }
+}
+
+impl Rewardable for Reach {
+    fn to_reward(&self) -> f64 {
+        -(distance(self.position, self.target) - self.tolerance).max(0.0)
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        if let Some(position) = position_of(reading) {
+            self.set_position(position)
+        }
+    }
+}
+
+impl SpatialGoal for Reach {
+    fn set_position(&mut self, position: Position) {
+        self.position = position
+    }
+}
+
+/// A region of space
+#[derive(Clone, Debug, PartialEq)]
+pub enum Region {
+    /// An axis-aligned box between two corners
+    Box {
+        /// The corner with the lowest coordinates
+        min: Position,
+        /// The corner with the highest coordinates
+        max: Position,
+    },
+    /// A polygon in the xy plane, extruded between two heights
+    Polygon {
+        /// The polygon's corners in order, either winding
+        vertices: Vec<[f64; 2]>,
+        /// The lowest z inside the region
+        floor: f64,
+        /// The highest z inside the region
+        ceiling: f64,
+    },
+}
+
+impl Region {
+    /// An axis-aligned box between any two opposite corners
+    pub fn aabb(a: Position, b: Position) -> Self {
+        Self::Box {
+            min: [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])],
+            max: [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])],
+        }
+    }
+
+    /// A polygon in the xy plane covering every height
+    pub fn polygon(vertices: Vec<[f64; 2]>) -> Self {
+        Self::Polygon {
+            vertices,
+            floor: f64::NEG_INFINITY,
+            ceiling: f64::INFINITY,
+        }
+    }
+
+    /// Returns true if the point is inside the region or on its boundary
+    pub fn contains(&self, point: Position) -> bool {
+        match self {
+            Self::Box { min, max } => (0..3).all(|i| min[i] <= point[i] && point[i] <= max[i]),
+            Self::Polygon {
+                vertices,
+                floor,
+                ceiling,
+            } => {
+                (*floor..=*ceiling).contains(&point[2])
+                    && (in_polygon(vertices, [point[0], point[1]])
+                        || edge_distance(vertices, [point[0], point[1]]) == 0.0)
+            }
+        }
+    }
+
+    /// How far the point is from the region's boundary, whether it's inside or out
+    pub fn boundary_distance(&self, point: Position) -> f64 {
+        match self {
+            Self::Box { min, max } if self.contains(point) => (0..3)
+                .map(|i| (point[i] - min[i]).min(max[i] - point[i]))
+                .fold(f64::INFINITY, f64::min),
+            Self::Box { min, max } => (0..3)
+                .map(|i| (min[i] - point[i]).max(point[i] - max[i]).max(0.0).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+            Self::Polygon {
+                vertices,
+                floor,
+                ceiling,
+            } => {
+                let flat = edge_distance(vertices, [point[0], point[1]]);
+                let inside_xy = in_polygon(vertices, [point[0], point[1]]);
+                let below = floor - point[2];
+                let above = point[2] - ceiling;
+                match (inside_xy, below > 0.0 || above > 0.0) {
+                    (true, false) => flat.min(-below).min(-above),
+                    (true, true) => below.max(above),
+                    (false, false) => flat,
+                    (false, true) => flat.hypot(below.max(above)),
+                }
+            }
+        }
+    }
+}
+
+/// Whether a point is inside a polygon, by counting crossings of a ray along +x
+fn in_polygon(vertices: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (i, &[x1, y1]) in vertices.iter().enumerate() {
+        let [x0, y0] = vertices[(i + vertices.len() - 1) % vertices.len()];
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// The distance from a point to the nearest edge of a polygon
+fn edge_distance(vertices: &[[f64; 2]], [x, y]: [f64; 2]) -> f64 {
+    (0..vertices.len())
+        .map(|i| {
+            let [x0, y0] = vertices[i];
+            let [x1, y1] = vertices[(i + 1) % vertices.len()];
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            let length = dx * dx + dy * dy;
+            let t = match length {
+                0.0 => 0.0,
+                _ => (((x - x0) * dx + (y - y0) * dy) / length).clamp(0.0, 1.0),
+            };
+            (x - (x0 + t * dx)).hypot(y - (y0 + t * dy))
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Whether the agent should be inside a region or out of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intent {
+    /// Get into the region
+    Enter,
+    /// Stay out of the region
+    Avoid,
+}
+
+/// Enter or avoid a region. Scores the negated distance to go while on the wrong side of its
+/// boundary, and 0 once on the right side
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionGoal {
+    /// The region
+    region: Region,
+    /// Whether to be inside it or out
+    intent: Intent,
+    /// Where the agent is
+    position: Position,
+}
+
+impl RegionGoal {
+    /// Creates a goal to get into a region
+    pub fn enter(region: Region) -> Self {
+        Self {
+            region,
+            intent: Intent::Enter,
+            position: [0.0; 3],
+        }
+    }
+
+    /// Creates a goal to stay out of a region
+    pub fn avoid(region: Region) -> Self {
+        Self {
+            region,
+            intent: Intent::Avoid,
+            position: [0.0; 3],
+        }
+    }
+
+    /// Returns true if the agent is on the right side of the region's boundary
+    pub fn is_met(&self) -> bool {
+        self.region.contains(self.position) == (self.intent == Intent::Enter)
+    }
+}
+
+impl Rewardable for RegionGoal {
+    fn to_reward(&self) -> f64 {
+        match self.is_met() {
+            true => 0.0,
+            false => -self.region.boundary_distance(self.position),
+        }
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        if let Some(position) = position_of(reading) {
+            self.set_position(position)
+        }
+    }
+}
+
+impl SpatialGoal for RegionGoal {
+    fn set_position(&mut self, position: Position) {
+        self.position = position
+    }
+}
+
+/// Keep a minimum clearance from every obstacle in a point cloud. Scores the negated amount the
+/// nearest obstacle is too close by, and 0 while every obstacle is far enough away
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clearance {
+    /// The closest any obstacle may be
+    min: f64,
+    /// Points on obstacles
+    obstacles: Vec<Position>,
+    /// Where the agent is
+    position: Position,
+}
+
+impl Clearance {
+    /// Creates a goal to stay at least `min` away from every point in a cloud
+    pub fn new(min: f64, obstacles: Vec<Position>) -> Self {
+        Self {
+            min,
+            obstacles,
+            position: [0.0; 3],
+        }
+    }
+
+    /// Adds newly observed obstacle points
+    pub fn extend(&mut self, obstacles: impl IntoIterator<Item = Position>) {
+        self.obstacles.extend(obstacles)
+    }
+
+    /// How far away the nearest obstacle is, `None` if there are none
+    pub fn nearest(&self) -> Option<f64> {
+        self.obstacles
+            .iter()
+            .map(|&obstacle| distance(self.position, obstacle))
+            .min_by(f64::total_cmp)
+    }
+}
+
+impl Rewardable for Clearance {
+    fn to_reward(&self) -> f64 {
+        self.nearest()
+            .map_or(0.0, |nearest| -(self.min - nearest).max(0.0))
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        if let Some(position) = position_of(reading) {
+            self.set_position(position)
+        }
+    }
+}
+
+impl SpatialGoal for Clearance {
+    fn set_position(&mut self, position: Position) {
+        self.position = position
+    }
+}
+
+/// Explore new space. Everything within sensing range of each position the agent moves to is
+/// observed, and the goal scores the volume observed for the first time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exploration {
+    /// The edge length of a voxel
+    voxel: f64,
+    /// How far the agent can sense
+    range: f64,
+    /// Every voxel observed so far
+    seen: HashSet<[i64; 3]>,
+    /// How many voxels were observed for the first time since progress was last reset
+    newly_seen: usize,
+}
+
+impl Exploration {
+    /// Creates a goal counting space in voxels of edge `voxel`, sensed within `range` of the agent.
+    /// `None` unless the voxel is positive and both are finite
+    pub fn new(voxel: f64, range: f64) -> Option<Self> {
+        (voxel > 0.0 && voxel.is_finite() && range.is_finite()).then(|| Self {
+            voxel,
+            range: range.abs(),
+            seen: HashSet::new(),
+            newly_seen: 0,
+        })
+    }
+
+    /// Marks the space around every point of an existing cloud as already observed
+    pub fn mark_seen(&mut self, points: impl IntoIterator<Item = Position>) {
+        for point in points {
+            self.seen.insert(self.voxel_of(point));
+        }
+    }
+
+    /// The volume observed for the first time since progress was last reset
+    pub fn newly_observed(&self) -> f64 {
+        self.newly_seen as f64 * self.voxel.powi(3)
+    }
+
+    /// Starts counting newly observed volume afresh, keeping what's been observed
+    pub fn reset_progress(&mut self) {
+        self.newly_seen = 0
+    }
+
+    /// The voxel a point is in
+    fn voxel_of(&self, point: Position) -> [i64; 3] {
+        point.map(|coord| (coord / self.voxel).floor() as i64)
+    }
+}
+
+impl Rewardable for Exploration {
+    fn to_reward(&self) -> f64 {
+        self.newly_observed()
+    }
+
+    fn observe(&mut self, reading: &[f64]) {
+        if let Some(position) = position_of(reading) {
+            self.set_position(position)
+        }
+    }
+}
+
+impl SpatialGoal for Exploration {
+    fn set_position(&mut self, position: Position) {
+        let centre = self.voxel_of(position);
+        let reach = (self.range / self.voxel).ceil() as i64;
+
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    let voxel = [centre[0] + dx, centre[1] + dy, centre[2] + dz];
+                    let middle = voxel.map(|i| (i as f64 + 0.5) * self.voxel);
+                    if distance(middle, position) <= self.range && self.seen.insert(voxel) {
+                        self.newly_seen += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::goals::Rewardable;
+
+    use super::{Clearance, Exploration, Reach, Region, RegionGoal, SpatialGoal};
+
+    #[test]
+    fn reaching_scores_remaining_distance() {
+        let mut reach = Reach::new([3.0, 4.0, 0.0]).with_tolerance(1.0);
+        assert_eq!(reach.to_reward(), -4.0);
+
+        reach.set_position([3.0, 4.5, 0.0]);
+        assert!(reach.is_reached());
+        assert_eq!(reach.to_reward(), 0.0);
+    }
+
+    #[test]
+    fn regions_are_entered_and_avoided() {
+        let square = Region::polygon(vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+        let mut enter = RegionGoal::enter(square.clone());
+        enter.set_position([-1.0, 1.0, 5.0]);
+        assert_eq!(enter.to_reward(), -1.0);
+        enter.set_position([1.0, 1.0, 5.0]);
+        assert_eq!(enter.to_reward(), 0.0);
+
+        let mut avoid = RegionGoal::avoid(Region::aabb([2.0, 2.0, 2.0], [0.0, 0.0, 0.0]));
+        avoid.set_position([0.5, 1.0, 1.0]);
+        assert_eq!(avoid.to_reward(), -0.5);
+        avoid.set_position([3.0, 1.0, 1.0]);
+        assert!(avoid.is_met());
+    }
+
+    #[test]
+    fn clearance_penalises_near_obstacles() {
+        let mut clearance = Clearance::new(1.0, vec![[0.0, 0.0, 0.0]]);
+        clearance.set_position([0.25, 0.0, 0.0]);
+        assert_eq!(clearance.to_reward(), -0.75);
+
+        clearance.set_position([2.0, 0.0, 0.0]);
+        assert_eq!(clearance.to_reward(), 0.0);
+    }
+
+    #[test]
+    fn exploration_only_counts_new_space() {
+        let mut exploration = Exploration::new(1.0, 1.0).unwrap();
+        exploration.mark_seen([[0.5, 0.5, 0.5]]);
+
+        exploration.set_position([0.5, 0.5, 0.5]);
+        // The 6 face neighbours of the voxel already seen
+        assert_eq!(exploration.to_reward(), 6.0);
+
+        exploration.reset_progress();
+        exploration.set_position([0.5, 0.5, 0.5]);
+        assert_eq!(exploration.to_reward(), 0.0);
+
+        assert!(Exploration::new(0.0, 1.0).is_none());
+        assert!(Exploration::new(-1.0, 1.0).is_none());
+        assert!(Exploration::new(1.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn observed_points_move_the_agent() {
+        let mut reach = Reach::new([3.0, 4.0, 0.0]);
+        reach.observe(&[3.0, 4.0, 0.0, 12.5]);
+        assert!(reach.is_reached());
+
+        // Too short to hold a position, so the agent stays put
+        reach.observe(&[0.0, 0.0]);
+        assert!(reach.is_reached());
+    }
+}
@@ -4,8 +4,12 @@
 #[cfg(all(feature = "jetson", feature = "rpi"))]
 compile_error! {"Jetson Nano and Raspberry Pi cannot both be targetted by the same sysetem"}
 
+// Lets `#[derive(Rewardable)]` resolve its paths when used inside this crate
+extern crate self as earthmover_achiever;
+
 pub mod body;
 pub mod brain;
+pub mod client;
 pub mod communication;
 pub mod goals;
 pub mod protocol;
@@ -1,6 +1,9 @@
 //! Enum and Struct definitions for the *ArrowHead Transfer Protocol*
 
-use crate::brain::instruction::Instruction;
+use crate::{
+    brain::{feedback::ExecutionFeedback, instruction::Plan, pareto::ParetoFront},
+    goals::{expr::Expr, ChannelGoal, GoalError},
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -16,12 +19,24 @@ where
     Send(Vec<[f32; DIMS]>),
     /// Connect to a session via websocket
     Connect(Uuid),
-    /// Set the current goal of the agent. That is, which index of dimension we want to
-    /// maximize (true) or minimize (false).
+    /// Set the dimensionality of every point sent in this session
+    SetDims(usize),
+    /// Set the current goals of the agent. That is, what each dimension's reading should do:
+    /// be maximized, minimized, held at a target or kept within a range.
     ///
-    /// For example, `Goal([(0, true)])` would attempt to maximize the first dimension on the
-    /// agent's readings.
-    Goal(Vec<(usize, bool)>),
+    /// For example, `Goal([ChannelGoal::maximize(0)])` would attempt to maximize the first
+    /// dimension on the agent's readings.
+    Goal(Vec<ChannelGoal>),
+    /// Set the agent's reward to an expression over the dimensions of its readings, replacing any
+    /// goals
+    Reward(Expr),
+    /// Begin training on all data sent so far
+    Train,
+    /// Begin training on all data sent so far, responding with every instruction set no other
+    /// beats on all objectives instead of a single best one
+    TrainFront,
+    /// Report how an instruction set went when it was performed, by its id
+    Feedback(ExecutionFeedback),
 }
 
 impl<const DIMS: usize> AhtpMessage<DIMS>
@@ -34,17 +49,74 @@ where
     }
 }
 
+/// An `AhtpMessage` as the server reads it. The server can't know the width of an agent's points
+/// ahead of time, so points are read as vectors of any length. Shares its wire format with
+/// `AhtpMessage`
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AhtpRequest {
+    /// A buffer of collected data points
+    Send(Vec<Vec<f32>>),
+    /// Connect to a session via websocket, always the first message on a socket
+    Connect(Uuid),
+    /// Set the dimensionality of every point sent in this session
+    SetDims(usize),
+    /// Set the current goals of the agent
+    Goal(Vec<ChannelGoal>),
+    /// Set the agent's reward to an expression over the dimensions of its readings
+    Reward(Expr),
+    /// Begin training on all data sent so far
+    Train,
+    /// Begin training, responding with the Pareto front
+    TrainFront,
+    /// Report how an instruction set went when it was performed, by its id
+    Feedback(ExecutionFeedback),
+}
+
+impl AhtpRequest {
+    /// Serializes the request as a json string
+    pub fn to_json_string(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Attempts to read a request from a json string
+    pub fn from_json_str(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
 /// A response from the simulation server.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum AhtpResponse {
     /// The initialization step was a success. Here is the session ID to init WebSocket
     /// communication with.
     Initialized(Uuid),
-    /// An instruction set from the simulation server.
-    Instruction(Vec<Instruction>),
+    /// An instruction set from the simulation server, under the id its feedback is reported with
+    Instruction(Plan),
+    /// The Pareto front of instruction sets, for the agent to choose a trade-off from. Every
+    /// candidate carries its own id
+    Front(ParetoFront),
+    /// Training could not be started or failed
+    TrainError(String),
+    /// The dimensionality sent was accepted
+    DimsSet,
+    /// The points sent were added to the session's data
+    DataReceived,
+    /// The goals sent were accepted
+    GoalsSet,
+    /// The goals sent were rejected, and none of them were applied
+    GoalError(GoalError),
+    /// The reward expression sent was accepted
+    RewardSet,
+    /// A message couldn't be understood or isn't allowed in the session's current state
+    Rejected(String),
 }
 
 impl AhtpResponse {
+    /// Serializes the response as a json string
+    pub fn to_json_string(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
     /// Returns the initialized ID if the type of this response is an init
     pub fn get_init(self) -> Option<Uuid> {
         match self {
@@ -73,12 +145,21 @@ impl_array_bounded_size!(
 
 // Some helpful stuff for creating messages conveniently
 
+impl<const DIMS: usize> From<Vec<ChannelGoal>> for AhtpMessage<DIMS>
+where
+    [f32; DIMS]: ArrayBoundedSize + Serialize + DeserializeOwned,
+{
+    fn from(value: Vec<ChannelGoal>) -> Self {
+        Self::Goal(value)
+    }
+}
+
 impl<const DIMS: usize> From<Vec<(usize, bool)>> for AhtpMessage<DIMS>
 where
     [f32; DIMS]: ArrayBoundedSize + Serialize + DeserializeOwned,
 {
     fn from(value: Vec<(usize, bool)>) -> Self {
-        Self::Goal(value)
+        Self::Goal(value.into_iter().map(ChannelGoal::from).collect())
     }
 }
 
@@ -90,3 +171,31 @@ where
         Self::Send(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{brain::feedback::ExecutionFeedback, goals::ChannelGoal};
+
+    use super::{AhtpMessage, AhtpRequest};
+
+    #[test]
+    fn every_message_reads_as_a_request() {
+        let messages: Vec<AhtpMessage<3>> = vec![
+            AhtpMessage::Send(vec![[1.0, 2.0, 3.0]]),
+            AhtpMessage::Connect(Uuid::new_v4()),
+            AhtpMessage::SetDims(3),
+            AhtpMessage::Goal(vec![ChannelGoal::maximize(0)]),
+            AhtpMessage::Train,
+            AhtpMessage::TrainFront,
+            AhtpMessage::Feedback(ExecutionFeedback::new(vec![])),
+        ];
+
+        for message in messages {
+            let json = message.to_json_string().unwrap();
+            let request = AhtpRequest::from_json_str(&json).unwrap();
+            assert_eq!(serde_json::to_string(&request).unwrap(), json);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Behaviour of `#[derive(Rewardable)]`, along with the annotations it must reject
+
+use earthmover_achiever::goals::Rewardable;
+
+#[derive(Rewardable)]
+#[reward(breakdown)]
+struct Greenhouse {
+    #[reward(maximize, weight = 2.0)]
+    light: f32,
+    #[reward(target = 22.0, tolerance = 0.5)]
+    temperature: f64,
+    #[reward(minimize)]
+    water_used: f32,
+    #[reward(skip)]
+    #[allow(dead_code)]
+    label: String,
+    humidity: u8,
+}
+
+#[derive(Rewardable)]
+struct Pair(#[reward(minimize)] f64, #[reward(weight = 3)] f64);
+
+#[test]
+fn fields_are_weighted_and_summed() {
+    let greenhouse = Greenhouse {
+        light: 10.0,
+        temperature: 25.0,
+        water_used: 4.0,
+        label: "east".into(),
+        humidity: 3,
+    };
+
+    // 2 * 10 - (3 - 0.5) - 4 + 3
+    assert_eq!(greenhouse.to_reward(), 16.5);
+    assert_eq!(
+        greenhouse.reward_breakdown(),
+        vec![
+            ("light", 20.0),
+            ("temperature", -2.5),
+            ("water_used", -4.0),
+            ("humidity", 3.0),
+        ]
+    );
+}
+
+#[test]
+fn tuple_structs_use_their_indices() {
+    assert_eq!(Pair(1.0, 2.0).to_reward(), 5.0);
+}
+
+#[test]
+fn bad_annotations_fail_to_compile() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use earthmover_achiever::goals::Rewardable;
+
+#[derive(Rewardable)]
+struct Sensor {
+    #[reward(maximize, minimize)]
+    reading: f32,
+}
+
+fn main() {}
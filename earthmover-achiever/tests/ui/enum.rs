@@ -0,0 +1,9 @@
+use earthmover_achiever::goals::Rewardable;
+
+#[derive(Rewardable)]
+enum Sensor {
+    On,
+    Off,
+}
+
+fn main() {}
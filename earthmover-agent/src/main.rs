@@ -0,0 +1,468 @@
+//! The agent's application cycle
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
+use earthmover_achiever::body::safety::{Feeder, Watchdog};
+use earthmover_achiever::body::sampling::{Sampler, DEFAULT_PERIOD};
+use earthmover_achiever::brain::agent::Untrained;
+use earthmover_achiever::brain::buffer::DataBuffer;
+use earthmover_achiever::brain::executor::{Executor, Outcome};
+use earthmover_achiever::brain::fusion::{FixedPosition, SpatialSampler, SpatialSource};
+#[cfg(feature = "rpi")]
+use earthmover_achiever::brain::fusion::{LidarReading, LidarSource};
+use earthmover_achiever::brain::history::{Convergence, ConvergenceDetector};
+use earthmover_achiever::brain::journal::Journal;
+use earthmover_achiever::brain::validate::{Policy, Validator};
+use earthmover_achiever::client::{Hivemind, RemoteHivemind};
+use earthmover_achiever::goals::expr::Expr;
+use earthmover_achiever::goals::multi_dim::PositionContextualReward;
+use earthmover_achiever::goals::{validate_goals, ChannelGoal, Goal, Rewardable};
+use earthmover_achiever::{
+    body::{calibration::CalibrationFile, description::BodyDescription, Body},
+    brain::AgentSession,
+};
+#[cfg(feature = "local")]
+use earthmover_hivemind::local::{LocalHivemind, LOCAL_NUM_SIMS};
+
+/// The widest point the agent's goals cover: xyz, then one dimension per channel
+pub const DIMS: usize = 16;
+/// How many points are collected before they're sent to the hivemind
+pub const BUFFER_SIZE: usize = 100_000;
+/// How often the watchdog checks on the body
+pub const WATCHDOG_PERIOD: Duration = Duration::from_millis(50);
+/// How far a channel's reading may be from a position in time and still be joined with it
+pub const FUSION_TOLERANCE: Duration = Duration::from_millis(50);
+/// How long collection runs between feeds of the watchdog
+pub const COLLECT_SLICE: Duration = Duration::from_millis(250);
+/// The weakest lidar return that's used as a position
+#[cfg(feature = "rpi")]
+pub const MIN_LIDAR_QUALITY: u8 = 10;
+
+#[derive(Parser, Debug)]
+/// Configuration for the achiever session from the CLI
+struct Config {
+    #[arg(short = 'b', long = "body")]
+    /// A path to the JSON file serializing the agent's body
+    body: PathBuf,
+    #[arg(short = 'g', long = "with-goals", value_delimiter=' ', num_args = 1..)]
+    /// Pairs of a point dimension and whether it's maximized, such as `light true z false`. A
+    /// dimension is named as in a reward expression, or given by its index
+    with_goals: Option<Vec<String>>,
+    #[arg(short = 't', long = "threshold")]
+    /// The threshold for when the fitness is acceptable
+    threshold: f32,
+    #[arg(short = 'w', long = "window", default_value_t = 100)]
+    /// How many of the most recent rewards a plateau or oscillation is judged over
+    window: usize,
+    #[arg(short = 'e', long = "epsilon", default_value_t = 0.01)]
+    /// How little the reward may move across the window to count as a plateau
+    epsilon: f64,
+    #[arg(short = 's', long = "server")]
+    /// An optional server to bind to
+    server: Option<String>,
+    #[arg(short = 'c', long = "calibration")]
+    /// An optional path to the JSON calibration file for the body's inputs
+    calibration: Option<PathBuf>,
+    #[arg(short = 'j', long = "journal")]
+    /// An optional directory to log collected points to, so they survive a crash
+    journal: Option<PathBuf>,
+    #[arg(short = 'r', long = "reward")]
+    /// An optional reward expression over the agent's position, as `x`, `y` and `z`, and the
+    /// body's channels, replacing any goals, such as `maximize(light) - 0.5*abs(temp - 22)`
+    reward: Option<String>,
+    #[arg(long = "weights", value_delimiter = ' ', num_args = 1..)]
+    /// An optional weight per objective. When given, the hivemind returns its Pareto front of
+    /// plans and the one with the highest weighted sum of objectives is performed
+    weights: Option<Vec<f64>>,
+    #[arg(long = "position", value_delimiter = ' ', num_args = 3, default_values_t = [0.0, 0.0, 0.0])]
+    /// Where the agent senses from, as x y z. Every point collected is placed here unless a
+    /// lidar places them, and feedback is always reported from here
+    position: Vec<f32>,
+    #[cfg(feature = "rpi")]
+    #[arg(long = "lidar")]
+    /// Place every point collected at a return from an RPLidar A1 on the Pi's UART
+    lidar: bool,
+    #[cfg(feature = "rpi")]
+    #[arg(long = "lidar-motor-pin", default_value_t = 18, requires = "lidar")]
+    /// The GPIO pin spinning the lidar's motor
+    lidar_motor_pin: u8,
+    #[cfg(feature = "rpi")]
+    #[arg(long = "lidar-mount", value_delimiter = ' ', num_args = 2, default_values_t = [0.0, 0.0], requires = "lidar")]
+    /// The lidar's roll and pitch on the agent in degrees
+    lidar_mount: Vec<f32>,
+    #[arg(long = "plan-budget-ms")]
+    /// An optional limit on how long a plan may take, checked before anything moves
+    plan_budget_ms: Option<u64>,
+    #[arg(long = "plan-policy", value_enum, default_value_t = PlanPolicy::Reject)]
+    /// What to do with a plan that fails validation
+    plan_policy: PlanPolicy,
+    #[arg(long = "watchdog-ms", default_value_t = 5_000)]
+    /// How long the agent loop and hivemind connection may go quiet before every output is
+    /// brought to its safe state
+    watchdog_ms: u64,
+    #[cfg(feature = "local")]
+    #[arg(short = 'l', long = "local")]
+    /// Plan with a hivemind running in this process rather than a remote server, for sites with
+    /// no network
+    local: bool,
+    #[cfg(feature = "local")]
+    #[arg(long = "sim-budget", default_value_t = LOCAL_NUM_SIMS, requires = "local")]
+    /// How many simulations the local hivemind runs per training
+    sim_budget: usize,
+}
+
+/// What to do with a plan that fails validation, as given on the CLI
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PlanPolicy {
+    /// Perform none of it
+    Reject,
+    /// Perform the instructions before the first problem
+    ValidPrefix,
+}
+
+impl Config {
+    /// The validator every plan is checked with before it's performed
+    pub fn validator(&self) -> Validator {
+        let validator = Validator::new().with_policy(match self.plan_policy {
+            PlanPolicy::Reject => Policy::Reject,
+            PlanPolicy::ValidPrefix => Policy::ValidPrefix,
+        });
+        match self.plan_budget_ms {
+            Some(budget) => validator.with_budget(Duration::from_millis(budget)),
+            None => validator,
+        }
+    }
+
+    /// Builds the agent's body from its description and calibration, then its goals from
+    /// `--with-goals` against the body's channels
+    pub fn get_body_and_goals(&self) -> Result<(Body, Vec<ChannelGoal>), String> {
+        let mut body = BodyDescription::load(&self.body)
+            .and_then(|description| description.build())
+            .map_err(|err| format!("Failed to build the body: {err}"))?;
+        if let Some(path) = &self.calibration {
+            CalibrationFile::load(path)
+                .and_then(|calibration| calibration.apply_to(&mut body))
+                .map_err(|err| format!("Failed to load calibration file: {err}"))?;
+        }
+
+        let schema = body
+            .channel_schema()
+            .map_err(|err| format!("Body's channel columns are invalid: {err}"))?;
+        let pairs = self.with_goals.as_deref().unwrap_or_default();
+        if !pairs.len().is_multiple_of(2) {
+            return Err("Goals come in pairs of a dimension and true or false".into());
+        }
+
+        let goals = pairs
+            .chunks_exact(2)
+            .map(|pair| {
+                let maximize: bool = pair[1].parse().map_err(|_| {
+                    format!("Expected true or false after {}, got {}", pair[0], pair[1])
+                })?;
+                let goal = match maximize {
+                    true => Goal::Maximize,
+                    false => Goal::Minimize,
+                };
+                match pair[0].parse() {
+                    Ok(index) => Ok(ChannelGoal::new(index, goal)),
+                    Err(_) => schema
+                        .goal(&pair[0], goal)
+                        .ok_or_else(|| format!("No dimension is named {}", pair[0])),
+                }
+            })
+            .collect::<Result<_, String>>()?;
+        Ok((body, goals))
+    }
+}
+
+/// Starts reading the lidar on its own thread, turning each strong enough return into a position
+#[cfg(feature = "rpi")]
+fn spawn_lidar(args: &Config) -> Result<LidarSource, String> {
+    use std::cell::RefCell;
+
+    use rplidar_rppal::rplidar_a1::{RpLidarA1, ScanParser};
+    use rppal::{
+        gpio::Gpio,
+        uart::{Parity, Uart},
+    };
+
+    let motor = Gpio::new()
+        .and_then(|gpio| gpio.get(args.lidar_motor_pin))
+        .map_err(|err| format!("Failed to set up the lidar's motor: {err}"))?
+        .into_output();
+    let mut uart = Uart::new(115_200, Parity::None, 8, 1)
+        .map_err(|err| format!("Failed to open the lidar's UART: {err}"))?;
+    uart.set_read_mode(255, Duration::from_millis(500))
+        .map_err(|err| format!("Failed to set the lidar's read timeout: {err}"))?;
+
+    let (roll, pitch) = (args.lidar_mount[0], args.lidar_mount[1]);
+    let (returns, source) = LidarSource::channel();
+    std::thread::spawn(move || {
+        let parser = RefCell::new(ScanParser::new());
+        RpLidarA1::new(uart, motor).run_with_callback(|buf, read| {
+            for sample in parser.borrow_mut().push(&buf[..read as usize]) {
+                if sample.quality < MIN_LIDAR_QUALITY || sample.distance_mm == 0.0 {
+                    continue;
+                }
+                // Nobody is left to place points once the sampler is gone
+                let _ = returns.send(LidarReading {
+                    at: Instant::now(),
+                    roll,
+                    pitch,
+                    angle: sample.angle,
+                    distance: sample.distance_mm / 1000.0,
+                });
+            }
+        });
+    });
+    Ok(source)
+}
+
+/// What the hivemind trains towards, sent before the first training
+struct Objective {
+    /// Goals for single dimensions of each point
+    goals: Vec<ChannelGoal>,
+    /// A reward expression replacing the goals
+    reward: Option<Expr>,
+}
+
+/// Initializes an achiever system from a body description and the goals or reward set through the
+/// CLI
+#[tokio::main]
+pub async fn main() {
+    let args = Config::parse();
+
+    let (body, goals) = args.get_body_and_goals().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2)
+    });
+
+    let schema = body
+        .channel_schema()
+        .expect("Body's channel columns are invalid");
+    let dims = schema.point_dims();
+    if dims > DIMS {
+        eprintln!(
+            "Body has {} channels, making {dims} dimensional points, but the agent's goals cover \
+             at most {DIMS}",
+            schema.dims()
+        );
+        std::process::exit(2)
+    }
+
+    let reward = args.reward.as_deref().map(|source| {
+        schema.reward(source).unwrap_or_else(|err| {
+            eprintln!("Invalid reward expression:\n{}", err.pointer(source));
+            std::process::exit(2)
+        })
+    });
+    if goals.is_empty() && reward.is_none() {
+        eprintln!("Nothing to train towards, give the agent --with-goals or a --reward");
+        std::process::exit(2)
+    }
+
+    let mut scoring = PositionContextualReward::<DIMS>::default();
+    if let Err(err) = validate_goals(&goals, dims).and_then(|()| scoring.update(goals.clone())) {
+        eprintln!("{err}");
+        std::process::exit(2)
+    }
+    if let Some(reward) = &reward {
+        scoring.set_expression(reward.clone());
+    }
+    let objective = Objective { goals, reward };
+
+    let convergence = ConvergenceDetector::default()
+        .with_threshold(args.threshold as f64)
+        .with_plateau(args.window, args.epsilon)
+        .with_oscillation(args.window, args.window / 4, args.epsilon);
+
+    // The watchdog shares the body so it can bring it to rest however stuck the loop gets,
+    // and stops any plan underway when it trips
+    let body = Arc::new(Mutex::new(body));
+    let executor = Executor::new().with_validator(args.validator());
+    let watchdog = Watchdog::new(Duration::from_millis(args.watchdog_ms))
+        .with_stop_signal(executor.stop_signal());
+    let feeder = watchdog.feeder();
+    let _watchdog = watchdog.spawn(&body, WATCHDOG_PERIOD);
+
+    let mut builder = AgentSession::<_, Untrained, BUFFER_SIZE>::builder()
+        .with_body(body)
+        .with_goal(scoring)
+        .with_buffer(DataBuffer::default().with_dims(dims))
+        .with_convergence(convergence);
+    if let Some(dir) = &args.journal {
+        builder = builder.with_journal(Journal::open(dir).expect("Failed to open journal"));
+    }
+    let agent = builder.build().unwrap();
+
+    #[cfg(feature = "local")]
+    if args.local {
+        let hivemind = LocalHivemind::default().with_sim_budget(args.sim_budget);
+        run(hivemind, agent, feeder, executor, dims, objective, &args).await;
+        return;
+    }
+
+    // Connect to server
+    let server_to = args.server.as_deref().unwrap_or("0.0.0.0:1940");
+    let urdf = std::fs::read_to_string(&args.body).expect("Failed to read body file");
+    let hivemind = RemoteHivemind::connect(server_to, &urdf)
+        .await
+        .expect("Failed to connect to hivemind server")
+        .with_feeder(feeder.clone());
+    run(hivemind, agent, feeder, executor, dims, objective, &args).await;
+}
+
+/// Runs the agent's cycle of collecting, training, performing and reporting against a hivemind,
+/// wherever it lives, until the reward is good enough. The watchdog is fed on every pass of the
+/// loop
+async fn run<REWARD: Rewardable>(
+    mut hivemind: impl Hivemind,
+    mut agent: AgentSession<REWARD, Untrained, BUFFER_SIZE>,
+    feeder: Feeder,
+    executor: Executor,
+    dims: usize,
+    objective: Objective,
+    args: &Config,
+) {
+    hivemind
+        .set_dims(dims)
+        .await
+        .expect("Failed to set session dimensions");
+    if !objective.goals.is_empty() {
+        hivemind
+            .set_goals(objective.goals)
+            .await
+            .expect("Failed to set session goals");
+    }
+    if let Some(reward) = objective.reward {
+        hivemind
+            .set_reward(reward)
+            .await
+            .expect("Failed to set session reward");
+    }
+
+    // Upload anything logged but never acknowledged, say from before a crash
+    if let Some(journal) = agent.journal_mut() {
+        // Points from the last run are still in the open segment until it's sealed
+        journal.rotate().expect("Failed to seal journal segment");
+        for segment in journal.pending() {
+            let points = journal
+                .read_segment(segment.id)
+                .expect("Failed to read journal segment");
+            hivemind
+                .send_data(&points.concat())
+                .await
+                .expect("Failed to upload journal segment");
+            journal
+                .acknowledge(segment.id)
+                .expect("Failed to acknowledge journal segment");
+        }
+    }
+
+    let schema = agent
+        .get_body()
+        .lock()
+        .unwrap()
+        .channel_schema()
+        .expect("Body's channel columns are invalid");
+    let position = [args.position[0], args.position[1], args.position[2]];
+    let source: Box<dyn SpatialSource> = Box::new(FixedPosition::new(position, DEFAULT_PERIOD));
+    #[cfg(feature = "rpi")]
+    let source: Box<dyn SpatialSource> = match args.lidar {
+        true => Box::new(spawn_lidar(args).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(2)
+        })),
+        false => source,
+    };
+    let mut points = SpatialSampler::new(Sampler::new(&schema), source, FUSION_TOLERANCE);
+
+    loop {
+        // Collect until the buffer is full, a slice at a time so the watchdog stays fed
+        loop {
+            feeder.feed();
+            let deadline = Instant::now() + COLLECT_SLICE;
+            let full = agent
+                .collect(&mut points, deadline)
+                .expect("Failed to log point");
+            // Whatever the slice logged is on disk before the next one starts
+            if let Some(journal) = agent.journal_mut() {
+                journal.sync().expect("Failed to sync journal");
+            }
+            if full {
+                break;
+            }
+        }
+
+        hivemind
+            .send_data(&agent.export())
+            .await
+            .expect("Failed to send buffer");
+
+        // Everything logged so far has now been sent
+        if let Some(journal) = agent.journal_mut() {
+            journal.rotate().expect("Failed to seal journal segment");
+            if let Some(sent) = journal.pending().last() {
+                journal
+                    .acknowledge(sent.id)
+                    .expect("Failed to acknowledge journal segment");
+            }
+        }
+
+        match agent.convergence() {
+            Some(Convergence::Threshold(reward)) => {
+                println!("Reached a reward of {reward}, stopping");
+                break;
+            }
+            Some(convergence) => {
+                println!("Reward has converged ({convergence:?}), retraining");
+                agent.reset_history();
+            }
+            None => {}
+        }
+
+        // Tell server to begin training
+        let plan = match &args.weights {
+            Some(weights) => {
+                let front = hivemind.train_front().await.expect("Failed to train agent");
+                match front.choose(weights) {
+                    Some(candidate) => candidate.plan(),
+                    None => {
+                        eprintln!("The hivemind trained no plans, collecting more data");
+                        continue;
+                    }
+                }
+            }
+            None => hivemind.train().await.expect("Failed to train agent"),
+        };
+
+        // Perform the plan, then tell the hivemind how it went, even if only part of it or none of
+        // it ran. The watchdog may have stopped the last plan while training kept the loop quiet,
+        // so the stop is cleared once fed again
+        feeder.feed();
+        executor.stop_signal().reset();
+        let mut reviewing = agent.review(plan);
+        let run = match reviewing.act_async(&executor).await {
+            Ok(run) if run.outcome == Outcome::Completed => run,
+            Ok(run) => {
+                eprintln!("Plan was cut short: {:?}", run.outcome);
+                run
+            }
+            Err(err) => {
+                eprintln!("Plan was not performed: {err}");
+                reviewing.unperformed(err.as_ref())
+            }
+        };
+        let feedback = reviewing
+            .report(&run, position)
+            .expect("Failed to read channels after the plan");
+        hivemind
+            .report(feedback)
+            .await
+            .expect("Failed to report feedback");
+        agent = reviewing.retrain();
+    }
+}
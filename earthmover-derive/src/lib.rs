@@ -0,0 +1,289 @@
+//! Derive macros for earthmover, most notably `#[derive(Rewardable)]` for building rewards out of
+//! structs of readings
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, ExprLit, ExprUnary, Index, Lit,
+    UnOp,
+};
+
+/// Derives `Rewardable` for a struct, summing a weighted score for each of its fields. Every field
+/// must itself be `Rewardable`, and is maximized with a weight of 1 unless annotated:
+///
+/// ```ignore
+/// #[derive(Rewardable)]
+/// #[reward(breakdown)]
+/// struct Greenhouse {
+///     #[reward(maximize, weight = 2.0)]
+///     light: f32,
+///     #[reward(target = 22.0, tolerance = 0.5)]
+///     temperature: f64,
+///     #[reward(minimize)]
+///     water_used: f32,
+///     #[reward(skip)]
+///     label: String,
+/// }
+/// ```
+///
+/// `#[reward(breakdown)]` on the struct also generates a `reward_breakdown` method listing each
+/// field's weighted contribution
+#[proc_macro_derive(Rewardable, attributes(reward))]
+pub fn derive_rewardable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The direction a field is scored in
+enum Direction {
+    /// Higher is better
+    Maximize,
+    /// Lower is better
+    Minimize,
+    /// Closer to the value is better
+    Target(Expr),
+}
+
+/// How a single field contributes to the reward
+struct FieldReward {
+    /// The field's accessor, a name or a tuple index
+    member: TokenStream2,
+    /// The name reported in the breakdown
+    name: String,
+    /// How the field is scored
+    direction: Direction,
+    /// How much the field's score counts towards the total
+    weight: Option<Expr>,
+    /// How far from a target still counts as on target
+    tolerance: Option<Expr>,
+}
+
+impl FieldReward {
+    /// The expression for this field's weighted score
+    fn score(&self) -> TokenStream2 {
+        let member = &self.member;
+        let goal = match &self.direction {
+            Direction::Maximize => quote!(::earthmover_achiever::goals::Goal::Maximize),
+            Direction::Minimize => quote!(::earthmover_achiever::goals::Goal::Minimize),
+            Direction::Target(target) => {
+                quote!(::earthmover_achiever::goals::Goal::Target((#target) as f64))
+            }
+        };
+        let weight = as_f64(self.weight.as_ref(), quote!(1.0));
+        let tolerance = as_f64(self.tolerance.as_ref(), quote!(0.0));
+
+        quote! {
+            #weight * #goal.score(
+                ::earthmover_achiever::goals::Rewardable::to_reward(&self.#member),
+                #tolerance,
+            )
+        }
+    }
+}
+
+/// An optional numeric literal as an f64 expression, or `default` when it's missing
+fn as_f64(value: Option<&Expr>, default: TokenStream2) -> TokenStream2 {
+    match value {
+        Some(value) => quote!(((#value) as f64)),
+        None => default,
+    }
+}
+
+/// Generates the `Rewardable` impl, or the first problem with the annotations
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(data) => {
+            return Err(syn::Error::new(
+                data.enum_token.span,
+                "Rewardable can only be derived for structs",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span,
+                "Rewardable can only be derived for structs",
+            ))
+        }
+    };
+
+    let breakdown = struct_options(&input)?;
+
+    let mut rewards = vec![];
+    for (idx, field) in fields.iter().enumerate() {
+        let (member, name) = match &field.ident {
+            Some(ident) => (ident.to_token_stream(), ident.to_string()),
+            None => (Index::from(idx).to_token_stream(), idx.to_string()),
+        };
+        if let Some(reward) = field_options(field, member, name)? {
+            rewards.push(reward);
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let scores = rewards.iter().map(FieldReward::score).collect::<Vec<_>>();
+
+    let breakdown = breakdown.then(|| {
+        let names = rewards.iter().map(|reward| &reward.name);
+        quote! {
+            impl #impl_generics #ident #ty_generics #where_clause {
+                /// Each rewarded field's name alongside its weighted contribution to the reward
+                pub fn reward_breakdown(&self) -> ::std::vec::Vec<(&'static str, f64)> {
+                    ::std::vec![#((#names, #scores)),*]
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::earthmover_achiever::goals::Rewardable for #ident #ty_generics #where_clause {
+            fn to_reward(&self) -> f64 {
+                0.0 #(+ #scores)*
+            }
+        }
+
+        #breakdown
+    })
+}
+
+/// Parses the struct level `#[reward(..)]` options, returning whether a breakdown was asked for
+fn struct_options(input: &DeriveInput) -> syn::Result<bool> {
+    let mut breakdown = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("reward"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("breakdown") {
+                breakdown = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown struct level reward option, expected `breakdown`"))
+            }
+        })?;
+    }
+
+    Ok(breakdown)
+}
+
+/// Parses a field's `#[reward(..)]` options, `None` if the field is skipped
+fn field_options(
+    field: &syn::Field,
+    member: TokenStream2,
+    name: String,
+) -> syn::Result<Option<FieldReward>> {
+    let mut skip = None;
+    let mut direction = None;
+    let mut weight = None;
+    let mut tolerance = None;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("reward"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let span = meta.path.span();
+            if meta.path.is_ident("skip") {
+                skip = Some(span);
+            } else if meta.path.is_ident("maximize") {
+                set_direction(&mut direction, Direction::Maximize, &meta)?;
+            } else if meta.path.is_ident("minimize") {
+                set_direction(&mut direction, Direction::Minimize, &meta)?;
+            } else if meta.path.is_ident("target") {
+                let target = number(&meta)?;
+                set_direction(&mut direction, Direction::Target(target), &meta)?;
+            } else if meta.path.is_ident("weight") {
+                set_once(&mut weight, number(&meta)?, &meta)?;
+            } else if meta.path.is_ident("tolerance") {
+                set_once(&mut tolerance, number(&meta)?, &meta)?;
+            } else {
+                return Err(meta.error(
+                    "unknown reward option, expected one of `maximize`, `minimize`, `target`, \
+                     `weight`, `tolerance` or `skip`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+
+    if let Some(span) = skip {
+        if direction.is_some() || weight.is_some() || tolerance.is_some() {
+            return Err(syn::Error::new(
+                span,
+                "a skipped field can't have any other reward options",
+            ));
+        }
+        return Ok(None);
+    }
+
+    let direction = direction.unwrap_or(Direction::Maximize);
+    if let (Some(tolerance), false) = (&tolerance, matches!(direction, Direction::Target(_))) {
+        return Err(syn::Error::new_spanned(
+            tolerance,
+            "`tolerance` only applies to fields with a `target`",
+        ));
+    }
+
+    Ok(Some(FieldReward {
+        member,
+        name,
+        direction,
+        weight,
+        tolerance,
+    }))
+}
+
+/// Sets a field's direction, erroring if it already has one
+fn set_direction(
+    direction: &mut Option<Direction>,
+    new: Direction,
+    meta: &syn::meta::ParseNestedMeta<'_>,
+) -> syn::Result<()> {
+    if direction.is_some() {
+        return Err(meta.error(
+            "conflicting reward directions, use only one of `maximize`, `minimize` or `target`",
+        ));
+    }
+    *direction = Some(new);
+    Ok(())
+}
+
+/// Sets a numeric option, erroring if it was already given
+fn set_once(
+    slot: &mut Option<Expr>,
+    value: Expr,
+    meta: &syn::meta::ParseNestedMeta<'_>,
+) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(meta.error("duplicate reward option"));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Parses the `= value` of an option, which must be a numeric literal
+fn number(meta: &syn::meta::ParseNestedMeta<'_>) -> syn::Result<Expr> {
+    let value: Expr = meta.value()?.parse()?;
+    let literal = match &value {
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => expr.as_ref(),
+        other => other,
+    };
+
+    match literal {
+        Expr::Lit(ExprLit {
+            lit: Lit::Float(_) | Lit::Int(_),
+            ..
+        }) => Ok(value),
+        _ => Err(syn::Error::new_spanned(value, "expected a number")),
+    }
+}
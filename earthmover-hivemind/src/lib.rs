@@ -1,8 +1,12 @@
 //! All required defintions for handling AHTP incoming state messages
 
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
 use service::ServerService;
 use state::{message::MessageReceiver, ServerState};
+use tokio::net::TcpListener;
 
+pub mod local;
 pub mod service;
 pub mod state;
 
@@ -15,3 +19,35 @@ pub fn new_state() -> (MessageReceiver, ServerState, ServerService) {
 
     (msg_reader, state, service)
 }
+
+/// Serves AHTP sessions on a listener, handling every session's messages until the listener fails
+pub async fn serve(listener: TcpListener) {
+    let (mut msg_queue, mut state, service) = new_state();
+
+    tokio::spawn(async move {
+        loop {
+            // Handle connections
+            let (socket, _) = listener
+                .accept()
+                .await
+                .expect("Error accepting incoming connection");
+
+            let io = TokioIo::new(socket);
+
+            let service = service.clone();
+            tokio::spawn(async move {
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(io, service)
+                    .with_upgrades()
+                    .await
+                {
+                    eprintln!("Error serving connection: {}", e);
+                }
+            });
+        }
+    });
+
+    while let Some(msg) = msg_queue.recv().await {
+        state.handle(msg).await
+    }
+}
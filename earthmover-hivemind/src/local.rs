@@ -0,0 +1,255 @@
+//! A hivemind embedded in the agent's own process, for sites with no network at all
+
+use earthmover_achiever::{
+    brain::{feedback::ExecutionFeedback, instruction::Plan, pareto::ParetoFront},
+    client::{ClientError, Hivemind, Result},
+    goals::{expr::Expr, multi_dim::PositionContextualReward, validate_goals, ChannelGoal},
+};
+use earthmover_simulation::sim::backend::{physics::BevyPhysicsInformedBackend, Simulation};
+
+use crate::state::{
+    check_dims, check_reward, session_dims, train_front_on, train_on, PlanLog, NUM_DIMS,
+};
+
+/// How many simulations an embedded hivemind runs per training by default. Much smaller than the
+/// server's `NUM_SIMS` since it shares the agent's hardware
+pub const LOCAL_NUM_SIMS: usize = 1_000;
+
+/// A hivemind that runs the simulation `Orchestrator` in-process instead of over AHTP. Implements
+/// the same `Hivemind` client interface as a remote server, so an agent can plan locally
+pub struct LocalHivemind<
+    SIM: Simulation + Send + Sync + Copy + 'static = BevyPhysicsInformedBackend,
+> {
+    /// The simulation backend to train with
+    backend: SIM,
+    /// How many simulations to run per training
+    sim_budget: usize,
+    /// Simulation dimensions
+    dims: usize,
+    /// Current goal
+    goal: PositionContextualReward<NUM_DIMS>,
+    /// Current data read in
+    buf: Vec<f32>,
+    /// Every plan trained so far
+    plans: PlanLog,
+}
+
+impl Default for LocalHivemind {
+    fn default() -> Self {
+        Self::new(BevyPhysicsInformedBackend)
+    }
+}
+
+impl<SIM: Simulation + Send + Sync + Copy + 'static> LocalHivemind<SIM> {
+    /// Creates a new embedded hivemind over a simulation backend
+    pub fn new(backend: SIM) -> Self {
+        Self {
+            backend,
+            sim_budget: LOCAL_NUM_SIMS,
+            dims: 0,
+            goal: PositionContextualReward::default(),
+            buf: vec![],
+            plans: PlanLog::default(),
+        }
+    }
+
+    /// Sets how many simulations are run per training
+    pub fn with_sim_budget(mut self, sim_budget: usize) -> Self {
+        self.sim_budget = sim_budget;
+        self
+    }
+
+    /// Every plan trained so far
+    pub fn plans(&self) -> &PlanLog {
+        &self.plans
+    }
+
+    /// The dimensionality the agent has set for this session
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+}
+
+impl<SIM: Simulation + Send + Sync + Copy + 'static> Hivemind for LocalHivemind<SIM> {
+    async fn set_dims(&mut self, dims: usize) -> Result<()> {
+        check_dims(dims).map_err(ClientError::Rejected)?;
+        self.dims = dims;
+        Ok(())
+    }
+
+    async fn set_goals(&mut self, goals: Vec<ChannelGoal>) -> Result<()> {
+        validate_goals(&goals, session_dims(self.dims))?;
+        Ok(self.goal.update(goals)?)
+    }
+
+    async fn set_reward(&mut self, reward: Expr) -> Result<()> {
+        check_reward(&reward, self.dims).map_err(ClientError::Rejected)?;
+        self.goal.set_expression(reward);
+        Ok(())
+    }
+
+    async fn send_data(&mut self, buf: &[f32]) -> Result<()> {
+        if self.dims == 0 || !buf.len().is_multiple_of(self.dims) {
+            return Err(ClientError::Misshapen {
+                len: buf.len(),
+                dims: self.dims,
+            });
+        }
+
+        self.buf.extend(buf);
+        Ok(())
+    }
+
+    async fn train(&mut self) -> Result<Plan> {
+        let best_fit = train_on(
+            self.backend,
+            self.goal.clone(),
+            &self.buf,
+            self.dims,
+            self.sim_budget,
+        )
+        .await;
+        Ok(self.plans.record(&best_fit))
+    }
+
+    async fn train_front(&mut self) -> Result<ParetoFront> {
+        let mut front = train_front_on(
+            self.backend,
+            self.goal.clone(),
+            &self.buf,
+            self.dims,
+            self.sim_budget,
+        )
+        .await;
+        self.plans.record_front(&mut front);
+        Ok(front)
+    }
+
+    async fn report(&mut self, feedback: ExecutionFeedback) -> Result<()> {
+        let plan = feedback.plan;
+        if self.plans.record_feedback(feedback) {
+            Ok(())
+        } else {
+            Err(ClientError::Rejected(format!(
+                "Feedback was reported on plan {plan}, which was never trained"
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use earthmover_achiever::{
+        brain::{feedback::ExecutionFeedback, instruction::Instruction},
+        client::{ClientError, Hivemind},
+        goals::{expr::Expr, ChannelGoal, Rewardable},
+    };
+    use earthmover_simulation::sim::{backend::Simulation, SimArgs, SimMessage};
+    use tokio::sync::mpsc::UnboundedSender;
+
+    use crate::state::NUM_DIMS;
+
+    use super::LocalHivemind;
+
+    /// A test backend that always plans one instruction, scoring it the same every time
+    #[derive(Clone, Copy)]
+    struct FixedBackend;
+
+    impl Simulation for FixedBackend {
+        fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
+            &self,
+            _args: Arc<SimArgs<REWARD, DIMS>>,
+            message_sender: UnboundedSender<SimMessage>,
+        ) {
+            message_sender
+                .send(SimMessage::Instruction(Instruction::default()))
+                .expect("Failed to send instruction");
+            message_sender
+                .send(SimMessage::Objectives(vec![1.0, 0.5]))
+                .expect("Failed to send objectives");
+            message_sender
+                .send(SimMessage::Close(1.5))
+                .expect("Failed to close out simulation");
+        }
+    }
+
+    #[tokio::test]
+    async fn local_hivemind_trains_and_records_plans() {
+        let mut hivemind = LocalHivemind::new(FixedBackend).with_sim_budget(10);
+        hivemind.set_dims(3).await.unwrap();
+        hivemind
+            .set_goals(vec![ChannelGoal::maximize(0)])
+            .await
+            .unwrap();
+        hivemind.send_data(&[0.0, 1.0, 2.0]).await.unwrap();
+
+        let first = hivemind.train().await.unwrap();
+        let second = hivemind.train().await.unwrap();
+        assert_eq!(first.instructions, vec![Instruction::default()]);
+        assert_ne!(first.id, second.id);
+        assert_eq!(hivemind.plans().plans().len(), 2);
+        assert_eq!(hivemind.plans().get(first.id).unwrap().predicted_score, 1.5);
+
+        hivemind
+            .report(
+                ExecutionFeedback::default()
+                    .with_plan(first.id)
+                    .with_reward(1.0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            hivemind.plans().get(first.id).unwrap().prediction_error(),
+            Some(-0.5)
+        );
+        assert_eq!(hivemind.plans().get(second.id).unwrap().feedback, None);
+    }
+
+    #[tokio::test]
+    async fn local_hivemind_trains_fronts() {
+        let mut hivemind = LocalHivemind::new(FixedBackend).with_sim_budget(10);
+        let front = hivemind.train_front().await.unwrap();
+
+        assert_eq!(front.len(), 1);
+        assert_eq!(front.candidates()[0].objectives, vec![1.0, 0.5]);
+
+        let chosen = front.candidates()[0].id;
+        assert_eq!(hivemind.plans().get(chosen).unwrap().predicted_score, 1.5);
+        hivemind
+            .report(ExecutionFeedback::default().with_plan(chosen))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn local_hivemind_rejects_bad_dims_goals_rewards_and_early_feedback() {
+        let mut hivemind = LocalHivemind::new(FixedBackend);
+        for dims in [2, NUM_DIMS + 1] {
+            assert!(matches!(
+                hivemind.set_dims(dims).await,
+                Err(ClientError::Rejected(_))
+            ));
+        }
+        hivemind.set_dims(3).await.unwrap();
+
+        assert!(matches!(
+            hivemind.set_goals(vec![ChannelGoal::maximize(7)]).await,
+            Err(ClientError::Goals(_))
+        ));
+        assert!(matches!(
+            hivemind.send_data(&[0.0; 4]).await,
+            Err(ClientError::Misshapen { len: 4, dims: 3 })
+        ));
+        assert!(matches!(
+            hivemind.set_reward(Expr::Channel(3)).await,
+            Err(ClientError::Rejected(_))
+        ));
+        hivemind.set_reward(Expr::Channel(2)).await.unwrap();
+        assert!(matches!(
+            hivemind.report(ExecutionFeedback::default()).await,
+            Err(ClientError::Rejected(_))
+        ));
+    }
+}
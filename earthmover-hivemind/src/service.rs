@@ -2,6 +2,13 @@
 
 pub mod service_impl;
 
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use uuid::Uuid;
+
 use crate::state::message::MessageSender;
 
 /// A server service handler
@@ -9,11 +16,30 @@ use crate::state::message::MessageSender;
 pub struct ServerService {
     /// The message send channel
     pub message_sender: MessageSender,
+    /// Sessions that have been initiated but not yet connected to, with the URDF each was
+    /// initiated with
+    initiated: Arc<Mutex<HashMap<Uuid, String>>>,
 }
 
 impl ServerService {
     /// Creates a new service instance
     pub fn new(message_sender: MessageSender) -> Self {
-        Self { message_sender }
+        Self {
+            message_sender,
+            initiated: Arc::default(),
+        }
+    }
+
+    /// Issues a new session ID for an agent described by `urdf`
+    pub fn initiate(&self, urdf: String) -> Uuid {
+        let id = Uuid::new_v4();
+        self.initiated.lock().unwrap().insert(id, urdf);
+        id
+    }
+
+    /// Claims an initiated session so a socket can connect to it, returning its URDF. Each session
+    /// can only be claimed once
+    pub fn claim(&self, id: &Uuid) -> Option<String> {
+        self.initiated.lock().unwrap().remove(id)
     }
 }
@@ -2,6 +2,7 @@
 
 use std::{future::Future, pin::Pin};
 
+use earthmover_achiever::protocol::AhtpRequest;
 use futures_util::{SinkExt, StreamExt};
 use http_body_util::Full;
 use hyper::{
@@ -9,9 +10,10 @@ use hyper::{
     service::Service,
     Request, Response, StatusCode,
 };
-use uuid::Uuid;
+use hyper_tungstenite::{tungstenite, HyperWebsocket};
+use tracing::warn;
 
-use crate::state::message::Message;
+use crate::state::message::{Message, Response as AhtpResponse};
 
 use super::ServerService;
 
@@ -21,57 +23,28 @@ impl Service<Request<body::Incoming>> for ServerService {
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn call(&self, mut req: Request<body::Incoming>) -> Self::Future {
-        let sender = self.message_sender.clone();
         if hyper_tungstenite::is_upgrade_request(&req) {
             let (response, websocket) =
                 hyper_tungstenite::upgrade(&mut req, None).expect("Error upgrading to WebSocket");
-            tokio::spawn(async move {
-                match websocket.await {
-                    Ok(ws) => {
-                        let (mut writer, mut reader) = ws.split();
-                        let id = Uuid::new_v4();
-                        let (com_writer, mut com_reader) = tokio::sync::mpsc::unbounded_channel();
-                        sender
-                            .send(Message::Connection(id, com_writer))
-                            .expect("Failed to send to sender");
-
-                        tokio::spawn(async move {
-                            while let Some(response) = com_reader.recv().await {
-                                writer
-                                    .send(hyper_tungstenite::tungstenite::Message::Text(
-                                        response
-                                            .serialize_to_string()
-                                            .expect("Failed to serialize response message"),
-                                    ))
-                                    .await
-                                    .expect("Failed to send ws message");
-                            }
-                        });
-
-                        while let Some(msg) = reader.next().await {
-                            match msg {
-                                Ok(msg) => {
-                                    if let hyper_tungstenite::tungstenite::Message::Text(txt) = msg
-                                    {
-                                        let message = Message::from_string(&txt);
-                                        if let Ok(message) = message {
-                                            sender
-                                                .send(message)
-                                                .expect("Failed to send back to channel");
-                                        }
-                                    }
-                                }
-                                Err(err) => eprintln!("{err}"),
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("Failed to establish WebSocket Connection: {}", err)
-                    }
-                }
-            });
+            tokio::spawn(self.clone().serve_socket(websocket));
 
             Box::pin(async { Ok(response) })
+        } else if req.uri().path() == "/initiate" {
+            let res = match urdf_param(req.uri().query()) {
+                Some(urdf) => {
+                    let id = self.initiate(urdf);
+                    let body = AhtpResponse::Initialized(id)
+                        .to_json_string()
+                        .expect("Failed to serialize initiation response");
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .body(Full::new(Bytes::from(body)))
+                }
+                None => Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Full::new(Bytes::from_static(b"Missing urdf parameter"))),
+            };
+            Box::pin(async { res })
         } else {
             let response = Response::builder().status(StatusCode::OK);
 
@@ -94,3 +67,95 @@ ___\.~~-.-~|     .-~_
         }
     }
 }
+
+impl ServerService {
+    /// Serves an upgraded websocket. The first message must connect to an initiated session, and
+    /// every message after it belongs to that session
+    async fn serve_socket(self, websocket: HyperWebsocket) {
+        let ws = match websocket.await {
+            Ok(ws) => ws,
+            Err(err) => {
+                eprintln!("Failed to establish WebSocket Connection: {}", err);
+                return;
+            }
+        };
+        let (mut writer, mut reader) = ws.split();
+        let (com_writer, mut com_reader) = tokio::sync::mpsc::unbounded_channel::<AhtpResponse>();
+
+        tokio::spawn(async move {
+            while let Some(response) = com_reader.recv().await {
+                let text = response
+                    .to_json_string()
+                    .expect("Failed to serialize response message");
+                if writer.send(tungstenite::Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let id = match next_request(&mut reader).await {
+            Some(Ok(AhtpRequest::Connect(id))) => id,
+            Some(Ok(_)) => return reject(&com_writer, "The first message must be a Connect"),
+            Some(Err(err)) => return reject(&com_writer, &format!("Malformed message: {err}")),
+            None => return,
+        };
+        let Some(urdf) = self.claim(&id) else {
+            return reject(&com_writer, &format!("Session {id} was never initiated"));
+        };
+
+        self.message_sender
+            .send(Message::Connection(id, com_writer.clone(), urdf))
+            .expect("Failed to send to sender");
+
+        while let Some(request) = next_request(&mut reader).await {
+            match request.map(|request| Message::from_request(id, request)) {
+                Ok(Some(message)) => self
+                    .message_sender
+                    .send(message)
+                    .expect("Failed to send back to channel"),
+                Ok(None) => reject(&com_writer, "Already connected to a session"),
+                Err(err) => {
+                    warn!("Malformed message from session {id}: {err}");
+                    reject(&com_writer, &format!("Malformed message: {err}"))
+                }
+            }
+        }
+
+        self.message_sender
+            .send(Message::Disconnection(id))
+            .expect("Failed to send back to channel");
+    }
+}
+
+/// Reads the next text frame from a socket as a request, `None` once the socket closes
+async fn next_request(
+    reader: &mut (impl StreamExt<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin),
+) -> Option<serde_json::Result<AhtpRequest>> {
+    while let Some(msg) = reader.next().await {
+        match msg {
+            Ok(tungstenite::Message::Text(txt)) => return Some(AhtpRequest::from_json_str(&txt)),
+            Ok(tungstenite::Message::Close(_)) => return None,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{err}");
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Tells a client its message was rejected
+fn reject(responses: &tokio::sync::mpsc::UnboundedSender<AhtpResponse>, reason: &str) {
+    // The client may already be gone, in which case there's no one left to tell
+    let _ = responses.send(AhtpResponse::Rejected(reason.into()));
+}
+
+/// The URL decoded `urdf` parameter of a query string
+fn urdf_param(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("urdf="))
+        .and_then(|urdf| urlencoding::decode(urdf).ok())
+        .map(|urdf| urdf.into_owned())
+}
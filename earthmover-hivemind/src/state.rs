@@ -5,20 +5,35 @@ use std::{
     ops::{Index, IndexMut},
 };
 
-use earthmover_achiever::{body::Body, goals::multi_dim::PositionContextualReward};
+use earthmover_achiever::{
+    body::Body,
+    brain::{
+        feedback::ExecutionFeedback,
+        fusion::SPATIAL_DIMS,
+        instruction::{Instruction, Plan, PlanId},
+        pareto::{Candidate, ParetoFront},
+    },
+    goals::{
+        expr::Expr, multi_dim::PositionContextualReward, validate_goals, ChannelGoal, GoalError,
+    },
+};
 use earthmover_simulation::{
-    sim::{backend::physics::BevyPhysicsInformedBackend, SimArgs, SimRes},
+    sim::{
+        backend::{physics::BevyPhysicsInformedBackend, Simulation},
+        SimArgs, SimRes,
+    },
     Orchestrator,
 };
-use message::{Response, ResponseSender};
+use message::{Message, Response, ResponseSender};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 pub mod message;
 
 /// How many simulations per "batch"
 pub const NUM_SIMS: usize = 100_000;
-/// Dimension of batch
-pub const NUM_DIMS: usize = 3;
+/// The widest point the hivemind simulates. Narrower points are padded with zeros
+pub const NUM_DIMS: usize = 16;
 
 /// The current server's state
 #[derive(Default)]
@@ -29,8 +44,110 @@ pub struct ServerState {
 
 impl ServerState {
     /// Adds a new session to the internal sessions
-    pub fn new_session(&mut self, id: Uuid, channel: ResponseSender) {
-        self.sessions.insert(id, Connection::new(channel));
+    pub fn new_session(&mut self, id: Uuid, channel: ResponseSender, urdf: String) {
+        self.sessions
+            .insert(id, Connection::new(channel).with_urdf(urdf));
+    }
+
+    /// Handles a message from a session, responding to it where the protocol calls for it
+    pub async fn handle(&mut self, msg: Message) {
+        match msg {
+            Message::Connection(id, res_channel, urdf) => {
+                self.new_session(id, res_channel, urdf);
+            }
+            Message::SetDims(id, dims) => {
+                let response = match self[&id].set_dims(dims) {
+                    Ok(()) => Response::DimsSet,
+                    Err(reason) => {
+                        warn!("Rejected dimensions for session {id}: {reason}");
+                        Response::Rejected(reason)
+                    }
+                };
+                self[&id]
+                    .send(response)
+                    .expect("Failed to send message to response channel");
+            }
+            Message::Goal(id, goal) => {
+                let response = match self[&id].set_goals(goal) {
+                    Ok(()) => Response::GoalsSet,
+                    Err(err) => {
+                        warn!("Rejected goals for session {id}: {err}");
+                        Response::GoalError(err)
+                    }
+                };
+                self[&id]
+                    .send(response)
+                    .expect("Failed to send message to response channel");
+            }
+            Message::Reward(id, reward) => {
+                let response = match self[&id].set_reward(reward) {
+                    Ok(()) => Response::RewardSet,
+                    Err(reason) => {
+                        warn!("Rejected reward for session {id}: {reason}");
+                        Response::Rejected(reason)
+                    }
+                };
+                self[&id]
+                    .send(response)
+                    .expect("Failed to send message to response channel");
+            }
+            Message::SendData(id, points) => {
+                let response = match self[&id].write(&points) {
+                    Ok(()) => Response::DataReceived,
+                    Err(reason) => {
+                        warn!("Rejected data for session {id}: {reason}");
+                        Response::Rejected(reason)
+                    }
+                };
+                self[&id]
+                    .send(response)
+                    .expect("Failed to send message to response channel");
+            }
+            Message::Train(id) => match self[&id].train().await {
+                Some(result) => {
+                    info!("Trained to a fitness of {}", result.score);
+                    let plan = self[&id].record_plan(&result);
+                    let instruction_response = Response::Instruction(plan);
+                    self[&id]
+                        .send(instruction_response)
+                        .expect("Failed to propagate send instructions");
+                }
+                None => {
+                    self[&id]
+                        .send(Response::TrainError(
+                            "Not all agent attributes have been set yet".into(),
+                        ))
+                        .expect("Failed to send message to response channel");
+                }
+            },
+            Message::TrainFront(id) => match self[&id].train_front().await {
+                Some(mut front) => {
+                    self[&id].record_front(&mut front);
+                    info!("Trained a Pareto front of {} plans", front.len());
+                    self[&id]
+                        .send(Response::Front(front))
+                        .expect("Failed to propagate send Pareto front");
+                }
+                None => {
+                    self[&id]
+                        .send(Response::TrainError(
+                            "Not all agent attributes have been set yet".into(),
+                        ))
+                        .expect("Failed to send message to response channel");
+                }
+            },
+            Message::Feedback(id, feedback) => {
+                let plan = feedback.plan;
+                if !self[&id].record_feedback(feedback) {
+                    warn!(
+                        "Received feedback for session {id} on plan {plan}, which was never sent"
+                    );
+                }
+            }
+            Message::Disconnection(id) => {
+                self.sessions.remove(&id);
+            }
+        }
     }
 }
 
@@ -51,12 +168,146 @@ impl IndexMut<&Uuid> for ServerState {
 pub struct Connection {
     /// Where to send response messages
     response_channel: ResponseSender,
+    /// The URDF the agent described its body with on initiation
+    urdf: String,
     /// Simulation dimensions
     dims: usize,
     /// Current goal
     goal: PositionContextualReward<NUM_DIMS>,
     /// Current data read in
     buf: Vec<f32>,
+    /// Every plan sent to the agent
+    plans: PlanLog,
+}
+
+/// A plan sent to an agent, with what the simulation predicted and what actually happened
+#[derive(Clone, Debug, Default)]
+pub struct PlanRecord {
+    /// The plan's id within its session
+    pub id: PlanId,
+    /// The instructions sent
+    pub instructions: Vec<Instruction>,
+    /// The score the simulation predicted
+    pub predicted_score: f64,
+    /// What the agent reported after performing the plan
+    pub feedback: Option<ExecutionFeedback>,
+}
+
+impl PlanRecord {
+    /// Records a plan from its simulation result under an id
+    pub fn new(id: PlanId, result: &SimRes) -> Self {
+        Self {
+            id,
+            instructions: result.instructions.clone(),
+            predicted_score: result.score,
+            feedback: None,
+        }
+    }
+
+    /// Records a candidate of a front under its id, predicting the sum of its objectives, which is
+    /// the reward they were split from
+    pub fn from_candidate(candidate: &Candidate) -> Self {
+        Self {
+            id: candidate.id,
+            instructions: candidate.instructions.clone(),
+            predicted_score: candidate.objectives.iter().sum(),
+            feedback: None,
+        }
+    }
+
+    /// How far the measured reward landed from the predicted score, once feedback has arrived
+    pub fn prediction_error(&self) -> Option<f64> {
+        self.feedback
+            .as_ref()
+            .map(|feedback| feedback.reward - self.predicted_score)
+    }
+}
+
+/// Checks that a session's points can be simulated: each must start with its xyz position and be
+/// no wider than `NUM_DIMS`
+pub fn check_dims(dims: usize) -> Result<(), String> {
+    if (SPATIAL_DIMS..=NUM_DIMS).contains(&dims) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Points of {dims} dimensions can't be simulated, they need between {SPATIAL_DIMS} and \
+             {NUM_DIMS}"
+        ))
+    }
+}
+
+/// Checks that a reward expression only reads dimensions a session's points have
+pub fn check_reward(reward: &Expr, dims: usize) -> Result<(), String> {
+    let dims = session_dims(dims);
+    match reward.max_channel() {
+        Some(channel) if channel >= dims => Err(format!(
+            "The reward reads dimension {channel}, but points only have {dims}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// How many dimensions goals are checked against: the session's own once it's set, capped at what
+/// the hivemind is built for
+pub fn session_dims(dims: usize) -> usize {
+    match dims {
+        0 => NUM_DIMS,
+        dims => dims.min(NUM_DIMS),
+    }
+}
+
+/// Every plan sent to an agent, each under its own id so feedback finds the plan it's about
+#[derive(Clone, Debug, Default)]
+pub struct PlanLog {
+    /// Every plan recorded, oldest first
+    plans: Vec<PlanRecord>,
+    /// The id the next plan is given
+    next_id: PlanId,
+}
+
+impl PlanLog {
+    /// Gives out the next unused plan id
+    pub fn issue(&mut self) -> PlanId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Remembers a plan that's being sent to the agent, returning it under its new id
+    pub fn record(&mut self, result: &SimRes) -> Plan {
+        let id = self.issue();
+        self.plans.push(PlanRecord::new(id, result));
+        Plan::new(id, result.instructions.clone())
+    }
+
+    /// Remembers every candidate of a front that's being sent to the agent, each under its own new
+    /// id, so feedback on whichever is chosen finds it
+    pub fn record_front(&mut self, front: &mut ParetoFront) {
+        front.number(|| self.issue());
+        self.plans
+            .extend(front.candidates().iter().map(PlanRecord::from_candidate));
+    }
+
+    /// Stores feedback against the plan it names, returning false if no such plan was recorded
+    pub fn record_feedback(&mut self, feedback: ExecutionFeedback) -> bool {
+        match self.plans.iter_mut().find(|plan| plan.id == feedback.plan) {
+            Some(plan) => {
+                plan.feedback = Some(feedback);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The plan recorded under an id
+    pub fn get(&self, id: PlanId) -> Option<&PlanRecord> {
+        self.plans.iter().find(|plan| plan.id == id)
+    }
+
+    /// Every plan recorded, oldest first
+    pub fn plans(&self) -> &[PlanRecord] {
+        &self.plans
+    }
 }
 
 impl Connection {
@@ -64,22 +315,47 @@ impl Connection {
     pub fn new(response_channel: ResponseSender) -> Self {
         Self {
             response_channel,
+            urdf: String::new(),
             dims: 0,
             goal: PositionContextualReward::default(),
             buf: vec![],
+            plans: PlanLog::default(),
         }
     }
 
-    /// Sets the dims for this session
-    pub fn set_dims(&mut self, dims: usize) {
-        self.dims = dims
+    /// Sets the URDF the agent described its body with
+    pub fn with_urdf(mut self, urdf: String) -> Self {
+        self.urdf = urdf;
+        self
     }
 
-    /// Sets the goals for the current session
-    pub fn set_goals(&mut self, goals: Vec<(usize, bool)>) {
+    /// The URDF the agent described its body with on initiation
+    pub fn urdf(&self) -> &str {
+        &self.urdf
+    }
+
+    /// Sets the dims for this session, unless points that wide can't be simulated
+    pub fn set_dims(&mut self, dims: usize) -> Result<(), String> {
+        check_dims(dims)?;
+        self.dims = dims;
+        Ok(())
+    }
+
+    /// Sets the goals for the current session, checked against its dimensions. Nothing is changed
+    /// if any goal is invalid
+    pub fn set_goals(&mut self, goals: Vec<ChannelGoal>) -> Result<(), GoalError> {
+        validate_goals(&goals, session_dims(self.dims))?;
         self.goal.update(goals)
     }
 
+    /// Sets the reward expression for the current session, replacing any goals. Nothing is
+    /// changed if it reads past the session's dimensions
+    pub fn set_reward(&mut self, reward: Expr) -> Result<(), String> {
+        check_reward(&reward, self.dims)?;
+        self.goal.set_expression(reward);
+        Ok(())
+    }
+
     /// Sends a response to the underlying client
     pub fn send(
         &mut self,
@@ -88,23 +364,152 @@ impl Connection {
         self.response_channel.send(response)
     }
 
-    /// Writes data to the buffer
-    pub fn write(&mut self, buf: &[f32]) {
-        self.buf.extend(buf)
+    /// Writes points to the buffer. Nothing is written unless every point has the session's
+    /// dimensions, as one ragged point would shift every point after it
+    pub fn write(&mut self, points: &[Vec<f32>]) -> Result<(), String> {
+        if self.dims == 0 {
+            return Err("Dimensions must be set before any data is sent".into());
+        }
+        if let Some((index, point)) = points
+            .iter()
+            .enumerate()
+            .find(|(_, point)| point.len() != self.dims)
+        {
+            return Err(format!(
+                "Point {index} has {} values, but the session's points have {}",
+                point.len(),
+                self.dims
+            ));
+        }
+
+        self.buf.extend(points.iter().flatten());
+        Ok(())
+    }
+
+    /// Remembers a plan that's being sent to the agent, returning it under its new id
+    pub fn record_plan(&mut self, result: &SimRes) -> Plan {
+        self.plans.record(result)
+    }
+
+    /// Remembers every candidate of a front being sent to the agent, each under its own new id
+    pub fn record_front(&mut self, front: &mut ParetoFront) {
+        self.plans.record_front(front)
+    }
+
+    /// Stores the agent's feedback against the plan it names, returning false if the agent was
+    /// never sent that plan
+    pub fn record_feedback(&mut self, feedback: ExecutionFeedback) -> bool {
+        self.plans.record_feedback(feedback)
+    }
+
+    /// Every plan sent to the agent
+    pub fn plans(&self) -> &PlanLog {
+        &self.plans
     }
 
     /// Begins training the agent
     pub async fn train(&mut self) -> Option<SimRes> {
-        let mut orchestrator: Orchestrator<BevyPhysicsInformedBackend, NUM_DIMS> =
-            Orchestrator::new(BevyPhysicsInformedBackend);
+        let best_fit = train_on(
+            BevyPhysicsInformedBackend,
+            self.goal.clone(),
+            &self.buf,
+            self.dims,
+            NUM_SIMS,
+        )
+        .await;
 
-        let body = Body::default();
-        let job: SimArgs<_, NUM_DIMS> = SimArgs::new(self.goal, vec![], body);
+        Some(best_fit)
+    }
 
-        orchestrator.submit(job, NUM_SIMS);
+    /// Begins training the agent, keeping every plan on the Pareto front
+    pub async fn train_front(&mut self) -> Option<ParetoFront> {
+        let front = train_front_on(
+            BevyPhysicsInformedBackend,
+            self.goal.clone(),
+            &self.buf,
+            self.dims,
+            NUM_SIMS,
+        )
+        .await;
 
-        let best_fit = orchestrator.run().await;
+        Some(front)
+    }
+}
 
-        Some(best_fit)
+/// Runs `num_sims` simulations of a goal over a flat buffer of collected data, `dims` values to a
+/// point, on the given backend and returns the best fit
+pub async fn train_on<SIM: Simulation + Send + Sync + Copy + 'static>(
+    backend: SIM,
+    goal: PositionContextualReward<NUM_DIMS>,
+    buf: &[f32],
+    dims: usize,
+    num_sims: usize,
+) -> SimRes {
+    orchestrate(backend, goal, buf, dims, num_sims).run().await
+}
+
+/// Runs `num_sims` simulations of a goal over a flat buffer of collected data, `dims` values to a
+/// point, on the given backend and returns the Pareto front of their results
+pub async fn train_front_on<SIM: Simulation + Send + Sync + Copy + 'static>(
+    backend: SIM,
+    goal: PositionContextualReward<NUM_DIMS>,
+    buf: &[f32],
+    dims: usize,
+    num_sims: usize,
+) -> ParetoFront {
+    orchestrate(backend, goal, buf, dims, num_sims)
+        .run_pareto()
+        .await
+}
+
+/// Creates an orchestrator with `num_sims` simulations of a goal over a flat buffer of collected
+/// data submitted, each point padded out to `NUM_DIMS`
+fn orchestrate<SIM: Simulation + Send + Sync + Copy + 'static>(
+    backend: SIM,
+    goal: PositionContextualReward<NUM_DIMS>,
+    buf: &[f32],
+    dims: usize,
+    num_sims: usize,
+) -> Orchestrator<SIM, NUM_DIMS> {
+    let mut orchestrator: Orchestrator<SIM, NUM_DIMS> = Orchestrator::new(backend);
+
+    let dims = session_dims(dims);
+    let data = buf
+        .chunks_exact(dims)
+        .map(|values| {
+            let mut point = [0.0; NUM_DIMS];
+            point[..dims].copy_from_slice(values);
+            point
+        })
+        .collect();
+
+    let body = Body::default();
+    let job: SimArgs<_, NUM_DIMS> = SimArgs::new(goal, data, body);
+
+    orchestrator.submit(job, num_sims);
+
+    orchestrator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Connection;
+
+    #[test]
+    fn ragged_points_are_rejected_whole() {
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut connection = Connection::new(sender);
+        assert!(connection.write(&[vec![0.0; 3]]).is_err());
+
+        connection.set_dims(3).unwrap();
+        assert!(connection
+            .write(&[vec![0.0, 1.0, 2.0], vec![3.0, 4.0]])
+            .is_err());
+        assert!(connection.buf.is_empty());
+
+        connection
+            .write(&[vec![0.0, 1.0, 2.0], vec![3.0, 4.0, 5.0]])
+            .unwrap();
+        assert_eq!(connection.buf, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
     }
 }
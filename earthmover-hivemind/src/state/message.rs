@@ -1,9 +1,14 @@
 //! The variants a message may be
 
-use earthmover_achiever::brain::instruction::Instruction;
-use serde::{Deserialize, Serialize};
+use earthmover_achiever::{
+    brain::feedback::ExecutionFeedback,
+    goals::{expr::Expr, ChannelGoal},
+    protocol::AhtpRequest,
+};
 use uuid::Uuid;
 
+pub use earthmover_achiever::protocol::AhtpResponse as Response;
+
 /// A message receiver for the message enum
 pub type MessageReceiver = tokio::sync::mpsc::UnboundedReceiver<Message>;
 /// A message sender for the message enum
@@ -15,51 +20,40 @@ pub type ResponseReceiver = tokio::sync::mpsc::UnboundedReceiver<Response>;
 pub type ResponseSender = tokio::sync::mpsc::UnboundedSender<Response>;
 
 /// All variants that a message can be, including connection requests and existing user contexts
-#[derive(Deserialize, Serialize)]
 pub enum Message {
-    /// A client initial connection
-    #[serde(skip_deserializing, skip_serializing)]
-    Connection(Uuid, ResponseSender),
+    /// A client connected to an initiated session, along with the URDF it initiated with
+    Connection(Uuid, ResponseSender, String),
     /// Set the dimensionality of this simulation
     SetDims(Uuid, usize),
-    /// Send a data buffer in sequences of set dimension chunks(every n elements are considered a
-    /// point)
-    SendData(Uuid, Vec<f32>),
-    /// Set the goal for the current agent (what point in the dimension to focus on and whether we
-    /// want to maximize(true) or minizmize(false))
-    Goal(Uuid, Vec<(usize, bool)>),
+    /// Send a buffer of points, each of which must have the session's dimensions
+    SendData(Uuid, Vec<Vec<f32>>),
+    /// Set the goals for the current agent, one per data channel
+    Goal(Uuid, Vec<ChannelGoal>),
+    /// Set the reward for the current agent as an expression over each point's dimensions
+    Reward(Uuid, Expr),
     /// Begin training
     Train(Uuid),
+    /// Begin training, responding with the Pareto front rather than a single best plan
+    TrainFront(Uuid),
+    /// How the last plan went when the agent performed it
+    Feedback(Uuid, ExecutionFeedback),
     /// Disconnect from the session
-    Disconnection,
+    Disconnection(Uuid),
 }
 
 impl Message {
-    /// Attempts to get a message from a serde string
-    pub fn from_string(from: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(from)
-    }
-
-    /// Attempts to serialize a message to a json string
-    pub fn to_string(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
-    }
-}
-
-/// All variants a server response may have
-#[derive(Serialize)]
-pub enum Response {
-    /// Client is connected - here's the session ID
-    Connected(Uuid),
-    /// Generated instruction for achieving a goal
-    Instruction(Vec<Instruction>),
-    /// Training error
-    TrainError(&'static str),
-}
-
-impl Response {
-    /// Attempts to serialize the given response to a json string
-    pub fn serialize_to_string(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
+    /// Turns a request read from the socket bound to session `id` into a message for that
+    /// session. `None` for a `Connect`, which is only valid as a socket's first message
+    pub fn from_request(id: Uuid, request: AhtpRequest) -> Option<Self> {
+        Some(match request {
+            AhtpRequest::Send(points) => Self::SendData(id, points),
+            AhtpRequest::Connect(_) => return None,
+            AhtpRequest::SetDims(dims) => Self::SetDims(id, dims),
+            AhtpRequest::Goal(goals) => Self::Goal(id, goals),
+            AhtpRequest::Reward(reward) => Self::Reward(id, reward),
+            AhtpRequest::Train => Self::Train(id),
+            AhtpRequest::TrainFront => Self::TrainFront(id),
+            AhtpRequest::Feedback(feedback) => Self::Feedback(id, feedback),
+        })
     }
 }
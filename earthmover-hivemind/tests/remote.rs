@@ -0,0 +1,74 @@
+//! Round trips between the remote hivemind client and a real server
+
+use earthmover_achiever::{
+    client::{ClientError, Hivemind, RemoteHivemind},
+    goals::{expr::Expr, ChannelGoal, GoalProblem},
+};
+use tokio::net::TcpListener;
+
+/// Serves a hivemind on a free local port, returning its address
+async fn spawn_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(earthmover_hivemind::serve(listener));
+    addr
+}
+
+#[tokio::test]
+async fn remote_hivemind_round_trips_goals() {
+    let server = spawn_server().await;
+    let mut hivemind = RemoteHivemind::connect(&server, "<robot name=\"test\"/>")
+        .await
+        .expect("Failed to connect to hivemind");
+
+    assert!(matches!(
+        hivemind.set_dims(2).await,
+        Err(ClientError::Rejected(_))
+    ));
+    hivemind.set_dims(3).await.unwrap();
+    hivemind
+        .set_goals(vec![ChannelGoal::maximize(0), ChannelGoal::target(2, 1.0)])
+        .await
+        .expect("Valid goals were rejected");
+
+    let err = hivemind
+        .set_goals(vec![ChannelGoal::maximize(5)])
+        .await
+        .expect_err("Out of range goals were accepted");
+    let ClientError::Goals(err) = err else {
+        panic!("Expected a goal error, got {err}");
+    };
+    assert_eq!(
+        err.problems,
+        vec![GoalProblem::OutOfRange { index: 5, dims: 3 }]
+    );
+
+    hivemind
+        .set_reward(Expr::Channel(2))
+        .await
+        .expect("Reward fits the session's dims");
+    assert!(matches!(
+        hivemind.set_reward(Expr::Channel(3)).await,
+        Err(ClientError::Rejected(_))
+    ));
+}
+
+#[tokio::test]
+async fn sessions_are_kept_apart() {
+    let server = spawn_server().await;
+    let mut first = RemoteHivemind::connect(&server, "<robot/>").await.unwrap();
+    let mut second = RemoteHivemind::connect(&server, "<robot/>").await.unwrap();
+    assert_ne!(first.id(), second.id());
+
+    first.set_dims(5).await.unwrap();
+    second.set_dims(3).await.unwrap();
+
+    first
+        .set_goals(vec![ChannelGoal::maximize(4)])
+        .await
+        .expect("Goal fits the first session's dims");
+    assert!(matches!(
+        second.set_goals(vec![ChannelGoal::maximize(4)]).await,
+        Err(ClientError::Goals(_))
+    ));
+}
@@ -33,7 +33,7 @@ type SimulationExecution<OUT> = Pin<Box<dyn Future<Output = OUT> + Send>>;
 /// collection of N-dimensional points, an agent's configuration(hardware alongside current
 /// angles/position) and a `GOAL` function
 pub async fn simulate<
-    REWARD: Rewardable + Send + Sync + 'static,
+    REWARD: Rewardable + Clone + Send + Sync + 'static,
     const N: usize,
     SIM: Simulation + Send + Sync + 'static,
 >(
@@ -54,6 +54,7 @@ where
     while let Some(msg) = receiver.recv().await {
         match msg {
             SimMessage::Instruction(instr) => res.push_instruction(instr),
+            SimMessage::Objectives(objectives) => res.set_objectives(objectives),
             SimMessage::Close(score) => {
                 info!("Final Score: {score}");
                 res.set_score(score);
@@ -74,15 +75,15 @@ pub struct Orchestrator<SIM: Simulation + Send + Sync + Copy + 'static, const N:
 }
 
 #[cfg(test)]
-/// A test backend that just creates dummy instructions and scores
+/// A test backend that just creates dummy instructions, scoring the reward as it's given
 #[derive(Clone, Copy)]
 struct SimpleBackend;
 
 #[cfg(test)]
 impl Simulation for SimpleBackend {
-    fn simulate<REWARD: Rewardable, const DIMS: usize>(
+    fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
         &self,
-        _args: Arc<SimArgs<REWARD, DIMS>>,
+        args: Arc<SimArgs<REWARD, DIMS>>,
         message_sender: UnboundedSender<SimMessage>,
     ) {
         let _ = tracing_subscriber::fmt::try_init();
@@ -95,7 +96,10 @@ impl Simulation for SimpleBackend {
         }
 
         message_sender
-            .send(SimMessage::Close(rng.gen_range(0f64..1f64)))
+            .send(SimMessage::Objectives(args.reward.objectives()))
+            .expect("Failed to send objectives");
+        message_sender
+            .send(SimMessage::Close(args.reward.to_reward()))
             .expect("Failed to close out simulation");
     }
 
@@ -105,19 +109,20 @@ impl Simulation for SimpleBackend {
 }
 
 #[cfg(test)]
-/// A test backend that just creates dummy instructions and scores
+/// A test backend that drops a ball as the agent, scoring the reward it observes on the way down
 #[derive(Clone, Copy)]
 struct SimplePhysicsBackend;
 
 #[cfg(test)]
 impl Simulation for SimplePhysicsBackend {
-    fn simulate<REWARD: Rewardable, const DIMS: usize>(
+    fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
         &self,
-        _args: Arc<SimArgs<REWARD, DIMS>>,
+        args: Arc<SimArgs<REWARD, DIMS>>,
         message_sender: UnboundedSender<SimMessage>,
     ) {
         let _ = tracing_subscriber::fmt::try_init();
         let mut rng = thread_rng();
+        let mut reward = args.reward.clone();
 
         let mut rigid_body_set = RigidBodySet::new();
         let mut collider_set = ColliderSet::new();
@@ -168,12 +173,17 @@ impl Simulation for SimplePhysicsBackend {
                 &physics_hooks,
                 &event_handler,
             );
+
+            let at = rigid_body_set[ball_body_handle].translation();
+            reward.observe(&args.reading_at([at.x, at.y, at.z]));
         }
 
-        let ball_body = &rigid_body_set[ball_body_handle];
         message_sender
-            .send(SimMessage::Close(ball_body.translation().y as f64))
-            .expect("Failed to send final ball y");
+            .send(SimMessage::Objectives(reward.objectives()))
+            .expect("Failed to send objectives");
+        message_sender
+            .send(SimMessage::Close(reward.to_reward()))
+            .expect("Failed to send final reward");
     }
 
     fn name(&self) -> String {
@@ -181,11 +191,41 @@ impl Simulation for SimplePhysicsBackend {
     }
 }
 
+#[cfg(test)]
+/// A test backend that scores two conflicting objectives, such as reaching a goal against saving
+/// energy
+#[derive(Clone, Copy)]
+struct TradeOffBackend;
+
+#[cfg(test)]
+impl Simulation for TradeOffBackend {
+    fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
+        &self,
+        _args: Arc<SimArgs<REWARD, DIMS>>,
+        message_sender: UnboundedSender<SimMessage>,
+    ) {
+        let mut rng = thread_rng();
+        let progress = rng.gen_range(0f64..1f64);
+        let energy = -progress * rng.gen_range(0.5f64..1.5f64);
+
+        message_sender
+            .send(SimMessage::Objectives(vec![progress, energy]))
+            .expect("Failed to send objectives");
+        message_sender
+            .send(SimMessage::Close(progress + energy))
+            .expect("Failed to close out simulation");
+    }
+
+    fn name(&self) -> String {
+        "Trade-off Backend".into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use earthmover_achiever::body::Body;
 
-    use crate::{sim::SimArgs, Orchestrator, SimpleBackend, SimplePhysicsBackend};
+    use crate::{sim::SimArgs, Orchestrator, SimpleBackend, SimplePhysicsBackend, TradeOffBackend};
 
     #[tokio::test]
     async fn orchestrator_simple_simulation_backend() {
@@ -200,4 +240,20 @@ mod tests {
         orchestrator.submit(SimArgs::new(1.0, vec![], Body::default()), 1000);
         let _ = orchestrator.run().await;
     }
+
+    #[tokio::test]
+    async fn orchestrator_pareto_front() {
+        let mut orchestrator: Orchestrator<_, 3> = Orchestrator::new(TradeOffBackend);
+        orchestrator.submit(SimArgs::new(1.0, vec![], Body::default()), 200);
+        let front = orchestrator.run_pareto().await;
+
+        assert!(!front.is_empty());
+        for candidate in front.candidates() {
+            assert_eq!(candidate.objectives.len(), 2);
+            assert!(front
+                .candidates()
+                .iter()
+                .all(|other| !other.dominates(candidate)));
+        }
+    }
 }
@@ -2,7 +2,7 @@
 
 use std::{sync::Arc, time::Duration};
 
-use earthmover_achiever::goals::Rewardable;
+use earthmover_achiever::{brain::pareto::ParetoFront, goals::Rewardable};
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
 use tracing::info;
@@ -17,7 +17,7 @@ use crate::{
 
 impl<const N: usize, SIM: Simulation + Send + Sync + Copy + 'static> Orchestrator<SIM, N> {
     /// Submits `sim_amount` simulations to the Orchestrator for execution
-    pub fn submit<REWARD: Rewardable + Sync + Send + 'static>(
+    pub fn submit<REWARD: Rewardable + Clone + Sync + Send + 'static>(
         &mut self,
         job: SimArgs<REWARD, N>,
         sim_amount: usize,
@@ -36,6 +36,21 @@ impl<const N: usize, SIM: Simulation + Send + Sync + Copy + 'static> Orchestrato
 
     /// Runs all batch simulations and returns the simulation with the best fitness
     pub async fn run(&mut self) -> SimRes {
+        self.collect().await.into_iter().max().unwrap()
+    }
+
+    /// Runs all batch simulations and returns those no other simulation beats on every objective.
+    /// Simulations that only report a single score are compared on that score alone
+    pub async fn run_pareto(&mut self) -> ParetoFront {
+        self.collect()
+            .await
+            .into_iter()
+            .map(SimRes::into_candidate)
+            .collect()
+    }
+
+    /// Runs all batch simulations to completion, reporting progress as they finish
+    async fn collect(&mut self) -> Vec<SimRes> {
         let mut results = vec![];
         let progress = ProgressBar::new(self.batch_sims.len() as u64);
         progress.set_style(
@@ -55,7 +70,7 @@ impl<const N: usize, SIM: Simulation + Send + Sync + Copy + 'static> Orchestrato
             results.push(result);
         }
 
-        results.into_iter().max().unwrap()
+        results
     }
 
     /// Creates a new Orchestrator based on a given simulation backend
@@ -5,7 +5,11 @@ pub mod backend;
 use std::sync::Arc;
 
 use bevy::prelude::Resource;
-use earthmover_achiever::{body::Body, brain::instruction::Instruction, goals::Rewardable};
+use earthmover_achiever::{
+    body::Body,
+    brain::{fusion::SPATIAL_DIMS, instruction::Instruction, pareto::Candidate},
+    goals::Rewardable,
+};
 
 /// Any agruments that a simulation may take in
 pub struct SimArgs<REWARD: Rewardable + Send + Sync + 'static, const DIMS: usize> {
@@ -27,6 +31,28 @@ impl<REWARD: Rewardable + Send + Sync + 'static, const DIMS: usize> SimArgs<REWA
     pub fn new(reward: REWARD, data: Vec<[f32; DIMS]>, body: Body) -> Self {
         Self { reward, data, body }
     }
+
+    /// What the agent would read at a position: the collected point nearest it, moved to the
+    /// position. Every channel reads 0 when there's no data
+    pub fn reading_at(&self, position: [f32; SPATIAL_DIMS]) -> [f64; DIMS] {
+        let distance = |point: &[f32; DIMS]| -> f32 {
+            point
+                .iter()
+                .zip(position)
+                .map(|(value, coord)| (value - coord).powi(2))
+                .sum()
+        };
+
+        let mut reading = self
+            .data
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .map_or([0.0; DIMS], |point| point.map(f64::from));
+        for (value, coord) in reading.iter_mut().zip(position) {
+            *value = coord as f64;
+        }
+        reading
+    }
 }
 
 /// An arc-wrapped SimArg
@@ -40,6 +66,8 @@ pub struct ArcSimArgs<REWARD: Rewardable + Send + Sync + 'static, const DIMS: us
 pub struct SimRes {
     /// The agent's score
     pub score: f64,
+    /// The agent's score on each objective, if the simulation scored more than one
+    pub objectives: Option<Vec<f64>>,
     /// The instructions to achieve this score
     pub instructions: Vec<Instruction>,
 }
@@ -80,6 +108,23 @@ impl SimRes {
         self.score
     }
 
+    /// Sets the agent's score on each objective
+    pub fn set_objectives(&mut self, objectives: Vec<f64>) {
+        self.objectives = Some(objectives)
+    }
+
+    /// Returns the score on each objective, if the simulation scored more than one
+    pub fn get_objectives(&self) -> Option<&[f64]> {
+        self.objectives.as_deref()
+    }
+
+    /// Turns the result into a Pareto candidate, falling back to the single score as the only
+    /// objective
+    pub fn into_candidate(self) -> Candidate {
+        let objectives = self.objectives.unwrap_or_else(|| vec![self.score]);
+        Candidate::new(objectives, self.instructions)
+    }
+
     /// Returns the instructions
     pub fn get_instructions(&self) -> &[Instruction] {
         &self.instructions
@@ -90,6 +135,8 @@ impl SimRes {
 pub enum SimMessage {
     /// A new instruction
     Instruction(Instruction),
+    /// The score on each of several objectives, sent before closing
+    Objectives(Vec<f64>),
     /// Simulation has ended with a given score
     Close(f64),
 }
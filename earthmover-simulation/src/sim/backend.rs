@@ -13,8 +13,9 @@ use super::{SimArgs, SimMessage};
 /// score
 pub trait Simulation {
     /// Runs through a simulation based on beginning arguments, reports back to a Receiver with
-    /// instructions to reach a certain `Score`
-    fn simulate<REWARD: Rewardable, const DIMS: usize>(
+    /// instructions to reach a certain `Score`. Each simulation scores its own copy of the reward,
+    /// observing what the agent reads as it moves
+    fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
         &self,
         args: Arc<SimArgs<REWARD, DIMS>>,
         message_sender: UnboundedSender<SimMessage>,
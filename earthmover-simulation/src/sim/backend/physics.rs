@@ -27,6 +27,14 @@ use super::Simulation;
 #[derive(Resource)]
 pub struct MessageChannel(pub UnboundedSender<SimMessage>);
 
+/// This simulation's own copy of the reward, observing what the agent reads as it moves
+#[derive(Resource)]
+pub struct SimReward<REWARD: Rewardable + 'static>(pub REWARD);
+
+/// Marks the body standing in for the agent
+#[derive(Component)]
+pub struct SimAgent;
+
 /// All data held in a single training context. Including mappings from 3 space to peripheral
 /// readings
 #[derive(Default, Resource)]
@@ -44,19 +52,21 @@ impl Simulation for BevyPhysicsInformedBackend {
         "Bevy-Based Physics Backend".into()
     }
 
-    fn simulate<REWARD: Rewardable, const DIMS: usize>(
+    fn simulate<REWARD: Rewardable + Clone, const DIMS: usize>(
         &self,
         args: Arc<SimArgs<REWARD, DIMS>>,
         message_sender: tokio::sync::mpsc::UnboundedSender<SimMessage>,
     ) {
         App::new()
             .insert_resource(MessageChannel(message_sender))
+            .insert_resource(SimReward(args.reward.clone()))
             .insert_resource(ArcSimArgs(args))
             .insert_resource(TrainContext::<DIMS>::default())
             .add_plugins(DefaultPlugins)
             .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
             .add_systems(Startup, setup::<REWARD, DIMS>)
             .add_systems(Update, update::<REWARD, DIMS>)
+            .add_systems(Last, finish::<REWARD>)
             .run();
     }
 }
@@ -114,7 +124,8 @@ fn setup<REWARD: Rewardable, const DIMS: usize>(
         .insert(Collider::ball(0.1))
         .insert(GravityScale(1.0))
         .insert(Restitution::coefficient(0.7))
-        .insert(Velocity::linear(Vec3::ZERO));
+        .insert(Velocity::linear(Vec3::ZERO))
+        .insert(SimAgent);
 
     commands.spawn(Camera3dBundle {
         transform: Transform::from_xyz(0.0, 2.0, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
@@ -127,7 +138,34 @@ fn setup<REWARD: Rewardable, const DIMS: usize>(
     });
 }
 
-#[allow(unused_attributes)]
-#[allow(elided_lifetimes_in_paths)]
-/// Sets up the Bevy simulation world with respect to the points provided
-fn update<REWARD: Rewardable, const DIMS: usize>() {}
+/// Steps the reward along with the agent, observing what it would read wherever it's moved to
+fn update<REWARD: Rewardable, const DIMS: usize>(
+    args: Res<ArcSimArgs<REWARD, DIMS>>,
+    mut reward: ResMut<SimReward<REWARD>>,
+    agents: Query<&Transform, With<SimAgent>>,
+) {
+    for transform in &agents {
+        let at = transform.translation;
+        reward.0.observe(&args.0.reading_at([at.x, at.y, at.z]));
+    }
+}
+
+/// Reports the reward on each objective once the simulation exits, then closes it out
+fn finish<REWARD: Rewardable>(
+    mut exits: EventReader<AppExit>,
+    reward: Res<SimReward<REWARD>>,
+    channel: Res<MessageChannel>,
+) {
+    if exits.read().next().is_none() {
+        return;
+    }
+
+    channel
+        .0
+        .send(SimMessage::Objectives(reward.0.objectives()))
+        .expect("Failed to send objectives");
+    channel
+        .0
+        .send(SimMessage::Close(reward.0.to_reward()))
+        .expect("Failed to close out simulation");
+}
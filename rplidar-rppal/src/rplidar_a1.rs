@@ -35,6 +35,63 @@ pub enum Command {
     GetLidarConf = 0x84,
 }
 
+/// A single measurement from a scan
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanSample {
+    /// How strong the return was, from 0 to 63
+    pub quality: u8,
+    /// The angle of the measurement in degrees
+    pub angle: f32,
+    /// The distance measured in millimeters, 0 when nothing was in range
+    pub distance_mm: f32,
+    /// Whether this measurement starts a new revolution
+    pub start: bool,
+}
+
+/// Splits the bytes read during a scan into samples. Reads may end partway through a sample, so
+/// leftover bytes are kept for the next read, and bytes that can't start a sample, such as the
+/// scan's response descriptor, are skipped
+#[derive(Default)]
+pub struct ScanParser {
+    /// Bytes read that don't make up a whole sample yet
+    pending: Vec<u8>,
+}
+
+impl ScanParser {
+    /// Creates a parser with nothing read
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses every whole sample once `bytes` are added to what's left from earlier reads
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<ScanSample> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut samples = vec![];
+        let mut index = 0;
+        while let Some(sample) = self.pending.get(index..index + 5) {
+            // A sample's start bit is followed by its inverse, and its check bit is always set
+            let start = sample[0] & 0b01 != 0;
+            let inverse_start = sample[0] & 0b10 != 0;
+            if start == inverse_start || sample[1] & 0b01 == 0 {
+                index += 1;
+                continue;
+            }
+
+            samples.push(ScanSample {
+                quality: sample[0] >> 2,
+                angle: (u16::from_le_bytes([sample[1], sample[2]]) >> 1) as f32 / 64.0,
+                distance_mm: u16::from_le_bytes([sample[3], sample[4]]) as f32 / 4.0,
+                start,
+            });
+            index += 5;
+        }
+
+        self.pending.drain(..index);
+        samples
+    }
+}
+
 impl RpLidarA1 {
     /// Creates a new RpLidar from already constructed UART and OutputPin
     pub fn new(uart: Uart, motor: OutputPin) -> Self {
@@ -86,3 +143,28 @@ impl RpLidarA1 {
         reader
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ScanParser, ScanSample};
+
+    #[test]
+    fn samples_are_parsed_across_reads_past_garbage() {
+        // Quality 15 starting a revolution, at 90 degrees and 1000mm
+        let sample = [0x3D, 0x01, 0x2D, 0xA0, 0x0F];
+        let mut parser = ScanParser::new();
+
+        assert!(parser.push(&[0xFF, sample[0], sample[1]]).is_empty());
+        let samples = parser.push(&sample[2..]);
+
+        assert_eq!(
+            samples,
+            vec![ScanSample {
+                quality: 15,
+                angle: 90.0,
+                distance_mm: 1000.0,
+                start: true,
+            }]
+        );
+    }
+}